@@ -40,6 +40,20 @@ impl AxisAlignedBoundingBox {
         }
     }
 
+    // Flat objects such as quads get a little thickness so slab tests against them stay robust
+    pub fn pad_to_minimums(self) -> Self {
+        const DELTA: f64 = 0.0001;
+        let pad = |interval: Interval| {
+            if interval.max - interval.min < DELTA {
+                interval.expand(DELTA)
+            } else {
+                interval
+            }
+        };
+
+        Self::new(pad(self.x), pad(self.y), pad(self.z))
+    }
+
     pub const fn axis_interval(&self, n: usize) -> Interval {
         match n {
             1 => self.y,
@@ -48,6 +62,20 @@ impl AxisAlignedBoundingBox {
         }
     }
 
+    pub fn longest_axis(&self) -> usize {
+        let x_size = self.x.max - self.x.min;
+        let y_size = self.y.max - self.y.min;
+        let z_size = self.z.max - self.z.min;
+
+        if x_size > y_size {
+            if x_size > z_size { 0 } else { 2 }
+        } else if y_size > z_size {
+            1
+        } else {
+            2
+        }
+    }
+
     pub fn hit(&self, r: &Ray, mut ray_t: Interval) -> bool {
         let origin = r.origin();
         let dir = r.dir();
@@ -0,0 +1,130 @@
+use crate::running_stats::RunningStats;
+
+// Two-sided 95% confidence interval of a normally distributed mean
+const CONFIDENCE_Z_SCORE: f64 = 1.96;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AdaptiveSampling {
+    min_samples_per_pixel: u32,
+    max_samples_per_pixel: u32,
+    error_threshold: f64,
+}
+
+impl AdaptiveSampling {
+    pub const fn new(
+        min_samples_per_pixel: u32,
+        max_samples_per_pixel: u32,
+        error_threshold: f64,
+    ) -> Self {
+        Self {
+            min_samples_per_pixel,
+            max_samples_per_pixel: if max_samples_per_pixel < min_samples_per_pixel {
+                min_samples_per_pixel
+            } else {
+                max_samples_per_pixel
+            },
+            error_threshold,
+        }
+    }
+
+    pub const fn min_samples_per_pixel(&self) -> u32 {
+        self.min_samples_per_pixel
+    }
+
+    pub const fn max_samples_per_pixel(&self) -> u32 {
+        self.max_samples_per_pixel
+    }
+
+    pub const fn error_threshold(&self) -> f64 {
+        self.error_threshold
+    }
+
+    // A pixel keeps receiving samples until it has the minimum sample count and the half-width of
+    // its confidence interval, relative to its mean, is within the threshold
+    pub fn needs_samples(&self, stats: &RunningStats) -> bool {
+        if stats.count() < self.min_samples_per_pixel {
+            return true;
+        }
+
+        stats.count() < self.max_samples_per_pixel
+            && CONFIDENCE_Z_SCORE * stats.relative_error() > self.error_threshold
+    }
+
+    pub fn samples_for_pixel(&self, stats: &RunningStats, pass_samples: u32) -> u32 {
+        if self.needs_samples(stats) {
+            pass_samples.min(self.max_samples_per_pixel - stats.count())
+        } else {
+            0
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{RngExt, SeedableRng, rngs::Xoshiro256PlusPlus};
+
+    // Renders one pixel pass by pass until adaptive sampling stops giving it samples
+    fn sample_pixel(
+        adaptive: &AdaptiveSampling,
+        pass_samples: u32,
+        mut sample: impl FnMut() -> f64,
+    ) -> u32 {
+        let mut stats = RunningStats::default();
+        loop {
+            let samples = adaptive.samples_for_pixel(&stats, pass_samples);
+            if samples == 0 {
+                assert!(!adaptive.needs_samples(&stats));
+                return stats.count();
+            }
+            for _ in 0..samples {
+                stats.push(sample());
+            }
+            assert!(stats.count() <= adaptive.max_samples_per_pixel());
+        }
+    }
+
+    #[test]
+    fn sample_counts_stay_within_bounds() {
+        let mut rng = Xoshiro256PlusPlus::seed_from_u64(5);
+        for (min, max) in [(32, 2000), (16, 100), (8, 8), (64, 16)] {
+            for threshold in [0.0, 0.01, 0.1, 10.0] {
+                let adaptive = AdaptiveSampling::new(min, max, threshold);
+                for pass_samples in [1, 7, 10, 64] {
+                    // Constant, black, mildly noisy and firefly-ridden pixels
+                    let pixels: [fn(&mut Xoshiro256PlusPlus) -> f64; 4] = [
+                        |_| 0.5,
+                        |_| 0.0,
+                        |rng| 0.2f64.mul_add(rng.random::<f64>(), 0.4),
+                        |rng| {
+                            if rng.random::<f64>() < 0.01 {
+                                100.0
+                            } else {
+                                0.1
+                            }
+                        },
+                    ];
+                    for pixel in pixels {
+                        let count = sample_pixel(&adaptive, pass_samples, || pixel(&mut rng));
+                        assert!(
+                            (min..=max.max(min)).contains(&count),
+                            "{count} samples for bounds {min} to {max}"
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    // A pixel that never converges is sampled up to the maximum and no further
+    #[test]
+    fn noisy_pixels_reach_the_maximum() {
+        let adaptive = AdaptiveSampling::new(16, 100, 0.0);
+        let mut toggle = false;
+        let count = sample_pixel(&adaptive, 7, || {
+            toggle = !toggle;
+            f64::from(u8::from(toggle))
+        });
+        assert_eq!(count, 100);
+    }
+}
@@ -4,7 +4,6 @@ use crate::{
     interval::Interval,
     ray::Ray,
 };
-use rand::{RngExt, rng};
 use std::{cmp::Ordering, sync::Arc};
 
 fn box_compare(a: &dyn RayIntersection, b: &dyn RayIntersection, axis: usize) -> Ordering {
@@ -27,7 +26,14 @@ impl BvhNode {
             [single] => (single.clone(), single.clone()),
             [first, second] => (first.clone(), second.clone()),
             _ => {
-                let axis = rng().random_range(0..=2);
+                // Splitting along the longest axis keeps the hierarchy identical between runs
+                let bbox =
+                    objects
+                        .iter()
+                        .fold(AxisAlignedBoundingBox::default(), |bbox, object| {
+                            AxisAlignedBoundingBox::merge_boxes(&bbox, &object.bounding_box())
+                        });
+                let axis = bbox.longest_axis();
                 objects.sort_by(|a, b| box_compare(a.as_ref(), b.as_ref(), axis));
 
                 let mid = objects.len() / 2;
@@ -1,53 +1,39 @@
 use crate::{
+    adaptive_sampling::AdaptiveSampling,
+    checkpoint::{Checkpoint, RenderSettings},
     color::Color,
+    film::{Film, TileBuffer},
+    filter::Filter,
     hittable::{HitRecord, RayIntersection},
-    hittable_collection::HittableCollection,
-    interval,
-    material::Scatter,
+    interval::{self, Interval},
+    light::LightSource,
+    material::{Lobe, Scatter},
     ray::Ray,
+    sampler::{BounceStage, SampleGenerator, Sampler, SamplerKind},
+    scene::Scene,
     scope_timer::ScopeTimer,
-    vec3::{Point3, Vec3, random_in_unit_disk},
+    spectrum::{SampledSpectrum, SampledWavelengths, Wavelengths},
+    tile::{Tile, TileOrder, generate_tiles},
+    vec3::{Onb, Point3, Vec3, sample_uniform_disk},
 };
-use log::info;
-use rand::RngExt;
+use log::{info, warn};
 use rayon::prelude::*;
 use std::{
+    io::{self, BufWriter},
+    path::PathBuf,
     sync::{
         Arc,
         atomic::{AtomicUsize, Ordering},
     },
     thread,
-    time::Duration,
+    time::{Duration, Instant},
 };
 
-#[derive(Clone, Debug)]
-pub struct Basis {
-    u: Vec3,
-    v: Vec3,
-    w: Vec3,
-}
-
-impl Basis {
-    pub fn new(look_from: &Point3, look_at: &Point3, v_up: &Vec3) -> Self {
-        let w = (look_from - look_at).unit_vector();
-        let u = v_up.cross(&w).unit_vector();
-        let v = w.cross(&u);
-
-        Self { u, v, w }
-    }
+// Per-pixel error estimates from fewer samples than this are too unreliable to stop rendering on
+const MIN_SAMPLES_FOR_ERROR_ESTIMATE: u32 = 16;
 
-    pub const fn u(&self) -> &Vec3 {
-        &self.u
-    }
-
-    pub const fn v(&self) -> &Vec3 {
-        &self.v
-    }
-
-    pub const fn w(&self) -> &Vec3 {
-        &self.w
-    }
-}
+// Shadow rays stop this fraction of the distance short of the light they were aimed at
+const SHADOW_RAY_EPSILON: f64 = 1e-4;
 
 #[derive(Clone, Debug)]
 pub struct Camera {
@@ -57,12 +43,29 @@ pub struct Camera {
     pixel_delta_v: Vec3,
     defocus_disk_u: Vec3,
     defocus_disk_v: Vec3,
-    pixel_samples_scale: f64,
     defocus_angle: f64,
     image_width: i32,
     image_height: i32,
     samples_per_pixel: i32,
     max_depth: u32,
+    russian_roulette_depth: u32,
+    tile_size: usize,
+    tile_order: TileOrder,
+    samples_per_pass: Option<i32>,
+    snapshot_path: Option<PathBuf>,
+    snapshot_interval: Option<Duration>,
+    checkpoint_path: Option<PathBuf>,
+    checkpoint_interval: Option<Duration>,
+    resume: bool,
+    time_limit: Option<Duration>,
+    target_relative_error: Option<f64>,
+    adaptive_sampling: Option<AdaptiveSampling>,
+    sample_count_map_path: Option<PathBuf>,
+    scene_fingerprint: u64,
+    seed: u64,
+    sampler_kind: SamplerKind,
+    filter: Filter,
+    spectral: bool,
 }
 
 #[derive(Clone, Debug)]
@@ -77,6 +80,24 @@ struct NewCameraParameters {
     pub image_width: i32,
     pub samples_per_pixel: i32,
     pub max_depth: u32,
+    pub russian_roulette_depth: u32,
+    pub tile_size: usize,
+    pub tile_order: TileOrder,
+    pub samples_per_pass: Option<i32>,
+    pub snapshot_path: Option<PathBuf>,
+    pub snapshot_interval: Option<Duration>,
+    pub checkpoint_path: Option<PathBuf>,
+    pub checkpoint_interval: Option<Duration>,
+    pub resume: bool,
+    pub time_limit: Option<Duration>,
+    pub target_relative_error: Option<f64>,
+    pub adaptive_sampling: Option<AdaptiveSampling>,
+    pub sample_count_map_path: Option<PathBuf>,
+    pub scene_fingerprint: u64,
+    pub seed: u64,
+    pub sampler_kind: SamplerKind,
+    pub filter: Filter,
+    pub spectral: bool,
 }
 
 impl Camera {
@@ -96,7 +117,7 @@ impl Camera {
         let h = (0.5 * theta).tan();
         let viewport_height = 2.0 * h * params.focus_distance;
         let viewport_width = viewport_height * image_width_f64 / image_height_f64;
-        let basis = Basis::new(&params.look_from, &params.look_at, &params.v_up);
+        let basis = Onb::with_up(&(params.look_from - params.look_at), &params.v_up);
         let viewport_u = viewport_width * basis.u();
         let viewport_v = -viewport_height * basis.v();
         let pixel_delta_u = viewport_u / image_width_f64;
@@ -113,96 +134,344 @@ impl Camera {
             pixel00_loc,
             pixel_delta_u,
             pixel_delta_v,
-            pixel_samples_scale: f64::from(params.samples_per_pixel).recip(),
             image_width: params.image_width,
             image_height,
             samples_per_pixel: params.samples_per_pixel,
             max_depth: params.max_depth,
+            russian_roulette_depth: params.russian_roulette_depth,
             defocus_angle: params.defocus_angle,
             defocus_disk_u: defocus_radius * basis.u(),
             defocus_disk_v: defocus_radius * basis.v(),
+            tile_size: params.tile_size,
+            tile_order: params.tile_order,
+            samples_per_pass: params.samples_per_pass,
+            snapshot_path: params.snapshot_path.clone(),
+            snapshot_interval: params.snapshot_interval,
+            checkpoint_path: params.checkpoint_path.clone(),
+            checkpoint_interval: params.checkpoint_interval,
+            resume: params.resume,
+            time_limit: params.time_limit,
+            target_relative_error: params.target_relative_error,
+            adaptive_sampling: params.adaptive_sampling,
+            sample_count_map_path: params.sample_count_map_path.clone(),
+            scene_fingerprint: params.scene_fingerprint,
+            seed: params.seed,
+            sampler_kind: params.sampler_kind,
+            filter: params.filter,
+            spectral: params.spectral,
         }
     }
 
-    pub fn render(&self, world: &HittableCollection) {
+    pub fn render(&self, scene: &Scene) -> io::Result<()> {
         let _timer = ScopeTimer::new("Camera::render");
 
+        let film = self.render_film(scene)?;
+
         // Output PPM image to standard output
+        film.write_ppm(BufWriter::new(io::stdout().lock()))?;
+
+        info!("Image rendering complete");
+
+        Ok(())
+    }
+
+    // Renders the scene to a film, resuming from and writing snapshots and checkpoints as set up
+    fn render_film(&self, scene: &Scene) -> io::Result<Film> {
+        let image_width = usize::try_from(self.image_width).unwrap();
+        let image_height = usize::try_from(self.image_height).unwrap();
+        let samples_per_pass = self.samples_per_pass();
+
+        let (mut film, mut pass) = if self.resume {
+            self.load_checkpoint(image_width, image_height, samples_per_pass)?
+        } else {
+            (Film::new(image_width, image_height), 0)
+        };
+
+        let tiles = generate_tiles(image_width, image_height, self.tile_size, self.tile_order);
+        let deadline = self.time_limit.map(|limit| Instant::now() + limit);
+        let mut last_snapshot = Instant::now();
+        let mut last_checkpoint = Instant::now();
+        let mut finished = self.render_finished(pass, &film, deadline);
+
+        while !finished {
+            let pass_samples = if self.uses_fixed_sample_count() {
+                samples_per_pass.min(self.samples_per_pixel - pass * samples_per_pass)
+            } else {
+                samples_per_pass
+            };
+
+            for buffer in &self.render_pass(&tiles, pass_samples, &film, deadline, scene) {
+                film.merge_tile(buffer);
+            }
+
+            pass += 1;
+            finished = self.render_finished(pass, &film, deadline);
+
+            info!("Pass {pass} complete");
+
+            let snapshot_due = self
+                .snapshot_interval
+                .is_none_or(|interval| last_snapshot.elapsed() >= interval);
+            if let Some(path) = &self.snapshot_path
+                && !finished
+                && snapshot_due
+            {
+                match film.write_ppm_file(path) {
+                    Ok(()) => info!("Wrote snapshot to {}", path.display()),
+                    Err(e) => warn!("Failed to write snapshot to {}: {e}", path.display()),
+                }
+                last_snapshot = Instant::now();
+            }
+
+            let checkpoint_due = self
+                .checkpoint_interval
+                .is_none_or(|interval| last_checkpoint.elapsed() >= interval);
+            if let Some(path) = &self.checkpoint_path
+                && !finished
+                && checkpoint_due
+            {
+                match Checkpoint::save(path, &film, pass, &self.render_settings(samples_per_pass)) {
+                    Ok(()) => info!("Wrote checkpoint to {}", path.display()),
+                    Err(e) => warn!("Failed to write checkpoint to {}: {e}", path.display()),
+                }
+                last_checkpoint = Instant::now();
+            }
+        }
+
+        info!("{}", completion_report(pass, &film));
+        if let Some(warning) = self.target_error_warning(&film) {
+            warn!("{warning}");
+        }
+
+        if let Some(path) = &self.sample_count_map_path {
+            match film.write_sample_count_ppm_file(path) {
+                Ok(()) => info!("Wrote sample count map to {}", path.display()),
+                Err(e) => warn!(
+                    "Failed to write sample count map to {}: {e}",
+                    path.display()
+                ),
+            }
+        }
+
+        Ok(film)
+    }
+
+    fn samples_per_pass(&self) -> i32 {
+        let samples_per_pass = self.samples_per_pass.unwrap_or(self.samples_per_pixel);
+        if self.uses_fixed_sample_count() {
+            samples_per_pass.clamp(1, self.samples_per_pixel.max(1))
+        } else {
+            samples_per_pass.max(1)
+        }
+    }
+
+    const fn uses_fixed_sample_count(&self) -> bool {
+        self.time_limit.is_none()
+            && self.target_relative_error.is_none()
+            && self.adaptive_sampling.is_none()
+    }
+
+    // Without a stop condition or adaptive sampling the render runs for a fixed number of samples
+    // per pixel. Otherwise passes are added until the time limit is reached, every pixel meets the
+    // target error, or adaptive sampling has no pixel left that needs more samples. Without a time
+    // limit, a target error that is never met still stops at the fixed number of samples.
+    fn render_finished(
+        &self,
+        completed_passes: i32,
+        film: &Film,
+        deadline: Option<Instant>,
+    ) -> bool {
+        if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            return true;
+        }
+
+        let target_met = self
+            .target_relative_error
+            .map(|target| film.max_relative_error(MIN_SAMPLES_FOR_ERROR_ESTIMATE) <= target);
+
+        match (target_met, self.adaptive_sampling) {
+            (Some(true), _) => true,
+            (_, Some(adaptive)) => !film
+                .all_pixel_stats()
+                .any(|stats| adaptive.needs_samples(stats)),
+            (_, None) if self.time_limit.is_some() => false,
+            (_, None) => completed_passes * self.samples_per_pass() >= self.samples_per_pixel,
+        }
+    }
 
-        // PPM header
-        // First line indicates colors are in ASCII
-        // Second line indicates the width and height of the image
-        // Third line indicates the maximum color value
-        println!("P3");
-        println!("{} {}", self.image_width, self.image_height);
-        println!("255");
+    fn target_error_warning(&self, film: &Film) -> Option<String> {
+        let target = self.target_relative_error?;
+        let error = film.max_relative_error(MIN_SAMPLES_FOR_ERROR_ESTIMATE);
+        (error > target).then(|| {
+            format!("Target error of {target} not met, the largest pixel error is {error:.4}")
+        })
+    }
 
-        let total_pixels = usize::try_from(self.image_height * self.image_width).unwrap();
+    fn render_pass(
+        &self,
+        tiles: &[Tile],
+        samples: i32,
+        film: &Film,
+        deadline: Option<Instant>,
+        scene: &Scene,
+    ) -> Vec<TileBuffer> {
+        let total_pixels = tiles.iter().map(Tile::pixel_count).sum();
         let completed_pixel_count = Arc::new(AtomicUsize::new(0));
         let completed_clone = Arc::clone(&completed_pixel_count);
 
         let monitor_handle = thread::spawn(move || monitor_loop(&completed_clone, total_pixels));
 
-        let pixels = (0..total_pixels)
-            .into_par_iter()
-            .map(|i| self.get_pixel_color(i, &completed_pixel_count, world))
+        // Bridging the tile list hands tiles to workers in the requested order as they become free.
+        // Once the time limit is reached, the remaining tiles are skipped; the film keeps per-pixel
+        // sample counts, so a partially rendered pass still averages correctly.
+        let mut tile_buffers = tiles
+            .iter()
+            .par_bridge()
+            .filter_map(|tile| {
+                if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                    completed_pixel_count.fetch_add(tile.pixel_count(), Ordering::Relaxed);
+                    None
+                } else {
+                    Some(self.render_tile(tile, samples, film, &completed_pixel_count, scene))
+                }
+            })
             .collect::<Vec<_>>();
 
         monitor_handle.join().unwrap();
 
-        for pixel in pixels {
-            println!("{pixel}");
-        }
+        // Filter footprints overlap neighbouring tiles, so buffers are merged in scanline order of
+        // their position to keep the floating point sums independent of thread scheduling and of
+        // the order tiles were handed out in
+        tile_buffers.sort_unstable_by_key(|buffer| (buffer.tile().y0(), buffer.tile().x0()));
+        tile_buffers
+    }
 
-        info!("Image rendering complete");
+    const fn render_settings(&self, samples_per_pass: i32) -> RenderSettings {
+        RenderSettings {
+            scene_fingerprint: self.scene_fingerprint,
+            samples_per_pixel: self.samples_per_pixel,
+            samples_per_pass,
+            max_depth: self.max_depth,
+            russian_roulette_depth: self.russian_roulette_depth,
+            target_relative_error: self.target_relative_error,
+            adaptive_sampling: self.adaptive_sampling,
+            seed: self.seed,
+            sampler_kind: self.sampler_kind,
+            filter: self.filter,
+            spectral: self.spectral,
+        }
     }
 
-    fn get_pixel_color(
+    fn load_checkpoint(
         &self,
-        pixel_index: usize,
-        completed_pixel_count: &Arc<AtomicUsize>,
-        world: &HittableCollection,
-    ) -> Color {
-        let (y, x) = (
-            pixel_index / usize::try_from(self.image_width).unwrap(),
-            pixel_index % usize::try_from(self.image_width).unwrap(),
+        image_width: usize,
+        image_height: usize,
+        samples_per_pass: i32,
+    ) -> io::Result<(Film, i32)> {
+        let path = self.checkpoint_path.as_ref().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Cannot resume a render without a checkpoint path",
+            )
+        })?;
+
+        let checkpoint = Checkpoint::load(path, image_width, image_height)?;
+        let completed_passes = checkpoint.completed_passes();
+        checkpoint
+            .settings()
+            .check_matches(&self.render_settings(samples_per_pass))?;
+        let film = checkpoint.into_film();
+
+        info!(
+            "Resuming render from {} after {completed_passes} completed passes",
+            path.display()
         );
 
-        let pixel_color: Color = (0..self.samples_per_pixel)
-            .into_par_iter()
-            .fold(Color::default, |color, _| {
-                let r = self.get_ray(i32::try_from(x).unwrap(), i32::try_from(y).unwrap());
-                color + ray_color(&r, self.max_depth, world)
-            })
-            .reduce(Color::default, |sum, c| sum + c);
+        Ok((film, completed_passes))
+    }
+
+    fn render_tile(
+        &self,
+        tile: &Tile,
+        pass_samples: i32,
+        film: &Film,
+        completed_pixel_count: &Arc<AtomicUsize>,
+        scene: &Scene,
+    ) -> TileBuffer {
+        let mut buffer = TileBuffer::new(*tile, self.filter, film.width(), film.height());
+        let pass_samples = u32::try_from(pass_samples).unwrap();
+        let samples_per_pixel = u32::try_from(self.samples_per_pixel).unwrap();
+
+        for (x, y) in tile.pixels() {
+            let (pixel_x, pixel_y) = (i32::try_from(x).unwrap(), i32::try_from(y).unwrap());
+            let pixel_index = u64::try_from(y * film.width() + x).unwrap();
+            let previous_samples = film.pixel_stats(x, y).count();
+            let pixel_samples = self.adaptive_sampling.map_or(pass_samples, |adaptive| {
+                adaptive.samples_for_pixel(film.pixel_stats(x, y), pass_samples)
+            });
+
+            // Samples are numbered across passes, so each one draws from the same random stream
+            // whether it is rendered in one go, in progressive passes, or after a resume
+            for sample_index in previous_samples..previous_samples + pixel_samples {
+                let mut sampler = Sampler::new(
+                    self.sampler_kind,
+                    self.seed,
+                    pixel_index,
+                    u64::from(sample_index),
+                    samples_per_pixel,
+                );
+                let offset = sample_square(&mut sampler);
+                let r = self.get_ray(pixel_x, pixel_y, &offset, &mut sampler);
+                let wavelengths = if self.spectral {
+                    Wavelengths::Sampled(SampledWavelengths::sample_visible(sampler.get_1d()))
+                } else {
+                    Wavelengths::Rgb
+                };
+                let color = ray_color(
+                    &r,
+                    self.max_depth,
+                    self.russian_roulette_depth,
+                    scene,
+                    &mut sampler,
+                    wavelengths,
+                );
+                buffer.add_sample(x, y, [offset.x(), offset.y()], color);
+            }
+        }
 
-        completed_pixel_count.fetch_add(1, Ordering::Relaxed);
+        completed_pixel_count.fetch_add(tile.pixel_count(), Ordering::Relaxed);
 
-        self.pixel_samples_scale * pixel_color
+        buffer
     }
 
-    fn get_ray(&self, x: i32, y: i32) -> Ray {
-        let offset = sample_square();
+    fn get_ray(&self, x: i32, y: i32, offset: &Vec3, sampler: &mut Sampler) -> Ray {
         let pixel_sample = self.pixel00_loc
             + ((f64::from(x) + offset.x()) * self.pixel_delta_u)
             + ((f64::from(y) + offset.y()) * self.pixel_delta_v);
         let ray_origin = if self.defocus_angle <= 0.0 {
             self.center
         } else {
-            self.defocus_disk_sample()
+            self.defocus_disk_sample(sampler)
         };
         let ray_direction = pixel_sample - ray_origin;
-        let ray_time: f64 = rand::rng().random();
+        let ray_time = sampler.get_1d();
 
         Ray::with_time(&ray_origin, &ray_direction, ray_time)
     }
 
-    fn defocus_disk_sample(&self) -> Point3 {
-        let p = random_in_unit_disk();
+    fn defocus_disk_sample(&self, sampler: &mut Sampler) -> Point3 {
+        let p = sample_uniform_disk(sampler.get_2d());
         self.center + (p[0] * self.defocus_disk_u) + (p[1] * self.defocus_disk_v)
     }
 }
 
+fn completion_report(completed_passes: i32, film: &Film) -> String {
+    format!(
+        "Rendered {completed_passes} passes, {:.1} samples per pixel on average",
+        film.average_samples_per_pixel()
+    )
+}
+
 fn monitor_loop(completed_pixel_count: &Arc<AtomicUsize>, total_pixels: usize) {
     loop {
         let current = completed_pixel_count.load(Ordering::Relaxed);
@@ -214,34 +483,166 @@ fn monitor_loop(completed_pixel_count: &Arc<AtomicUsize>, total_pixels: usize) {
     }
 }
 
-fn sample_square() -> Vec3 {
-    let mut rng = rand::rng();
-    Vec3::new(rng.random::<f64>() - 0.5, rng.random::<f64>() - 0.5, 0.0)
+fn sample_square(sampler: &mut Sampler) -> Vec3 {
+    let [u, v] = sampler.get_2d();
+    Vec3::new(u - 0.5, v - 0.5, 0.0)
 }
 
-fn ray_color(r: &Ray, depth: u32, world: &impl RayIntersection) -> Color {
-    if depth == 0 {
-        return Color::new(0.0, 0.0, 0.0);
-    }
+// Light reaching a surface is estimated twice, by sampling a light and by following the scattered
+// ray, and both estimates are combined with multiple importance sampling. `scattering_pdf` is the
+// density the previous surface chose the current ray with, or None for camera rays and specular
+// bounces, whose direction no light sample could have produced. Light is carried as RGB or at the
+// path's sampled wavelengths, and converted to RGB for the film at the end.
+fn ray_color(
+    camera_ray: &Ray,
+    max_depth: u32,
+    russian_roulette_depth: u32,
+    scene: &Scene,
+    sampler: &mut Sampler,
+    mut wavelengths: Wavelengths,
+) -> Color {
+    let mut radiance = SampledSpectrum::default();
+    let mut throughput = SampledSpectrum::constant(1.0);
+    let mut r = *camera_ray;
+    let mut scattering_pdf = None;
+
+    for depth in 0..max_depth {
+        let mut rec = HitRecord::default();
+        if !scene
+            .world()
+            .hit(&r, interval::ERROR_CORRECTED_NON_NEGATIVE, &mut rec)
+        {
+            radiance += throughput * wavelengths.illuminant(scene.background().color(&r));
+            for light in scene.lights() {
+                let mut escaped = wavelengths.illuminant(light.escaped_radiance(&r));
+                if let Some(scattering_pdf) = scattering_pdf
+                    && !escaped.is_black()
+                {
+                    let light_pdf = scene.light_selection_pdf() * light.pdf(&r, None);
+                    escaped *= power_heuristic(scattering_pdf, light_pdf);
+                }
+                radiance += throughput * escaped;
+            }
+            break;
+        }
+
+        sampler.start_next_bounce();
+        rec.set_wavelength(wavelengths.hero());
+        if let Some(normal) = rec.material().shading_normal(&rec) {
+            rec.set_shading_normal(&normal);
+        }
+        if rec.material().depends_on_wavelength() {
+            wavelengths.terminate_secondary();
+        }
 
-    let mut rec = HitRecord::default();
-    if world.hit(r, interval::ERROR_CORRECTED_NON_NEGATIVE, &mut rec) {
-        let mut scattered = Ray::default();
-        let mut attuentation = Color::default();
+        if !rec.front_face() {
+            throughput = throughput
+                * wavelengths.reflectance(
+                    rec.material()
+                        .transmittance(&rec, rec.t() * r.dir().length()),
+                );
+        }
 
-        if rec
-            .material()
-            .scatter(r, &rec, &mut attuentation, &mut scattered)
+        let mut emitted = wavelengths.illuminant(rec.material().emitted(&rec));
+        if let Some(scattering_pdf) = scattering_pdf
+            && !emitted.is_black()
         {
-            return attuentation * ray_color(&scattered, depth - 1, world);
+            let light_pdf = scene.light_pdf(&r, Some(&rec));
+            emitted *= power_heuristic(scattering_pdf, light_pdf);
+        }
+        radiance += throughput * emitted;
+
+        let wo = -r.dir().unit_vector();
+        let Some(sample) = rec.material().scatter(&wo, &rec, sampler) else {
+            break;
+        };
+
+        if sample.lobe.contains(Lobe::SPECULAR) {
+            scattering_pdf = None;
+        } else {
+            sampler.start_stage(BounceStage::Light);
+            radiance += throughput * sample_light(&r, &wo, &rec, scene, sampler, &wavelengths);
+            scattering_pdf = Some(sample.pdf);
         }
 
-        return Color::default();
+        throughput = throughput * wavelengths.reflectance(sample.weight);
+        r = Ray::with_time(rec.p(), &sample.direction, r.time());
+
+        // Russian roulette: paths carrying little light are terminated at random, and the survivors
+        // are weighted up to keep the estimate unbiased
+        if depth + 1 >= russian_roulette_depth {
+            let survival_probability = throughput.max_component().min(1.0);
+            sampler.start_stage(BounceStage::Roulette);
+            if sampler.get_1d() >= survival_probability {
+                break;
+            }
+            throughput *= survival_probability.recip();
+        }
+    }
+
+    wavelengths.to_color(radiance)
+}
+
+// Next-event estimation: picks a light, samples a direction towards it and traces a shadow ray to
+// check that nothing blocks it
+#[allow(
+    clippy::cast_possible_truncation,
+    clippy::cast_precision_loss,
+    clippy::cast_sign_loss
+)]
+fn sample_light(
+    r: &Ray,
+    wo: &Vec3,
+    rec: &HitRecord,
+    scene: &Scene,
+    sampler: &mut Sampler,
+    wavelengths: &Wavelengths,
+) -> SampledSpectrum {
+    let lights = scene.lights();
+    if lights.is_empty() {
+        return SampledSpectrum::default();
+    }
+
+    let index = ((sampler.get_1d() * lights.len() as f64) as usize).min(lights.len() - 1);
+    let Some(light_sample) = lights[index].sample(rec.p(), r.time(), sampler) else {
+        return SampledSpectrum::default();
+    };
+
+    let light_pdf = scene.light_selection_pdf() * light_sample.pdf;
+    let wi = light_sample.direction;
+    let bsdf = rec.material().eval(wo, &wi, rec);
+    if light_pdf <= 0.0 || bsdf.is_black() || light_sample.radiance.is_black() {
+        return SampledSpectrum::default();
     }
 
-    let unit_direction = r.dir().unit_vector();
-    let a = 0.5 * (unit_direction.y() + 1.0);
-    (1.0 - a) * Color::new(1.0, 1.0, 1.0) + a * Color::new(0.5, 0.7, 1.0)
+    // Stopping just short of the light keeps area lights from occluding themselves
+    let shadow_ray = Ray::with_time(rec.p(), &wi, r.time());
+    let shadow_t = Interval::new(
+        interval::ERROR_CORRECTED_NON_NEGATIVE.min,
+        light_sample.distance * (1.0 - SHADOW_RAY_EPSILON),
+    );
+    if scene
+        .world()
+        .hit(&shadow_ray, shadow_t, &mut HitRecord::default())
+    {
+        return SampledSpectrum::default();
+    }
+
+    let weight = if light_sample.delta {
+        1.0
+    } else {
+        power_heuristic(light_pdf, rec.material().pdf(wo, &wi, rec))
+    };
+
+    (weight / light_pdf)
+        * (wavelengths.reflectance(bsdf) * wavelengths.illuminant(light_sample.radiance))
+}
+
+// Veach's power heuristic with an exponent of two, weighting the strategy that sampled with
+// `pdf` against the one that would have produced the same direction with `other_pdf`
+fn power_heuristic(pdf: f64, other_pdf: f64) -> f64 {
+    let (a, b) = (pdf * pdf, other_pdf * other_pdf);
+    if a + b > 0.0 { a / (a + b) } else { 0.0 }
 }
 
 pub struct CameraBuilder(NewCameraParameters);
@@ -271,6 +672,11 @@ impl CameraBuilder {
         self
     }
 
+    pub const fn russian_roulette_depth(mut self, russian_roulette_depth: u32) -> Self {
+        self.0.russian_roulette_depth = russian_roulette_depth;
+        self
+    }
+
     pub const fn vertical_fov(mut self, vertical_fov: f64) -> Self {
         self.0.vertical_fov = vertical_fov;
         self
@@ -301,6 +707,92 @@ impl CameraBuilder {
         self
     }
 
+    pub const fn tile_size(mut self, tile_size: usize) -> Self {
+        self.0.tile_size = tile_size;
+        self
+    }
+
+    pub const fn tile_order(mut self, tile_order: TileOrder) -> Self {
+        self.0.tile_order = tile_order;
+        self
+    }
+
+    pub const fn samples_per_pass(mut self, samples_per_pass: i32) -> Self {
+        self.0.samples_per_pass = Some(samples_per_pass);
+        self
+    }
+
+    pub fn snapshot_path(mut self, snapshot_path: impl Into<PathBuf>) -> Self {
+        self.0.snapshot_path = Some(snapshot_path.into());
+        self
+    }
+
+    pub const fn snapshot_interval(mut self, snapshot_interval: Duration) -> Self {
+        self.0.snapshot_interval = Some(snapshot_interval);
+        self
+    }
+
+    pub fn checkpoint_path(mut self, checkpoint_path: impl Into<PathBuf>) -> Self {
+        self.0.checkpoint_path = Some(checkpoint_path.into());
+        self
+    }
+
+    pub const fn checkpoint_interval(mut self, checkpoint_interval: Duration) -> Self {
+        self.0.checkpoint_interval = Some(checkpoint_interval);
+        self
+    }
+
+    pub const fn resume(mut self, resume: bool) -> Self {
+        self.0.resume = resume;
+        self
+    }
+
+    pub const fn time_limit(mut self, time_limit: Duration) -> Self {
+        self.0.time_limit = Some(time_limit);
+        self
+    }
+
+    pub const fn target_relative_error(mut self, target_relative_error: f64) -> Self {
+        self.0.target_relative_error = Some(target_relative_error);
+        self
+    }
+
+    pub const fn adaptive_sampling(mut self, adaptive_sampling: AdaptiveSampling) -> Self {
+        self.0.adaptive_sampling = Some(adaptive_sampling);
+        self
+    }
+
+    pub fn sample_count_map_path(mut self, sample_count_map_path: impl Into<PathBuf>) -> Self {
+        self.0.sample_count_map_path = Some(sample_count_map_path.into());
+        self
+    }
+
+    // Identifies the scene in checkpoints, so a render is only resumed with the scene it started
+    pub const fn scene_fingerprint(mut self, scene_fingerprint: u64) -> Self {
+        self.0.scene_fingerprint = scene_fingerprint;
+        self
+    }
+
+    pub const fn seed(mut self, seed: u64) -> Self {
+        self.0.seed = seed;
+        self
+    }
+
+    pub const fn sampler(mut self, sampler_kind: SamplerKind) -> Self {
+        self.0.sampler_kind = sampler_kind;
+        self
+    }
+
+    pub const fn filter(mut self, filter: Filter) -> Self {
+        self.0.filter = filter;
+        self
+    }
+
+    pub const fn spectral(mut self, spectral: bool) -> Self {
+        self.0.spectral = spectral;
+        self
+    }
+
     pub fn build(self) -> Camera {
         Camera::new(&self.0)
     }
@@ -313,12 +805,206 @@ impl Default for CameraBuilder {
             image_width: 100,
             samples_per_pixel: 10,
             max_depth: 10,
+            russian_roulette_depth: 3,
             vertical_fov: 90.0,
             look_from: Point3::default(),
             look_at: Point3::new(0.0, 0.0, -1.0),
             v_up: Vec3::new(0.0, 1.0, 0.0),
             defocus_angle: 0.0,
             focus_distance: 10.0,
+            tile_size: 16,
+            tile_order: TileOrder::Scanline,
+            samples_per_pass: None,
+            snapshot_path: None,
+            snapshot_interval: None,
+            checkpoint_path: None,
+            checkpoint_interval: None,
+            resume: false,
+            time_limit: None,
+            target_relative_error: None,
+            adaptive_sampling: None,
+            sample_count_map_path: None,
+            scene_fingerprint: 0,
+            seed: 0,
+            sampler_kind: SamplerKind::Independent,
+            filter: Filter::default(),
+            spectral: false,
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        filter::MitchellNetravaliFilter,
+        hittable::{Hittable, Quad, Sphere},
+        hittable_collection::HittableCollection,
+        light::{AreaLight, Light},
+        material::{Dielectric, DiffuseLight, Lambertian, Material, Metal},
+        scene::Background,
+    };
+
+    // A diffuse floor, a glass and a metal ball under a small area light and the sky
+    fn scene() -> Scene {
+        let material = |material: Material| Arc::new(material);
+        let light: Arc<dyn RayIntersection> = Arc::new(Hittable::from(Quad::new(
+            &Point3::new(-0.5, 2.0, -1.5),
+            &Vec3::new(1.0, 0.0, 0.0),
+            &Vec3::new(0.0, 0.0, 1.0),
+            &material(Material::from(DiffuseLight::new(Color::new(8.0, 8.0, 8.0)))),
+        )));
+
+        let mut world = HittableCollection::default();
+        world.add(Arc::new(Hittable::from(Sphere::new(
+            &Point3::new(0.0, -100.5, -1.0),
+            100.0,
+            &material(Material::from(Lambertian::new(Color::new(0.6, 0.5, 0.4)))),
+        ))));
+        world.add(Arc::new(Hittable::from(Sphere::new(
+            &Point3::new(-0.5, 0.0, -1.0),
+            0.5,
+            &material(Material::from(Dielectric::new(1.5))),
+        ))));
+        world.add(Arc::new(Hittable::from(Sphere::new(
+            &Point3::new(0.5, 0.0, -1.0),
+            0.5,
+            &material(Material::from(Metal::new(Color::new(0.8, 0.6, 0.2), 0.3))),
+        ))));
+        world.add(Arc::clone(&light));
+
+        Scene::new(
+            world,
+            vec![Light::from(AreaLight::new(light))],
+            Background::Sky,
+        )
+    }
+
+    fn film_bytes(film: &Film) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        film.write_binary(&mut bytes).unwrap();
+        bytes
+    }
+
+    // Samples are seeded by pixel and sample index and tile buffers are merged in tile order, so
+    // neither the number of threads nor the order tiles are handed out in changes a single bit.
+    // The Mitchell filter splats every sample across tile borders.
+    #[test]
+    fn renders_are_independent_of_threads_and_tile_order() {
+        let scene = scene();
+        let render = |threads, tile_order| {
+            let camera = CameraBuilder::default()
+                .image_width(16)
+                .samples_per_pixel(2)
+                .max_depth(6)
+                .tile_size(5)
+                .tile_order(tile_order)
+                .sampler(SamplerKind::Sobol)
+                .filter(Filter::from(MitchellNetravaliFilter::new(
+                    2.0,
+                    1.0 / 3.0,
+                    1.0 / 3.0,
+                )))
+                .build();
+            let pool = rayon::ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .build()
+                .unwrap();
+            film_bytes(&pool.install(|| camera.render_film(&scene)).unwrap())
+        };
+
+        let reference = render(1, TileOrder::Scanline);
+        for threads in [1, 2, 3, 8] {
+            for tile_order in [TileOrder::Scanline, TileOrder::Spiral, TileOrder::Hilbert] {
+                assert!(
+                    render(threads, tile_order) == reference,
+                    "{threads} threads, {tile_order:?} order"
+                );
+            }
+        }
+    }
+
+    // A film whose pixels each received `samples` samples, all the same or alternating between
+    // black and white
+    fn film_with_samples(camera: &Camera, samples: u32, noisy: bool) -> Film {
+        let (width, height) = (
+            usize::try_from(camera.image_width).unwrap(),
+            usize::try_from(camera.image_height).unwrap(),
+        );
+        let mut film = Film::new(width, height);
+        let tile = Tile::new(0, 0, width, height);
+        let mut buffer = TileBuffer::new(tile, camera.filter, width, height);
+        for (x, y) in tile.pixels() {
+            for i in 0..samples {
+                let value = if noisy { f64::from(i % 2) } else { 0.5 };
+                buffer.add_sample(x, y, [0.0, 0.0], Color::new(value, value, value));
+            }
+        }
+        film.merge_tile(&buffer);
+        film
+    }
+
+    #[test]
+    fn target_error_stops_once_met() {
+        let camera = CameraBuilder::default()
+            .image_width(4)
+            .samples_per_pixel(64)
+            .samples_per_pass(8)
+            .target_relative_error(0.05)
+            .build();
+
+        // Converged pixels only count once there are enough samples to trust their error
+        let few = film_with_samples(&camera, MIN_SAMPLES_FOR_ERROR_ESTIMATE - 8, false);
+        assert!(!camera.render_finished(1, &few, None));
+        let converged = film_with_samples(&camera, MIN_SAMPLES_FOR_ERROR_ESTIMATE, false);
+        assert!(camera.render_finished(2, &converged, None));
+        assert_eq!(camera.target_error_warning(&converged), None);
+
+        // A target that is never met still stops at the fixed sample count, with a warning
+        let noisy = film_with_samples(&camera, 32, true);
+        assert!(!camera.render_finished(4, &noisy, None));
+        let noisy = film_with_samples(&camera, 64, true);
+        assert!(camera.render_finished(8, &noisy, None));
+        assert!(camera.target_error_warning(&noisy).is_some());
+    }
+
+    #[test]
+    fn time_limit_stops_at_the_deadline() {
+        let camera = CameraBuilder::default()
+            .image_width(4)
+            .samples_per_pixel(4)
+            .samples_per_pass(1)
+            .time_limit(Duration::from_mins(1))
+            .build();
+
+        // The fixed sample count doesn't apply under a time limit, only the deadline does
+        let film = film_with_samples(&camera, 8, true);
+        let later = Instant::now() + Duration::from_mins(1);
+        assert!(!camera.render_finished(8, &film, Some(later)));
+        assert!(camera.render_finished(8, &film, Some(Instant::now())));
+    }
+
+    #[test]
+    fn time_limited_renders_report_the_samples_reached() {
+        let camera = CameraBuilder::default()
+            .image_width(8)
+            .samples_per_pixel(1_000_000)
+            .samples_per_pass(1)
+            .max_depth(4)
+            .time_limit(Duration::from_millis(300))
+            .build();
+
+        let start = Instant::now();
+        let film = camera.render_film(&scene()).unwrap();
+        assert!(start.elapsed() < Duration::from_secs(10));
+
+        let samples = film.average_samples_per_pixel();
+        assert!((1.0..1_000_000.0).contains(&samples), "{samples}");
+        assert!(
+            completion_report(3, &film)
+                .ends_with(&format!("{samples:.1} samples per pixel on average")),
+            "{}",
+            completion_report(3, &film)
+        );
+    }
+}
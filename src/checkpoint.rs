@@ -0,0 +1,468 @@
+use crate::{
+    adaptive_sampling::AdaptiveSampling, film::Film, filter::Filter, sampler::SamplerKind,
+};
+use std::{
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+
+const MAGIC: &[u8; 8] = b"WRTCKPT\0";
+const VERSION: u32 = 6;
+
+// Settings that decide which samples a render takes, how they are weighted and when it stops. A
+// render can only be resumed with the same settings, or the film would blend different estimates
+// together. The scene itself is built in code, so it is identified by a fingerprint of the options
+// it was built from.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RenderSettings {
+    pub scene_fingerprint: u64,
+    pub samples_per_pixel: i32,
+    pub samples_per_pass: i32,
+    pub max_depth: u32,
+    pub russian_roulette_depth: u32,
+    pub target_relative_error: Option<f64>,
+    pub adaptive_sampling: Option<AdaptiveSampling>,
+    pub seed: u64,
+    pub sampler_kind: SamplerKind,
+    pub filter: Filter,
+    pub spectral: bool,
+}
+
+impl RenderSettings {
+    // Fails with a description of the first setting of a checkpoint that differs from the current
+    // render's
+    #[allow(clippy::float_cmp)]
+    pub fn check_matches(&self, expected: &Self) -> io::Result<()> {
+        let mismatch =
+            |setting: &str, saved: &dyn std::fmt::Debug, expected: &dyn std::fmt::Debug| {
+                Err(invalid_data(format!(
+                    "Checkpoint was rendered with {setting} {saved:?}, expected {expected:?}"
+                )))
+            };
+
+        if self.scene_fingerprint != expected.scene_fingerprint {
+            return Err(invalid_data(
+                "Checkpoint was rendered from a different scene or scene options".to_string(),
+            ));
+        }
+        if self.samples_per_pixel != expected.samples_per_pixel {
+            return mismatch(
+                "samples per pixel",
+                &self.samples_per_pixel,
+                &expected.samples_per_pixel,
+            );
+        }
+        if self.samples_per_pass != expected.samples_per_pass {
+            return mismatch(
+                "samples per pass",
+                &self.samples_per_pass,
+                &expected.samples_per_pass,
+            );
+        }
+        if (self.max_depth, self.russian_roulette_depth)
+            != (expected.max_depth, expected.russian_roulette_depth)
+        {
+            return mismatch(
+                "maximum and Russian roulette depths",
+                &(self.max_depth, self.russian_roulette_depth),
+                &(expected.max_depth, expected.russian_roulette_depth),
+            );
+        }
+        if self.target_relative_error != expected.target_relative_error {
+            return mismatch(
+                "target error",
+                &self.target_relative_error,
+                &expected.target_relative_error,
+            );
+        }
+        if self.adaptive_sampling != expected.adaptive_sampling {
+            return mismatch(
+                "adaptive sampling",
+                &self.adaptive_sampling,
+                &expected.adaptive_sampling,
+            );
+        }
+        // Continuing with a different seed would repeat or skip parts of the random streams
+        if self.seed != expected.seed {
+            return mismatch("seed", &self.seed, &expected.seed);
+        }
+        if self.sampler_kind != expected.sampler_kind {
+            return mismatch("sampler", &self.sampler_kind, &expected.sampler_kind);
+        }
+        // Pixels are normalized by the filter weights they received, so a different filter would
+        // mix two reconstructions
+        if self.filter != expected.filter {
+            return mismatch("filter", &self.filter, &expected.filter);
+        }
+        if self.spectral != expected.spectral {
+            let mode = |spectral| if spectral { "spectral" } else { "RGB" };
+            return Err(invalid_data(format!(
+                "Checkpoint was rendered in {} mode, expected {} mode",
+                mode(self.spectral),
+                mode(expected.spectral)
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+// 64-bit FNV-1a, which unlike the standard library's hasher is stable across builds
+pub fn fingerprint(bytes: &[u8]) -> u64 {
+    const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0100_0000_01b3;
+
+    bytes.iter().fold(OFFSET_BASIS, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(PRIME)
+    })
+}
+
+#[derive(Clone, Debug)]
+pub struct Checkpoint {
+    film: Film,
+    completed_passes: i32,
+    settings: RenderSettings,
+}
+
+impl Checkpoint {
+    pub fn into_film(self) -> Film {
+        self.film
+    }
+
+    pub const fn completed_passes(&self) -> i32 {
+        self.completed_passes
+    }
+
+    pub const fn settings(&self) -> &RenderSettings {
+        &self.settings
+    }
+
+    // Like snapshots, checkpoints are written to a temporary file first so an interrupted write
+    // never destroys the previous checkpoint
+    pub fn save(
+        path: &Path,
+        film: &Film,
+        completed_passes: i32,
+        settings: &RenderSettings,
+    ) -> io::Result<()> {
+        let temp_path = path.with_extension("tmp");
+        let mut out = BufWriter::new(File::create(&temp_path)?);
+
+        out.write_all(MAGIC)?;
+        out.write_all(&VERSION.to_le_bytes())?;
+        out.write_all(&completed_passes.to_le_bytes())?;
+        write_settings(&mut out, settings)?;
+        film.write_binary(&mut out)?;
+        out.flush()?;
+        drop(out);
+
+        fs::rename(&temp_path, path)
+    }
+
+    // Loads a checkpoint of a `width` by `height` render
+    pub fn load(path: &Path, width: usize, height: usize) -> io::Result<Self> {
+        let mut input = BufReader::new(File::open(path)?);
+
+        let mut magic = [0; MAGIC.len()];
+        input.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid_data(format!(
+                "{} is not a render checkpoint",
+                path.display()
+            )));
+        }
+
+        let version = read_u32(&mut input)?;
+        if version != VERSION {
+            return Err(invalid_data(format!(
+                "Unsupported checkpoint version {version}"
+            )));
+        }
+
+        let completed_passes = read_i32(&mut input)?;
+        if completed_passes < 0 {
+            return Err(invalid_data(format!(
+                "Invalid number of completed passes {completed_passes} in checkpoint"
+            )));
+        }
+        let settings = read_settings(&mut input)?;
+        let film = Film::read_binary(&mut input, width, height)?;
+
+        Ok(Self {
+            film,
+            completed_passes,
+            settings,
+        })
+    }
+}
+
+// Optional settings are stored as a presence flag followed by their values, zero when absent
+fn write_settings(out: &mut impl Write, settings: &RenderSettings) -> io::Result<()> {
+    out.write_all(&settings.scene_fingerprint.to_le_bytes())?;
+    out.write_all(&settings.samples_per_pixel.to_le_bytes())?;
+    out.write_all(&settings.samples_per_pass.to_le_bytes())?;
+    out.write_all(&settings.max_depth.to_le_bytes())?;
+    out.write_all(&settings.russian_roulette_depth.to_le_bytes())?;
+
+    out.write_all(&[u8::from(settings.target_relative_error.is_some())])?;
+    out.write_all(&settings.target_relative_error.unwrap_or(0.0).to_le_bytes())?;
+
+    let adaptive = settings.adaptive_sampling;
+    out.write_all(&[u8::from(adaptive.is_some())])?;
+    out.write_all(
+        &adaptive
+            .map_or(0, |a| a.min_samples_per_pixel())
+            .to_le_bytes(),
+    )?;
+    out.write_all(
+        &adaptive
+            .map_or(0, |a| a.max_samples_per_pixel())
+            .to_le_bytes(),
+    )?;
+    out.write_all(&adaptive.map_or(0.0, |a| a.error_threshold()).to_le_bytes())?;
+
+    out.write_all(&settings.seed.to_le_bytes())?;
+    out.write_all(&[settings.sampler_kind.to_tag(), u8::from(settings.spectral)])?;
+    let (filter_tag, filter_parameters) = settings.filter.to_parameters();
+    out.write_all(&[filter_tag])?;
+    for parameter in filter_parameters {
+        out.write_all(&parameter.to_le_bytes())?;
+    }
+
+    Ok(())
+}
+
+fn read_settings(input: &mut impl Read) -> io::Result<RenderSettings> {
+    let scene_fingerprint = read_u64(input)?;
+    let samples_per_pixel = read_i32(input)?;
+    let samples_per_pass = read_i32(input)?;
+    let max_depth = read_u32(input)?;
+    let russian_roulette_depth = read_u32(input)?;
+
+    let [has_target] = read_bytes(input)?;
+    let target = read_f64(input)?;
+    let [has_adaptive] = read_bytes(input)?;
+    let adaptive = AdaptiveSampling::new(read_u32(input)?, read_u32(input)?, read_f64(input)?);
+
+    let seed = read_u64(input)?;
+    let [sampler_tag, spectral, filter_tag] = read_bytes(input)?;
+    let filter_parameters = [read_f64(input)?, read_f64(input)?, read_f64(input)?];
+    let sampler_kind = SamplerKind::from_tag(sampler_tag)
+        .ok_or_else(|| invalid_data(format!("Unknown sampler {sampler_tag} in checkpoint")))?;
+    let filter = Filter::from_parameters(filter_tag, filter_parameters)
+        .ok_or_else(|| invalid_data(format!("Unknown filter {filter_tag} in checkpoint")))?;
+
+    Ok(RenderSettings {
+        scene_fingerprint,
+        samples_per_pixel,
+        samples_per_pass,
+        max_depth,
+        russian_roulette_depth,
+        target_relative_error: (has_target != 0).then_some(target),
+        adaptive_sampling: (has_adaptive != 0).then_some(adaptive),
+        seed,
+        sampler_kind,
+        filter,
+        spectral: spectral != 0,
+    })
+}
+
+fn read_bytes<const N: usize>(input: &mut impl Read) -> io::Result<[u8; N]> {
+    let mut bytes = [0; N];
+    input.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+pub fn read_u32(input: &mut impl Read) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    input.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+pub fn read_i32(input: &mut impl Read) -> io::Result<i32> {
+    let mut bytes = [0; 4];
+    input.read_exact(&mut bytes)?;
+    Ok(i32::from_le_bytes(bytes))
+}
+
+pub fn read_u64(input: &mut impl Read) -> io::Result<u64> {
+    let mut bytes = [0; 8];
+    input.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+pub fn read_f64(input: &mut impl Read) -> io::Result<f64> {
+    let mut bytes = [0; 8];
+    input.read_exact(&mut bytes)?;
+    Ok(f64::from_le_bytes(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        color::Color,
+        film::TileBuffer,
+        filter::{GaussianFilter, MitchellNetravaliFilter},
+        tile::Tile,
+    };
+    use std::{env, path::PathBuf};
+
+    const WIDTH: usize = 5;
+    const HEIGHT: usize = 3;
+
+    fn settings() -> RenderSettings {
+        RenderSettings {
+            scene_fingerprint: fingerprint(b"cornell"),
+            samples_per_pixel: 64,
+            samples_per_pass: 8,
+            max_depth: 12,
+            russian_roulette_depth: 4,
+            target_relative_error: Some(0.02),
+            adaptive_sampling: Some(AdaptiveSampling::new(16, 256, 0.05)),
+            seed: 42,
+            sampler_kind: SamplerKind::Sobol,
+            filter: Filter::from(MitchellNetravaliFilter::new(2.0, 1.0 / 3.0, 1.0 / 3.0)),
+            spectral: true,
+        }
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    fn film() -> Film {
+        let mut film = Film::new(WIDTH, HEIGHT);
+        let mut buffer = TileBuffer::new(
+            Tile::new(0, 0, WIDTH, HEIGHT),
+            Filter::from(GaussianFilter::default()),
+            WIDTH,
+            HEIGHT,
+        );
+        for (i, (x, y)) in Tile::new(0, 0, WIDTH, HEIGHT).pixels().enumerate() {
+            let value = i as f64 / 7.0;
+            buffer.add_sample(x, y, [0.3, 0.6], Color::new(value, 0.5, 1.0 - value));
+            buffer.add_sample(x, y, [0.8, 0.1], Color::new(0.25, value, 2.0));
+        }
+        film.merge_tile(&buffer);
+        film
+    }
+
+    fn film_bytes(film: &Film) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        film.write_binary(&mut bytes).unwrap();
+        bytes
+    }
+
+    fn temp_path(name: &str) -> PathBuf {
+        env::temp_dir().join(format!("checkpoint-test-{}-{name}", std::process::id()))
+    }
+
+    // Saves a valid checkpoint, lets `corrupt` edit its bytes and tries to load the result
+    fn load_corrupted(name: &str, corrupt: impl FnOnce(&mut Vec<u8>)) -> io::Result<Checkpoint> {
+        let path = temp_path(name);
+        Checkpoint::save(&path, &film(), 3, &settings()).unwrap();
+        let mut bytes = fs::read(&path).unwrap();
+        corrupt(&mut bytes);
+        fs::write(&path, bytes).unwrap();
+        let result = Checkpoint::load(&path, WIDTH, HEIGHT);
+        fs::remove_file(&path).unwrap();
+        result
+    }
+
+    #[test]
+    fn round_trips() {
+        let path = temp_path("round-trip");
+        let film = film();
+        for settings in [
+            settings(),
+            RenderSettings {
+                target_relative_error: None,
+                adaptive_sampling: None,
+                spectral: false,
+                ..settings()
+            },
+        ] {
+            Checkpoint::save(&path, &film, 3, &settings).unwrap();
+            let checkpoint = Checkpoint::load(&path, WIDTH, HEIGHT).unwrap();
+            fs::remove_file(&path).unwrap();
+
+            assert_eq!(checkpoint.completed_passes(), 3);
+            assert_eq!(*checkpoint.settings(), settings);
+            checkpoint.settings().check_matches(&settings).unwrap();
+            assert_eq!(film_bytes(&checkpoint.into_film()), film_bytes(&film));
+        }
+    }
+
+    #[test]
+    fn rejects_corrupted_files() {
+        // Offsets into the header: the magic, then the version and the completed passes
+        let failures = [
+            load_corrupted("magic", |bytes| bytes[0] = b'X'),
+            load_corrupted("version", |bytes| bytes[8] = 99),
+            load_corrupted("passes", |bytes| {
+                bytes[12..16].copy_from_slice(&(-1i32).to_le_bytes());
+            }),
+            load_corrupted("truncated", |bytes| bytes.truncate(bytes.len() - 5)),
+            load_corrupted("empty", Vec::clear),
+        ];
+        for failure in failures {
+            assert!(failure.is_err());
+        }
+
+        // The image size sits right after the settings, which end with the filter parameters
+        let film_start = settings_end();
+        let resized = load_corrupted("size", |bytes| {
+            bytes[film_start..film_start + 8].copy_from_slice(&u64::MAX.to_le_bytes());
+        });
+        assert_eq!(resized.unwrap_err().kind(), io::ErrorKind::InvalidData);
+
+        let path = temp_path("missing");
+        assert!(Checkpoint::load(&path, WIDTH, HEIGHT).is_err());
+    }
+
+    // Length of the header and settings that come before the film
+    fn settings_end() -> usize {
+        let mut bytes = Vec::new();
+        write_settings(&mut bytes, &settings()).unwrap();
+        MAGIC.len() + 4 + 4 + bytes.len()
+    }
+
+    #[test]
+    fn rejects_different_settings() {
+        let saved = settings();
+        let changed = [
+            RenderSettings {
+                scene_fingerprint: fingerprint(b"lookdev"),
+                ..saved
+            },
+            RenderSettings {
+                samples_per_pixel: 128,
+                ..saved
+            },
+            RenderSettings {
+                max_depth: 50,
+                ..saved
+            },
+            RenderSettings {
+                target_relative_error: None,
+                ..saved
+            },
+            RenderSettings {
+                adaptive_sampling: Some(AdaptiveSampling::new(16, 256, 0.1)),
+                ..saved
+            },
+            RenderSettings { seed: 7, ..saved },
+            RenderSettings {
+                spectral: false,
+                ..saved
+            },
+        ];
+        for expected in changed {
+            let error = saved.check_matches(&expected).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData, "{expected:?}");
+        }
+    }
+}
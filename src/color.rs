@@ -9,6 +9,49 @@ impl Color {
     pub const fn new(r: f64, g: f64, b: f64) -> Self {
         Self(Vec3::new(r, g, b))
     }
+
+    pub const fn r(&self) -> f64 {
+        self.0.x()
+    }
+
+    pub const fn g(&self) -> f64 {
+        self.0.y()
+    }
+
+    pub const fn b(&self) -> f64 {
+        self.0.z()
+    }
+
+    pub fn is_black(&self) -> bool {
+        self.0.x() == 0.0 && self.0.y() == 0.0 && self.0.z() == 0.0
+    }
+
+    pub const fn max_component(&self) -> f64 {
+        self.0.x().max(self.0.y()).max(self.0.z())
+    }
+
+    // Linear Rec. 709 RGB from CIE XYZ. Colors outside the gamut have negative components.
+    pub fn from_xyz_unclipped(x: f64, y: f64, z: f64) -> Self {
+        Self::new(
+            3.2406f64.mul_add(x, (-1.5372f64).mul_add(y, -0.4986 * z)),
+            (-0.9689f64).mul_add(x, 1.8758f64.mul_add(y, 0.0415 * z)),
+            0.0557f64.mul_add(x, (-0.2040f64).mul_add(y, 1.0570 * z)),
+        )
+    }
+
+    // As above, with colors outside the gamut clipped to black
+    pub fn from_xyz(x: f64, y: f64, z: f64) -> Self {
+        let color = Self::from_xyz_unclipped(x, y, z);
+        Self::new(color.r().max(0.0), color.g().max(0.0), color.b().max(0.0))
+    }
+
+    // Relative luminance using the Rec. 709 primaries
+    pub fn luminance(&self) -> f64 {
+        0.2126f64.mul_add(
+            self.0.x(),
+            0.7152f64.mul_add(self.0.y(), 0.0722 * self.0.z()),
+        )
+    }
 }
 
 impl Color {
@@ -0,0 +1,186 @@
+// A piecewise-constant density over [0, 1), proportional to the non-negative `function` values of
+// its equally sized segments
+#[derive(Clone, Debug)]
+pub struct Distribution1D {
+    function: Vec<f64>,
+    cdf: Vec<f64>,
+    integral: f64,
+}
+
+impl Distribution1D {
+    #[allow(clippy::cast_precision_loss)]
+    pub fn new(function: Vec<f64>) -> Self {
+        assert!(
+            !function.is_empty(),
+            "Distribution needs at least one value"
+        );
+        let n = function.len() as f64;
+
+        let mut cdf = Vec::with_capacity(function.len() + 1);
+        cdf.push(0.0);
+        for value in &function {
+            cdf.push(cdf[cdf.len() - 1] + value.max(0.0) / n);
+        }
+
+        // A function that is zero everywhere is sampled uniformly
+        let integral = cdf[function.len()];
+        for (i, value) in cdf.iter_mut().enumerate() {
+            *value = if integral > 0.0 {
+                *value / integral
+            } else {
+                i as f64 / n
+            };
+        }
+
+        Self {
+            function,
+            cdf,
+            integral,
+        }
+    }
+
+    pub const fn integral(&self) -> f64 {
+        self.integral
+    }
+
+    pub const fn len(&self) -> usize {
+        self.function.len()
+    }
+
+    // Maps `u` to a point in [0, 1), returning it with its density and the segment it lies in
+    #[allow(clippy::cast_precision_loss)]
+    pub fn sample(&self, u: f64) -> (f64, f64, usize) {
+        let segment = self
+            .cdf
+            .partition_point(|&c| c <= u)
+            .saturating_sub(1)
+            .min(self.len() - 1);
+
+        let width = self.cdf[segment + 1] - self.cdf[segment];
+        let offset = if width > 0.0 {
+            (u - self.cdf[segment]) / width
+        } else {
+            0.0
+        };
+
+        let x = ((segment as f64 + offset) / self.len() as f64).min(1.0 - f64::EPSILON);
+        (x, self.segment_pdf(segment), segment)
+    }
+
+    fn segment_pdf(&self, segment: usize) -> f64 {
+        if self.integral > 0.0 {
+            self.function[segment].max(0.0) / self.integral
+        } else {
+            1.0
+        }
+    }
+
+    #[allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_precision_loss,
+        clippy::cast_sign_loss
+    )]
+    fn segment(&self, x: f64) -> usize {
+        ((x * self.len() as f64) as usize).min(self.len() - 1)
+    }
+}
+
+// A piecewise-constant density over [0, 1)², sampled by picking a row from the marginal density
+// and then a column within it. `function` is stored row by row.
+#[derive(Clone, Debug)]
+pub struct Distribution2D {
+    rows: Vec<Distribution1D>,
+    marginal: Distribution1D,
+}
+
+impl Distribution2D {
+    pub fn new(function: &[f64], width: usize, height: usize) -> Self {
+        assert_eq!(
+            function.len(),
+            width * height,
+            "Value count must match size"
+        );
+
+        let rows: Vec<_> = function
+            .chunks_exact(width)
+            .map(|row| Distribution1D::new(row.to_vec()))
+            .collect();
+        let marginal = Distribution1D::new(rows.iter().map(Distribution1D::integral).collect());
+
+        Self { rows, marginal }
+    }
+
+    pub fn sample(&self, u: [f64; 2]) -> ([f64; 2], f64) {
+        let (y, row_pdf, row) = self.marginal.sample(u[1]);
+        let (x, column_pdf, _) = self.rows[row].sample(u[0]);
+        ([x, y], row_pdf * column_pdf)
+    }
+
+    pub fn pdf(&self, [x, y]: [f64; 2]) -> f64 {
+        let row = &self.rows[self.marginal.segment(y)];
+        self.marginal.segment_pdf(self.marginal.segment(y)) * row.segment_pdf(row.segment(x))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WIDTH: usize = 3;
+    const HEIGHT: usize = 2;
+    const FUNCTION: [f64; WIDTH * HEIGHT] = [1.0, 2.0, 3.0, 0.0, 4.0, 2.0];
+    const GRID: usize = 120;
+
+    #[allow(clippy::cast_precision_loss)]
+    fn grid_points() -> impl Iterator<Item = [f64; 2]> {
+        (0..GRID * GRID).map(|i| {
+            [
+                ((i % GRID) as f64 + 0.5) / GRID as f64,
+                ((i / GRID) as f64 + 0.5) / GRID as f64,
+            ]
+        })
+    }
+
+    #[test]
+    #[allow(clippy::cast_precision_loss)]
+    fn pdf_integrates_to_one() {
+        let distribution = Distribution2D::new(&FUNCTION, WIDTH, HEIGHT);
+        let integral = grid_points()
+            .map(|point| distribution.pdf(point))
+            .sum::<f64>()
+            / (GRID * GRID) as f64;
+        assert!((integral - 1.0).abs() < 1e-12, "{integral}");
+    }
+
+    #[test]
+    fn samples_report_their_pdf() {
+        let distribution = Distribution2D::new(&FUNCTION, WIDTH, HEIGHT);
+        for u in grid_points() {
+            let (point, pdf) = distribution.sample(u);
+            assert!(point.iter().all(|c| (0.0..1.0).contains(c)), "{point:?}");
+            assert!(pdf > 0.0, "{u:?} sampled {point:?} with zero density");
+            assert!((pdf - distribution.pdf(point)).abs() < 1e-12, "{u:?}");
+        }
+    }
+
+    #[test]
+    #[allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_precision_loss,
+        clippy::cast_sign_loss
+    )]
+    fn samples_follow_the_function() {
+        let distribution = Distribution2D::new(&FUNCTION, WIDTH, HEIGHT);
+        let mut counts = [0; WIDTH * HEIGHT];
+        for u in grid_points() {
+            let ([x, y], _) = distribution.sample(u);
+            counts[(y * HEIGHT as f64) as usize * WIDTH + (x * WIDTH as f64) as usize] += 1;
+        }
+
+        let total: f64 = FUNCTION.iter().sum();
+        for (count, value) in counts.into_iter().zip(FUNCTION) {
+            let fraction = f64::from(count) / (GRID * GRID) as f64;
+            assert!((fraction - value / total).abs() < 0.01, "{counts:?}");
+        }
+    }
+}
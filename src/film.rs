@@ -0,0 +1,381 @@
+use crate::{
+    checkpoint::{read_f64, read_u32, read_u64},
+    color::Color,
+    filter::{Filter, ReconstructionFilter},
+    running_stats::RunningStats,
+    tile::Tile,
+    vec3::Vec3,
+};
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter, Read, Write},
+    path::Path,
+};
+
+// Filters integrate to 1, so a sample landing in a pixel gives it a weight of the order of 1. Weight
+// sums below this come from the tails of samples centred elsewhere.
+const MIN_WEIGHT_SUM: f64 = 1e-2;
+
+// Samples are splatted into every pixel within the filter radius, so a tile buffer covers the tile
+// plus a margin that overlaps neighbouring tiles. Variance statistics are only kept for the pixels
+// of the tile itself, which are the pixels the samples were taken for.
+#[derive(Clone, Debug)]
+pub struct TileBuffer {
+    tile: Tile,
+    splat_region: Tile,
+    filter: Filter,
+    weighted_sums: Vec<Color>,
+    weight_sums: Vec<f64>,
+    pixel_stats: Vec<RunningStats>,
+}
+
+impl TileBuffer {
+    pub fn new(tile: Tile, filter: Filter, image_width: usize, image_height: usize) -> Self {
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        let margin = (filter.radius() - 0.5).ceil().max(0.0) as usize;
+        let splat_region = Tile::new(
+            tile.x0().saturating_sub(margin),
+            tile.y0().saturating_sub(margin),
+            (tile.x1() + margin).min(image_width),
+            (tile.y1() + margin).min(image_height),
+        );
+
+        Self {
+            tile,
+            splat_region,
+            filter,
+            weighted_sums: vec![Color::default(); splat_region.pixel_count()],
+            weight_sums: vec![0.0; splat_region.pixel_count()],
+            pixel_stats: vec![RunningStats::default(); tile.pixel_count()],
+        }
+    }
+
+    pub const fn tile(&self) -> &Tile {
+        &self.tile
+    }
+
+    #[allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_precision_loss,
+        clippy::cast_sign_loss
+    )]
+    pub fn add_sample(&mut self, x: usize, y: usize, offset: [f64; 2], color: Color) {
+        let index = (y - self.tile.y0()) * self.tile.width() + (x - self.tile.x0());
+        self.pixel_stats[index].push(color.luminance());
+
+        let region = self.splat_region;
+        let radius = self.filter.radius();
+        let sample_x = x as f64 + offset[0];
+        let sample_y = y as f64 + offset[1];
+
+        let x_min = (sample_x - radius).ceil().max(region.x0() as f64) as usize;
+        let x_max = (sample_x + radius).floor().min((region.x1() - 1) as f64) as usize;
+        let y_min = (sample_y - radius).ceil().max(region.y0() as f64) as usize;
+        let y_max = (sample_y + radius).floor().min((region.y1() - 1) as f64) as usize;
+
+        for pixel_y in y_min..=y_max {
+            for pixel_x in x_min..=x_max {
+                let weight = self
+                    .filter
+                    .evaluate(pixel_x as f64 - sample_x, pixel_y as f64 - sample_y);
+                if weight == 0.0 {
+                    continue;
+                }
+
+                let index = (pixel_y - region.y0()) * region.width() + (pixel_x - region.x0());
+                self.weighted_sums[index] += weight * color;
+                self.weight_sums[index] += weight;
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Film {
+    width: usize,
+    height: usize,
+    weighted_sums: Vec<Color>,
+    weight_sums: Vec<f64>,
+    pixel_stats: Vec<RunningStats>,
+}
+
+impl Film {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            weighted_sums: vec![Color::default(); width * height],
+            weight_sums: vec![0.0; width * height],
+            pixel_stats: vec![RunningStats::default(); width * height],
+        }
+    }
+
+    pub const fn width(&self) -> usize {
+        self.width
+    }
+
+    pub const fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn merge_tile(&mut self, buffer: &TileBuffer) {
+        let region = buffer.splat_region;
+        let splat_rows = buffer
+            .weighted_sums
+            .chunks_exact(region.width())
+            .zip(buffer.weight_sums.chunks_exact(region.width()));
+
+        for (row, (sums, weights)) in splat_rows.enumerate() {
+            let start = (region.y0() + row) * self.width + region.x0();
+            let film_sums = &mut self.weighted_sums[start..start + region.width()];
+            let film_weights = &mut self.weight_sums[start..start + region.width()];
+
+            for (film_sum, sum) in film_sums.iter_mut().zip(sums) {
+                *film_sum += *sum;
+            }
+            for (film_weight, weight) in film_weights.iter_mut().zip(weights) {
+                *film_weight += weight;
+            }
+        }
+
+        let tile = buffer.tile();
+        for (row, stats) in buffer.pixel_stats.chunks_exact(tile.width()).enumerate() {
+            let start = (tile.y0() + row) * self.width + tile.x0();
+            let film_stats = &mut self.pixel_stats[start..start + tile.width()];
+
+            for (film_stat, stat) in film_stats.iter_mut().zip(stats) {
+                film_stat.merge(stat);
+            }
+        }
+    }
+
+    pub fn pixel_stats(&self, x: usize, y: usize) -> &RunningStats {
+        &self.pixel_stats[y * self.width + x]
+    }
+
+    pub fn all_pixel_stats(&self) -> impl Iterator<Item = &RunningStats> {
+        self.pixel_stats.iter()
+    }
+
+    pub fn total_samples(&self) -> u64 {
+        self.pixel_stats
+            .iter()
+            .map(|stats| u64::from(stats.count()))
+            .sum()
+    }
+
+    pub fn average_samples_per_pixel(&self) -> f64 {
+        #![allow(clippy::cast_precision_loss)]
+        self.total_samples() as f64 / self.pixel_stats.len() as f64
+    }
+
+    pub fn max_relative_error(&self, min_samples: u32) -> f64 {
+        self.pixel_stats
+            .iter()
+            .map(|stats| {
+                if stats.count() < min_samples {
+                    f64::INFINITY
+                } else {
+                    stats.relative_error()
+                }
+            })
+            .fold(0.0, f64::max)
+    }
+
+    // Filters with negative lobes can leave a pixel that received no samples of its own with
+    // weights that nearly cancel, and dividing by them would turn it into a firefly. Such pixels
+    // are left black.
+    fn pixel_color(&self, index: usize) -> Color {
+        let weight = self.weight_sums[index];
+        if weight > MIN_WEIGHT_SUM {
+            weight.recip() * self.weighted_sums[index]
+        } else {
+            Color::default()
+        }
+    }
+
+    pub fn write_ppm(&self, mut out: impl Write) -> io::Result<()> {
+        // PPM header
+        // First line indicates colors are in ASCII
+        // Second line indicates the width and height of the image
+        // Third line indicates the maximum color value
+        writeln!(out, "P3")?;
+        writeln!(out, "{} {}", self.width, self.height)?;
+        writeln!(out, "255")?;
+
+        for index in 0..self.width * self.height {
+            writeln!(out, "{}", self.pixel_color(index))?;
+        }
+
+        out.flush()
+    }
+
+    // The image is written next to the destination and renamed over it, so a viewer never sees a
+    // partially written snapshot
+    pub fn write_ppm_file(&self, path: &Path) -> io::Result<()> {
+        let temp_path = path.with_extension("tmp");
+        self.write_ppm(BufWriter::new(File::create(&temp_path)?))?;
+        fs::rename(&temp_path, path)
+    }
+
+    // Debug view of the sample distribution, with brightness proportional to each pixel's sample
+    // count relative to the most sampled pixel
+    pub fn write_sample_count_ppm_file(&self, path: &Path) -> io::Result<()> {
+        let max_count = self
+            .pixel_stats
+            .iter()
+            .map(RunningStats::count)
+            .max()
+            .unwrap_or(0)
+            .max(1);
+
+        let mut out = BufWriter::new(File::create(path)?);
+        writeln!(out, "P3")?;
+        writeln!(out, "{} {}", self.width, self.height)?;
+        writeln!(out, "255")?;
+
+        for stats in &self.pixel_stats {
+            let level = u64::from(stats.count()) * 255 / u64::from(max_count);
+            writeln!(out, "{level} {level} {level}")?;
+        }
+
+        out.flush()
+    }
+
+    pub fn write_binary(&self, out: &mut impl Write) -> io::Result<()> {
+        out.write_all(&(self.width as u64).to_le_bytes())?;
+        out.write_all(&(self.height as u64).to_le_bytes())?;
+
+        let pixels = self
+            .weighted_sums
+            .iter()
+            .zip(&self.weight_sums)
+            .zip(&self.pixel_stats);
+        for ((sum, weight), stats) in pixels {
+            for component in Vec3::from(*sum).components {
+                out.write_all(&component.to_le_bytes())?;
+            }
+            out.write_all(&weight.to_le_bytes())?;
+            out.write_all(&stats.count().to_le_bytes())?;
+            out.write_all(&stats.mean().to_le_bytes())?;
+            out.write_all(&stats.m2().to_le_bytes())?;
+        }
+
+        Ok(())
+    }
+
+    // Reads a film written by `write_binary`, which must be `width` by `height`. The size is checked
+    // before anything is allocated, so a corrupt file can't request an enormous film.
+    pub fn read_binary(input: &mut impl Read, width: usize, height: usize) -> io::Result<Self> {
+        let (saved_width, saved_height) = (read_u64(input)?, read_u64(input)?);
+        if saved_width != width as u64 || saved_height != height as u64 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "Checkpoint image size {saved_width}x{saved_height} does not match render size {width}x{height}"
+                ),
+            ));
+        }
+
+        let mut film = Self::new(width, height);
+        let pixels = film
+            .weighted_sums
+            .iter_mut()
+            .zip(&mut film.weight_sums)
+            .zip(&mut film.pixel_stats);
+        for ((sum, weight), stats) in pixels {
+            let (r, g, b) = (read_f64(input)?, read_f64(input)?, read_f64(input)?);
+            *sum = Color::new(r, g, b);
+            *weight = read_f64(input)?;
+            *stats = RunningStats::new(read_u32(input)?, read_f64(input)?, read_f64(input)?);
+        }
+
+        Ok(film)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        filter::{BoxFilter, GaussianFilter, LanczosFilter, MitchellNetravaliFilter, TentFilter},
+        tile::{TileOrder, generate_tiles},
+    };
+    use rand::{RngExt, SeedableRng, rngs::Xoshiro256PlusPlus};
+
+    const WIDTH: usize = 13;
+    const HEIGHT: usize = 9;
+
+    // Splats `samples` samples of `color` into every pixel, tile by tile
+    fn splat_constant(filter: Filter, samples: u32, color: Color) -> Film {
+        let mut rng = Xoshiro256PlusPlus::seed_from_u64(11);
+        let mut film = Film::new(WIDTH, HEIGHT);
+        for tile in generate_tiles(WIDTH, HEIGHT, 4, TileOrder::Scanline) {
+            let mut buffer = TileBuffer::new(tile, filter, WIDTH, HEIGHT);
+            for (x, y) in tile.pixels() {
+                for _ in 0..samples {
+                    buffer.add_sample(
+                        x,
+                        y,
+                        [rng.random::<f64>() - 0.5, rng.random::<f64>() - 0.5],
+                        color,
+                    );
+                }
+            }
+            film.merge_tile(&buffer);
+        }
+        film
+    }
+
+    // Every pixel is a weighted average of the same color, whatever the signs of the weights
+    #[test]
+    fn constant_images_stay_constant() {
+        let color = Color::new(0.2, 0.5, 0.9);
+        for filter in [
+            Filter::from(BoxFilter::default()),
+            Filter::from(TentFilter::default()),
+            Filter::from(GaussianFilter::default()),
+            Filter::from(MitchellNetravaliFilter::default()),
+            Filter::from(LanczosFilter::default()),
+        ] {
+            for samples in [1, 4] {
+                let film = splat_constant(filter, samples, color);
+                for index in 0..WIDTH * HEIGHT {
+                    let pixel = Vec3::from(film.pixel_color(index));
+                    assert!(
+                        (pixel - Vec3::from(color)).length() < 1e-12,
+                        "{filter:?}, pixel {index}: {pixel:?}"
+                    );
+                }
+            }
+        }
+    }
+
+    // A pixel outside the single sample's positive lobe is left black instead of being divided by
+    // a tiny or negative weight
+    #[test]
+    fn pixels_with_cancelled_weights_stay_black() {
+        let filter = Filter::from(MitchellNetravaliFilter::default());
+        let mut film = Film::new(WIDTH, HEIGHT);
+        let mut buffer = TileBuffer::new(Tile::new(0, 0, WIDTH, HEIGHT), filter, WIDTH, HEIGHT);
+        buffer.add_sample(6, 4, [0.2, -0.1], Color::new(1.0, 1.0, 1.0));
+        film.merge_tile(&buffer);
+
+        for (index, &weight) in film.weight_sums.iter().enumerate() {
+            let pixel = film.pixel_color(index);
+            if weight <= MIN_WEIGHT_SUM {
+                assert!(pixel.is_black(), "pixel {index}: {pixel:?}");
+            } else {
+                assert!(
+                    pixel.max_component() <= 1.0 + 1e-12,
+                    "pixel {index}: {pixel:?}"
+                );
+            }
+        }
+
+        // Weights that nearly cancel, as the lobes of samples on either side of a pixel can
+        film.weight_sums[0] = 1e-6;
+        film.weighted_sums[0] = Color::new(1e-4, 1e-4, 1e-4);
+        assert!(film.pixel_color(0).is_black());
+    }
+}
@@ -0,0 +1,312 @@
+use enum_dispatch::enum_dispatch;
+use std::f64::consts::PI;
+
+// Filters are separable, so they are defined by their one dimensional profile. Profiles integrate to
+// 1, which the film relies on to tell weight sums that cancelled out from ones that are just small.
+#[enum_dispatch(Filter)]
+pub trait ReconstructionFilter {
+    fn radius(&self) -> f64;
+    fn evaluate_1d(&self, x: f64) -> f64;
+
+    fn evaluate(&self, x: f64, y: f64) -> f64 {
+        self.evaluate_1d(x) * self.evaluate_1d(y)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BoxFilter {
+    radius: f64,
+}
+
+impl BoxFilter {
+    pub const fn new(radius: f64) -> Self {
+        Self { radius }
+    }
+}
+
+impl Default for BoxFilter {
+    fn default() -> Self {
+        Self::new(0.5)
+    }
+}
+
+impl ReconstructionFilter for BoxFilter {
+    fn radius(&self) -> f64 {
+        self.radius
+    }
+
+    fn evaluate_1d(&self, x: f64) -> f64 {
+        if x.abs() < self.radius {
+            0.5 / self.radius
+        } else {
+            0.0
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TentFilter {
+    radius: f64,
+}
+
+impl TentFilter {
+    pub const fn new(radius: f64) -> Self {
+        Self { radius }
+    }
+}
+
+impl Default for TentFilter {
+    fn default() -> Self {
+        Self::new(1.0)
+    }
+}
+
+impl ReconstructionFilter for TentFilter {
+    fn radius(&self) -> f64 {
+        self.radius
+    }
+
+    fn evaluate_1d(&self, x: f64) -> f64 {
+        (self.radius - x.abs()).max(0.0) / (self.radius * self.radius)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GaussianFilter {
+    radius: f64,
+    sigma: f64,
+    scale: f64,
+}
+
+impl GaussianFilter {
+    pub fn new(radius: f64, sigma: f64) -> Self {
+        let mut filter = Self {
+            radius,
+            sigma,
+            scale: 1.0,
+        };
+        filter.scale = normalization(&filter);
+        filter
+    }
+
+    fn gaussian(&self, x: f64) -> f64 {
+        (-0.5 * (x / self.sigma).powi(2)).exp()
+    }
+}
+
+impl Default for GaussianFilter {
+    fn default() -> Self {
+        Self::new(1.5, 0.5)
+    }
+}
+
+impl ReconstructionFilter for GaussianFilter {
+    fn radius(&self) -> f64 {
+        self.radius
+    }
+
+    // Shifted down so the filter falls off to zero at its radius instead of being cut off
+    fn evaluate_1d(&self, x: f64) -> f64 {
+        self.scale * (self.gaussian(x) - self.gaussian(self.radius)).max(0.0)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MitchellNetravaliFilter {
+    radius: f64,
+    b: f64,
+    c: f64,
+}
+
+impl MitchellNetravaliFilter {
+    pub const fn new(radius: f64, b: f64, c: f64) -> Self {
+        Self { radius, b, c }
+    }
+}
+
+impl Default for MitchellNetravaliFilter {
+    fn default() -> Self {
+        Self::new(2.0, 1.0 / 3.0, 1.0 / 3.0)
+    }
+}
+
+impl ReconstructionFilter for MitchellNetravaliFilter {
+    fn radius(&self) -> f64 {
+        self.radius
+    }
+
+    // The cubic is defined over [-2, 2], where it integrates to 1, so the offset is rescaled to the
+    // filter radius and the value by its inverse
+    fn evaluate_1d(&self, x: f64) -> f64 {
+        let (b, c) = (self.b, self.c);
+        let x = (2.0 * x / self.radius).abs();
+
+        let value = if x >= 2.0 {
+            0.0
+        } else if x > 1.0 {
+            6.0f64.mul_add(-c, -b).mul_add(
+                x.powi(3),
+                6.0f64.mul_add(b, 30.0 * c).mul_add(
+                    x * x,
+                    (-12.0f64)
+                        .mul_add(b, -48.0 * c)
+                        .mul_add(x, 8.0f64.mul_add(b, 24.0 * c)),
+                ),
+            )
+        } else {
+            (-6.0f64).mul_add(c, (-9.0f64).mul_add(b, 12.0)).mul_add(
+                x.powi(3),
+                6.0f64
+                    .mul_add(c, 12.0f64.mul_add(b, -18.0))
+                    .mul_add(x * x, (-2.0f64).mul_add(b, 6.0)),
+            )
+        };
+
+        value / (3.0 * self.radius)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LanczosFilter {
+    radius: f64,
+    tau: f64,
+    scale: f64,
+}
+
+impl LanczosFilter {
+    pub fn new(radius: f64, tau: f64) -> Self {
+        let mut filter = Self {
+            radius,
+            tau,
+            scale: 1.0,
+        };
+        filter.scale = normalization(&filter);
+        filter
+    }
+}
+
+impl Default for LanczosFilter {
+    fn default() -> Self {
+        Self::new(3.0, 3.0)
+    }
+}
+
+impl ReconstructionFilter for LanczosFilter {
+    fn radius(&self) -> f64 {
+        self.radius
+    }
+
+    fn evaluate_1d(&self, x: f64) -> f64 {
+        if x.abs() >= self.radius {
+            0.0
+        } else {
+            self.scale * sinc(x) * sinc(x / self.tau)
+        }
+    }
+}
+
+// The factor that makes a profile without a closed form integral integrate to 1, by the midpoint
+// rule
+fn normalization(filter: &impl ReconstructionFilter) -> f64 {
+    const STEPS: u32 = 1024;
+
+    let step = 2.0 * filter.radius() / f64::from(STEPS);
+    let integral: f64 = (0..STEPS)
+        .map(|i| filter.evaluate_1d((f64::from(i) + 0.5).mul_add(step, -filter.radius())))
+        .sum();
+    (integral * step).recip()
+}
+
+fn sinc(x: f64) -> f64 {
+    if x.abs() < 1.0e-5 {
+        1.0
+    } else {
+        (PI * x).sin() / (PI * x)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[enum_dispatch]
+pub enum Filter {
+    Box(BoxFilter),
+    Tent(TentFilter),
+    Gaussian(GaussianFilter),
+    MitchellNetravali(MitchellNetravaliFilter),
+    Lanczos(LanczosFilter),
+}
+
+impl Default for Filter {
+    fn default() -> Self {
+        Self::Box(BoxFilter::default())
+    }
+}
+
+impl Filter {
+    // A tag for the kind of filter followed by its parameters, as stored in checkpoints
+    pub const fn to_parameters(self) -> (u8, [f64; 3]) {
+        match self {
+            Self::Box(filter) => (0, [filter.radius, 0.0, 0.0]),
+            Self::Tent(filter) => (1, [filter.radius, 0.0, 0.0]),
+            Self::Gaussian(filter) => (2, [filter.radius, filter.sigma, 0.0]),
+            Self::MitchellNetravali(filter) => (3, [filter.radius, filter.b, filter.c]),
+            Self::Lanczos(filter) => (4, [filter.radius, filter.tau, 0.0]),
+        }
+    }
+
+    pub fn from_parameters(tag: u8, [a, b, c]: [f64; 3]) -> Option<Self> {
+        Some(match tag {
+            0 => Self::Box(BoxFilter::new(a)),
+            1 => Self::Tent(TentFilter::new(a)),
+            2 => Self::Gaussian(GaussianFilter::new(a, b)),
+            3 => Self::MitchellNetravali(MitchellNetravaliFilter::new(a, b, c)),
+            4 => Self::Lanczos(LanczosFilter::new(a, b)),
+            _ => return None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STEPS: u32 = 400;
+
+    fn filters() -> [Filter; 9] {
+        [
+            Filter::from(BoxFilter::default()),
+            Filter::from(BoxFilter::new(1.5)),
+            Filter::from(TentFilter::default()),
+            Filter::from(TentFilter::new(2.0)),
+            Filter::from(GaussianFilter::default()),
+            Filter::from(MitchellNetravaliFilter::default()),
+            Filter::from(MitchellNetravaliFilter::new(1.5, 1.0, 0.0)),
+            Filter::from(LanczosFilter::default()),
+            Filter::from(LanczosFilter::new(2.0, 2.0)),
+        ]
+    }
+
+    #[test]
+    fn filters_integrate_to_one() {
+        for filter in filters() {
+            let radius = filter.radius();
+            let step = 2.0 * radius / f64::from(STEPS);
+            let mut integral = 0.0;
+            for i in 0..STEPS {
+                let x = (f64::from(i) + 0.5).mul_add(step, -radius);
+                for j in 0..STEPS {
+                    let y = (f64::from(j) + 0.5).mul_add(step, -radius);
+                    integral += filter.evaluate(x, y) * step * step;
+                }
+            }
+            assert!((integral - 1.0).abs() < 1e-2, "{filter:?}: {integral}");
+        }
+    }
+
+    #[test]
+    fn filters_round_trip_through_parameters() {
+        for filter in filters() {
+            let (tag, parameters) = filter.to_parameters();
+            assert_eq!(Filter::from_parameters(tag, parameters), Some(filter));
+        }
+    }
+}
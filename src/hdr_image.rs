@@ -0,0 +1,314 @@
+use crate::color::Color;
+use exr::prelude::traits::{ReadChannels, ReadLayers, read};
+use std::{
+    fs,
+    io::{self, Cursor},
+    path::Path,
+};
+
+const EXR_MAGIC: [u8; 4] = [0x76, 0x2f, 0x31, 0x01];
+
+// Twice a 16K by 8K environment map. Larger sizes in a header are taken to be corrupt rather than
+// allocated.
+const MAX_PIXELS: usize = 1 << 28;
+
+// A floating point RGB image, stored row by row from the top
+#[derive(Clone, Debug)]
+pub struct HdrImage {
+    width: usize,
+    height: usize,
+    pixels: Vec<Color>,
+}
+
+impl HdrImage {
+    pub fn new(width: usize, height: usize, pixels: Vec<Color>) -> Self {
+        assert_eq!(pixels.len(), width * height, "Pixel count must match size");
+        Self {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    pub const fn width(&self) -> usize {
+        self.width
+    }
+
+    pub const fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> Color {
+        self.pixels[y * self.width + x]
+    }
+
+    // Reads an OpenEXR (.exr) or Radiance RGBE (.hdr) file, told apart by their first bytes
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+        fs::read(path)
+            .and_then(|data| {
+                if data.starts_with(&EXR_MAGIC) {
+                    Self::parse_exr(&data)
+                } else {
+                    Self::parse_rgbe(&data)
+                }
+            })
+            .map_err(|e| io::Error::new(e.kind(), format!("{}: {e}", path.display())))
+    }
+
+    // The first layer with red, green and blue channels, at its largest resolution
+    fn parse_exr(data: &[u8]) -> io::Result<Self> {
+        let image = read()
+            .no_deep_data()
+            .largest_resolution_level()
+            .rgba_channels(
+                |resolution, _| {
+                    let (width, height) = (resolution.width(), resolution.height());
+                    Self::new(width, height, vec![Color::default(); width * height])
+                },
+                |image: &mut Self, position, (r, g, b, _): (f32, f32, f32, f32)| {
+                    image.pixels[position.y() * image.width + position.x()] =
+                        Color::new(f64::from(r), f64::from(g), f64::from(b));
+                },
+            )
+            .first_valid_layer()
+            .all_attributes()
+            .from_buffered(Cursor::new(data))
+            .map_err(|e| invalid_data(format!("Invalid OpenEXR file: {e}")))?;
+
+        Ok(image.layer_data.channel_data.pixels)
+    }
+
+    // Radiance RGBE, with flat or run-length encoded scanlines
+    fn parse_rgbe(data: &[u8]) -> io::Result<Self> {
+        let mut reader = ByteReader { data, position: 0 };
+
+        let magic = reader.read_line()?;
+        if !magic.starts_with("#?") {
+            return Err(invalid_data("Not an OpenEXR or Radiance HDR file"));
+        }
+
+        loop {
+            let line = reader.read_line()?;
+            if line.is_empty() {
+                break;
+            }
+            if let Some(format) = line.strip_prefix("FORMAT=")
+                && format != "32-bit_rle_rgbe"
+            {
+                return Err(invalid_data(format!("Unsupported pixel format {format}")));
+            }
+        }
+
+        // Only the standard orientation, rows from the top with pixels from the left, is supported
+        let resolution = reader.read_line()?;
+        let (height, width) = match resolution.split_whitespace().collect::<Vec<_>>()[..] {
+            ["-Y", height, "+X", width] => (parse_dimension(height)?, parse_dimension(width)?),
+            _ => {
+                return Err(invalid_data(format!(
+                    "Unsupported resolution line {resolution}"
+                )));
+            }
+        };
+
+        let pixel_count = width
+            .checked_mul(height)
+            .filter(|&count| count <= MAX_PIXELS)
+            .ok_or_else(|| invalid_data(format!("Image size {width}x{height} is too large")))?;
+
+        let mut pixels = Vec::with_capacity(pixel_count);
+        let mut scanline = vec![[0u8; 4]; width];
+        for _ in 0..height {
+            reader.read_scanline(&mut scanline)?;
+            pixels.extend(scanline.iter().map(|&rgbe| rgbe_to_color(rgbe)));
+        }
+
+        Ok(Self::new(width, height, pixels))
+    }
+}
+
+struct ByteReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl ByteReader<'_> {
+    fn read_byte(&mut self) -> io::Result<u8> {
+        let byte = *self
+            .data
+            .get(self.position)
+            .ok_or_else(|| invalid_data("Unexpected end of file"))?;
+        self.position += 1;
+        Ok(byte)
+    }
+
+    fn read_line(&mut self) -> io::Result<String> {
+        let mut line = Vec::new();
+        loop {
+            match self.read_byte()? {
+                b'\n' => break,
+                byte => line.push(byte),
+            }
+        }
+        String::from_utf8(line).map_err(|_| invalid_data("Header is not valid text"))
+    }
+
+    fn read_scanline(&mut self, scanline: &mut [[u8; 4]]) -> io::Result<()> {
+        let mut first = [0u8; 4];
+        for byte in &mut first {
+            *byte = self.read_byte()?;
+        }
+
+        // Run-length encoded scanlines start with 2, 2 and the width, then store each channel
+        // separately
+        let width = scanline.len();
+        let encoded_width = usize::from(first[2]) << 8 | usize::from(first[3]);
+        if !(8..0x8000).contains(&width) || first[0] != 2 || first[1] != 2 || first[2] & 0x80 != 0 {
+            scanline[0] = first;
+            for pixel in &mut scanline[1..] {
+                for byte in pixel {
+                    *byte = self.read_byte()?;
+                }
+            }
+            return Ok(());
+        }
+        if encoded_width != width {
+            return Err(invalid_data("Scanline width does not match image width"));
+        }
+
+        for channel in 0..4 {
+            let mut x = 0;
+            while x < width {
+                let count = self.read_byte()?;
+                let (run, repeated) = if count > 128 {
+                    (usize::from(count - 128), true)
+                } else {
+                    (usize::from(count), false)
+                };
+                if run == 0 || x + run > width {
+                    return Err(invalid_data("Bad run length in scanline"));
+                }
+
+                if repeated {
+                    let value = self.read_byte()?;
+                    for pixel in &mut scanline[x..x + run] {
+                        pixel[channel] = value;
+                    }
+                } else {
+                    for pixel in &mut scanline[x..x + run] {
+                        pixel[channel] = self.read_byte()?;
+                    }
+                }
+                x += run;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// Each channel is a mantissa sharing the exponent in the last byte
+fn rgbe_to_color([r, g, b, e]: [u8; 4]) -> Color {
+    if e == 0 {
+        return Color::default();
+    }
+
+    let scale = 2.0f64.powi(i32::from(e) - (128 + 8));
+    Color::new(
+        (f64::from(r) + 0.5) * scale,
+        (f64::from(g) + 0.5) * scale,
+        (f64::from(b) + 0.5) * scale,
+    )
+}
+
+fn parse_dimension(value: &str) -> io::Result<usize> {
+    value
+        .parse()
+        .ok()
+        .filter(|&dimension| dimension > 0)
+        .ok_or_else(|| invalid_data(format!("Invalid image dimension {value}")))
+}
+
+fn invalid_data(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEADER: &[u8] = b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n";
+
+    // RGBE values are exact multiples of powers of two, so decoding has no rounding error
+    #[allow(clippy::float_cmp)]
+    fn assert_color(color: Color, expected: [f64; 3]) {
+        assert_eq!([color.r(), color.g(), color.b()], expected);
+    }
+
+    #[test]
+    fn decodes_rgbe_pixels() {
+        assert_color(
+            rgbe_to_color([128, 64, 32, 129]),
+            [1.003_906_25, 0.503_906_25, 0.253_906_25],
+        );
+        assert_color(
+            rgbe_to_color([255, 0, 0, 128]),
+            [0.998_046_875, 0.001_953_125, 0.001_953_125],
+        );
+        assert_color(rgbe_to_color([200, 100, 50, 0]), [0.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn reads_flat_scanlines() {
+        let data = [HEADER, b"-Y 1 +X 2\n", &[128, 64, 32, 129, 0, 0, 0, 0]].concat();
+        let image = HdrImage::parse_rgbe(&data).unwrap();
+        assert_eq!((image.width(), image.height()), (2, 1));
+        assert_color(
+            image.pixel(0, 0),
+            [1.003_906_25, 0.503_906_25, 0.253_906_25],
+        );
+        assert_color(image.pixel(1, 0), [0.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn reads_run_length_encoded_scanlines() {
+        // One row of 8 pixels: red as a literal run, the other channels as repeated runs
+        let scanline = [
+            &[2, 2, 0, 8][..],
+            &[8, 128, 128, 128, 128, 255, 255, 255, 255],
+            &[128 + 8, 64],
+            &[128 + 8, 32],
+            &[128 + 8, 129],
+        ]
+        .concat();
+        let data = [HEADER, b"-Y 1 +X 8\n", &scanline].concat();
+        let image = HdrImage::parse_rgbe(&data).unwrap();
+        assert_eq!((image.width(), image.height()), (8, 1));
+        assert_color(
+            image.pixel(0, 0),
+            [1.003_906_25, 0.503_906_25, 0.253_906_25],
+        );
+        assert_color(
+            image.pixel(7, 0),
+            [1.996_093_75, 0.503_906_25, 0.253_906_25],
+        );
+    }
+
+    #[test]
+    fn rejects_truncated_files() {
+        let data = [HEADER, b"-Y 2 +X 2\n", &[128, 64, 32, 129]].concat();
+        assert!(HdrImage::parse_rgbe(&data).is_err());
+    }
+
+    #[test]
+    fn rejects_huge_sizes() {
+        for resolution in [
+            &b"-Y 65536 +X 65536\n"[..],
+            b"-Y 18446744073709551615 +X 2\n",
+        ] {
+            let data = [HEADER, resolution, &[128, 64, 32, 129]].concat();
+            let error = HdrImage::parse_rgbe(&data).unwrap_err();
+            assert!(error.to_string().contains("too large"), "{error}");
+        }
+    }
+}
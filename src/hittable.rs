@@ -1,20 +1,26 @@
 use crate::{
     aabb::AxisAlignedBoundingBox,
-    interval::Interval,
-    material::Material,
+    interval::{self, Interval},
+    material::{Material, Scatter},
     ray::Ray,
-    vec3::{Point3, Vec3},
+    sampler::{SampleGenerator, Sampler, mix},
+    vec3::{Onb, Point3, Vec3, sample_uniform_cone, uniform_cone_pdf},
 };
 use enum_dispatch::enum_dispatch;
-use std::{fmt::Debug, sync::Arc};
+use std::{f64::consts::PI, fmt::Debug, sync::Arc};
 
 #[derive(Clone, Debug, Default)]
 pub struct HitRecord {
     p: Point3,
     normal: Vec3,
+    shading_normal: Vec3,
+    tangent: Vec3,
+    bitangent: Vec3,
     mat: Arc<Material>,
     t: f64,
+    uv: [f64; 2],
     front_face: bool,
+    wavelength: Option<f64>,
 }
 
 impl HitRecord {
@@ -25,6 +31,17 @@ impl HitRecord {
         } else {
             -outward_normal
         };
+        self.shading_normal = self.normal;
+    }
+
+    // Replaces the shading normal, given on the outward side of the surface like the one passed to
+    // `set_face_normal`
+    pub fn set_shading_normal(&mut self, outward_normal: &Vec3) {
+        self.shading_normal = if self.front_face {
+            *outward_normal
+        } else {
+            -outward_normal
+        };
     }
 
     pub const fn p(&self) -> &Point3 {
@@ -35,10 +52,49 @@ impl HitRecord {
         self.t
     }
 
+    // Surface coordinates of the hit point, each in [0, 1]
+    pub const fn uv(&self) -> [f64; 2] {
+        self.uv
+    }
+
+    // The geometric normal, facing the incoming ray
     pub const fn normal(&self) -> &Vec3 {
         &self.normal
     }
 
+    // The normal materials scatter about, which normal and bump maps may tilt away from the
+    // geometric normal. It faces the same side of the surface.
+    pub const fn shading_normal(&self) -> &Vec3 {
+        &self.shading_normal
+    }
+
+    // Derivatives of the hit point along the surface coordinates u and v. They aren't normalized,
+    // so they also tell how far a step in the coordinates moves across the surface.
+    pub const fn tangent(&self) -> &Vec3 {
+        &self.tangent
+    }
+
+    pub const fn bitangent(&self) -> &Vec3 {
+        &self.bitangent
+    }
+
+    // An orthonormal frame around the shading normal with its first axis along the tangent
+    pub fn shading_frame(&self) -> Onb {
+        self.tangent_frame(&self.shading_normal)
+    }
+
+    // An orthonormal frame around the unit vector `normal` with its first axis along the tangent,
+    // falling back to an arbitrary frame where the tangent is degenerate
+    pub fn tangent_frame(&self, normal: &Vec3) -> Onb {
+        let tangent = self.tangent - self.tangent.dot(normal) * normal;
+        if tangent.length_squared() < 1e-16 {
+            return Onb::new(normal);
+        }
+
+        let u = tangent.unit_vector();
+        Onb::from_axes(u, normal.cross(&u), *normal)
+    }
+
     pub const fn material(&self) -> &Arc<Material> {
         &self.mat
     }
@@ -46,12 +102,51 @@ impl HitRecord {
     pub const fn front_face(&self) -> bool {
         self.front_face
     }
+
+    // The hero wavelength in nm of the path that made the hit, when rendering spectrally
+    pub const fn wavelength(&self) -> Option<f64> {
+        self.wavelength
+    }
+
+    pub const fn set_wavelength(&mut self, wavelength: Option<f64>) {
+        self.wavelength = wavelength;
+    }
+}
+
+// Alpha masks cut holes in surfaces. Hits where the material's alpha is 0 are ignored, so the ray
+// carries on to whatever is behind, and partly covered hits are kept with probability alpha. The
+// choice hashes the ray and the hit point rather than drawing a sample, so it stays the same each
+// time the ray is traced.
+#[allow(clippy::cast_precision_loss)]
+fn is_cut_out(mat: &Material, r: &Ray, uv: [f64; 2], p: &Point3) -> bool {
+    let alpha = mat.alpha(uv, p);
+    if alpha >= 1.0 {
+        return false;
+    }
+    if alpha <= 0.0 {
+        return true;
+    }
+
+    let hash = [r.dir().x(), r.dir().y(), r.dir().z(), p.x(), p.y(), p.z()]
+        .iter()
+        .fold(0, |hash, value| mix(hash ^ value.to_bits()));
+    (hash >> 11) as f64 / (1u64 << 53) as f64 >= alpha
 }
 
 #[enum_dispatch(Hittable)]
 pub trait RayIntersection: Debug + Send + Sync {
     fn hit(&self, r: &Ray, ray_t: Interval, hit_record: &mut HitRecord) -> bool;
     fn bounding_box(&self) -> AxisAlignedBoundingBox;
+
+    // Solid angle density, as seen from the origin of `r` at its time, of the directions `random`
+    // generates. Only objects used as lights need to support sampling.
+    fn pdf_value(&self, _r: &Ray) -> f64 {
+        0.0
+    }
+
+    fn random(&self, _origin: &Point3, _time: f64, _sampler: &mut Sampler) -> Vec3 {
+        Vec3::new(1.0, 0.0, 0.0)
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -112,6 +207,34 @@ impl Sphere {
             AxisAlignedBoundingBox::from_points(&(center - radius_vec), &(center + radius_vec)),
         )
     }
+
+    // Longitude from -x around through +z, and latitude from the bottom, of a point on the unit
+    // sphere
+    fn uv(p: &Vec3) -> [f64; 2] {
+        let theta = (-p.y()).clamp(-1.0, 1.0).acos();
+        let phi = (-p.z()).atan2(p.x()) + PI;
+        [phi / (2.0 * PI), theta / PI]
+    }
+
+    // Derivatives of the point with `uv` above along u and v. The u tangent vanishes at the poles.
+    fn tangents(&self, p: &Vec3) -> (Vec3, Vec3) {
+        let (x, y, z) = (p.x(), p.y(), p.z());
+        let rho = x.hypot(z);
+        let tangent = 2.0 * PI * self.radius * Vec3::new(z, 0.0, -x);
+        if rho < 1e-12 {
+            return (tangent, Vec3::new(PI * self.radius, 0.0, 0.0));
+        }
+
+        let bitangent = PI * self.radius * Vec3::new(-x * y / rho, rho, -y * z / rho);
+        (tangent, bitangent)
+    }
+
+    // Half angle of the cone the sphere subtends from a point at the given squared distance
+    fn cos_theta_max(&self, distance_squared: f64) -> f64 {
+        (1.0 - self.radius * self.radius / distance_squared)
+            .max(0.0)
+            .sqrt()
+    }
 }
 
 impl RayIntersection for Sphere {
@@ -127,20 +250,259 @@ impl RayIntersection for Sphere {
             return false;
         }
 
+        // The far root is the nearest hit when the ray starts inside or the near one is cut away
         let sqrt_d = discriminant.sqrt();
-
-        let mut root = (h - sqrt_d) / a;
-        if !ray_t.surrounds(root) {
-            root = (h + sqrt_d) / a;
+        for root in [(h - sqrt_d) / a, (h + sqrt_d) / a] {
             if !ray_t.surrounds(root) {
-                return false;
+                continue;
+            }
+
+            let point = r.at(root);
+            let outward_normal = (point - current_center) / self.radius;
+            let uv = Self::uv(&outward_normal);
+            if is_cut_out(&self.mat, r, uv, &point) {
+                continue;
             }
+
+            hit_record.t = root;
+            hit_record.p = point;
+            hit_record.set_face_normal(r, &outward_normal);
+            hit_record.uv = uv;
+            (hit_record.tangent, hit_record.bitangent) = self.tangents(&outward_normal);
+            hit_record.mat = self.mat.clone();
+            return true;
+        }
+
+        false
+    }
+
+    fn bounding_box(&self) -> AxisAlignedBoundingBox {
+        self.bbox
+    }
+
+    // Sphere lights are sampled uniformly within the cone of directions they subtend, which wastes no
+    // samples on the far side of the sphere
+    fn pdf_value(&self, r: &Ray) -> f64 {
+        let mut rec = HitRecord::default();
+        if !self.hit(r, interval::ERROR_CORRECTED_NON_NEGATIVE, &mut rec) {
+            return 0.0;
+        }
+
+        let distance_squared = (self.center.at(r.time()) - r.origin()).length_squared();
+        uniform_cone_pdf(self.cos_theta_max(distance_squared))
+    }
+
+    fn random(&self, origin: &Point3, time: f64, sampler: &mut Sampler) -> Vec3 {
+        let direction = self.center.at(time) - origin;
+        let cos_theta_max = self.cos_theta_max(direction.length_squared());
+
+        Onb::new(&direction.unit_vector())
+            .local_to_world(&sample_uniform_cone(sampler.get_2d(), cos_theta_max))
+    }
+}
+
+// A parallelogram spanned by the edges `u` and `v` from the corner `q`
+#[derive(Clone, Debug)]
+pub struct Quad {
+    q: Point3,
+    u: Vec3,
+    v: Vec3,
+    w: Vec3,
+    normal: Vec3,
+    d: f64,
+    area: f64,
+    mat: Arc<Material>,
+    bbox: AxisAlignedBoundingBox,
+}
+
+impl Quad {
+    pub fn new(q: &Point3, u: &Vec3, v: &Vec3, mat: &Arc<Material>) -> Self {
+        let n = u.cross(v);
+        let normal = n.unit_vector();
+
+        let diagonal_1 = AxisAlignedBoundingBox::from_points(q, &(q + u + v));
+        let diagonal_2 = AxisAlignedBoundingBox::from_points(&(q + u), &(q + v));
+
+        Self {
+            q: *q,
+            u: *u,
+            v: *v,
+            w: n / n.length_squared(),
+            normal,
+            d: normal.dot(q),
+            area: n.length(),
+            mat: mat.clone(),
+            bbox: AxisAlignedBoundingBox::merge_boxes(&diagonal_1, &diagonal_2).pad_to_minimums(),
+        }
+    }
+}
+
+impl RayIntersection for Quad {
+    fn hit(&self, r: &Ray, ray_t: Interval, hit_record: &mut HitRecord) -> bool {
+        let denominator = self.normal.dot(r.dir());
+
+        // Rays parallel to the plane never hit it
+        if denominator.abs() < 1e-8 {
+            return false;
+        }
+
+        let t = (self.d - self.normal.dot(r.origin())) / denominator;
+        if !ray_t.surrounds(t) {
+            return false;
+        }
+
+        // Planar coordinates of the hit point along the edges
+        let p = r.at(t);
+        let planar = p - self.q;
+        let alpha = self.w.dot(&planar.cross(&self.v));
+        let beta = self.w.dot(&self.u.cross(&planar));
+        if !(0.0..=1.0).contains(&alpha) || !(0.0..=1.0).contains(&beta) {
+            return false;
+        }
+        if is_cut_out(&self.mat, r, [alpha, beta], &p) {
+            return false;
+        }
+
+        hit_record.t = t;
+        hit_record.p = p;
+        hit_record.uv = [alpha, beta];
+        hit_record.set_face_normal(r, &self.normal);
+        hit_record.tangent = self.u;
+        hit_record.bitangent = self.v;
+        hit_record.mat = self.mat.clone();
+
+        true
+    }
+
+    fn bounding_box(&self) -> AxisAlignedBoundingBox {
+        self.bbox
+    }
+
+    // Points are sampled uniformly by area, so the density is converted to solid angle
+    fn pdf_value(&self, r: &Ray) -> f64 {
+        let mut rec = HitRecord::default();
+        if !self.hit(r, interval::ERROR_CORRECTED_NON_NEGATIVE, &mut rec) {
+            return 0.0;
         }
 
-        hit_record.t = root;
-        hit_record.p = r.at(root);
-        let outward_normal = (hit_record.p - current_center) / self.radius;
-        hit_record.set_face_normal(r, &outward_normal);
+        let distance_squared = rec.t() * rec.t() * r.dir().length_squared();
+        let cosine = (r.dir().dot(&self.normal) / r.dir().length()).abs();
+
+        distance_squared / (cosine * self.area)
+    }
+
+    fn random(&self, origin: &Point3, _time: f64, sampler: &mut Sampler) -> Vec3 {
+        let [s, t] = sampler.get_2d();
+        self.q + (s * self.u) + (t * self.v) - origin
+    }
+}
+
+// A triangle with surface coordinates at its corners, interpolated across it. By default the
+// corners `a`, `b` and `c` sit at (0, 0), (1, 0) and (0, 1).
+#[derive(Clone, Debug)]
+pub struct Triangle {
+    a: Point3,
+    ab: Vec3,
+    ac: Vec3,
+    normal: Vec3,
+    area: f64,
+    uvs: [[f64; 2]; 3],
+    tangent: Vec3,
+    bitangent: Vec3,
+    mat: Arc<Material>,
+    bbox: AxisAlignedBoundingBox,
+}
+
+impl Triangle {
+    pub fn new(a: &Point3, b: &Point3, c: &Point3, mat: &Arc<Material>) -> Self {
+        let (ab, ac) = (b - a, c - a);
+        let n = ab.cross(&ac);
+
+        let bbox = AxisAlignedBoundingBox::merge_boxes(
+            &AxisAlignedBoundingBox::from_points(a, b),
+            &AxisAlignedBoundingBox::from_points(a, c),
+        );
+
+        Self {
+            a: *a,
+            ab,
+            ac,
+            normal: n.unit_vector(),
+            area: 0.5 * n.length(),
+            uvs: [[0.0, 0.0], [1.0, 0.0], [0.0, 1.0]],
+            tangent: ab,
+            bitangent: ac,
+            mat: mat.clone(),
+            bbox: bbox.pad_to_minimums(),
+        }
+    }
+
+    // Surface coordinates at `a`, `b` and `c`. The tangents follow from how they change across the
+    // triangle; when they don't span an area, the edges from `a` are used instead.
+    pub fn with_uvs(mut self, uvs: [[f64; 2]; 3]) -> Self {
+        // Changes in the coordinates along the edges from `a` to `b` and to `c`
+        let [ab, ac] = [1, 2].map(|i| [uvs[i][0] - uvs[0][0], uvs[i][1] - uvs[0][1]]);
+        let determinant = ab[0].mul_add(ac[1], -(ab[1] * ac[0]));
+
+        self.uvs = uvs;
+        if determinant.abs() < 1e-12 {
+            (self.tangent, self.bitangent) = (self.ab, self.ac);
+        } else {
+            let inverse = determinant.recip();
+            self.tangent = inverse * (ac[1] * self.ab - ab[1] * self.ac);
+            self.bitangent = inverse * (ab[0] * self.ac - ac[0] * self.ab);
+        }
+        self
+    }
+}
+
+impl RayIntersection for Triangle {
+    // Möller-Trumbore: solves for the distance and the barycentric coordinates of `b` and `c` at
+    // once
+    fn hit(&self, r: &Ray, ray_t: Interval, hit_record: &mut HitRecord) -> bool {
+        let p = r.dir().cross(&self.ac);
+        let determinant = self.ab.dot(&p);
+
+        // Rays parallel to the plane never hit it
+        if determinant.abs() < 1e-12 {
+            return false;
+        }
+
+        let inverse = determinant.recip();
+        let to_origin = r.origin() - self.a;
+        let beta = inverse * to_origin.dot(&p);
+        if !(0.0..=1.0).contains(&beta) {
+            return false;
+        }
+
+        let q = to_origin.cross(&self.ab);
+        let gamma = inverse * r.dir().dot(&q);
+        if gamma < 0.0 || beta + gamma > 1.0 {
+            return false;
+        }
+
+        let t = inverse * self.ac.dot(&q);
+        if !ray_t.surrounds(t) {
+            return false;
+        }
+
+        let alpha = 1.0 - beta - gamma;
+        let [uv_a, uv_b, uv_c] = self.uvs;
+        let uv = [
+            alpha.mul_add(uv_a[0], beta.mul_add(uv_b[0], gamma * uv_c[0])),
+            alpha.mul_add(uv_a[1], beta.mul_add(uv_b[1], gamma * uv_c[1])),
+        ];
+        let p = r.at(t);
+        if is_cut_out(&self.mat, r, uv, &p) {
+            return false;
+        }
+
+        hit_record.t = t;
+        hit_record.p = p;
+        hit_record.uv = uv;
+        hit_record.set_face_normal(r, &self.normal);
+        hit_record.tangent = self.tangent;
+        hit_record.bitangent = self.bitangent;
         hit_record.mat = self.mat.clone();
 
         true
@@ -149,10 +511,357 @@ impl RayIntersection for Sphere {
     fn bounding_box(&self) -> AxisAlignedBoundingBox {
         self.bbox
     }
+
+    // Points are sampled uniformly by area, so the density is converted to solid angle
+    fn pdf_value(&self, r: &Ray) -> f64 {
+        let mut rec = HitRecord::default();
+        if !self.hit(r, interval::ERROR_CORRECTED_NON_NEGATIVE, &mut rec) {
+            return 0.0;
+        }
+
+        let distance_squared = rec.t() * rec.t() * r.dir().length_squared();
+        let cosine = (r.dir().dot(&self.normal) / r.dir().length()).abs();
+
+        distance_squared / (cosine * self.area)
+    }
+
+    // Folding the unit square in half along its diagonal covers the triangle uniformly
+    fn random(&self, origin: &Point3, _time: f64, sampler: &mut Sampler) -> Vec3 {
+        let [mut s, mut t] = sampler.get_2d();
+        if s + t > 1.0 {
+            (s, t) = (1.0 - s, 1.0 - t);
+        }
+        self.a + (s * self.ab) + (t * self.ac) - origin
+    }
 }
 
 #[derive(Clone, Debug)]
 #[enum_dispatch]
 pub enum Hittable {
     Sphere(Sphere),
+    Quad(Quad),
+    Triangle(Triangle),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        bvh_node::BvhNode,
+        color::Color,
+        hittable_collection::HittableCollection,
+        light::{LightSource, PointLight},
+        material::{Lambertian, Masked},
+        sampler::SamplerKind,
+        texture::CheckerTexture,
+    };
+
+    // A unit sphere at the origin behind a quad at z = 2 with the given alpha
+    fn world(alpha: f64, use_bvh: bool) -> HittableCollection {
+        let sphere_material = Arc::new(Material::from(Lambertian::new(Color::new(0.2, 0.4, 0.6))));
+        let quad_material = Arc::new(Material::from(Masked::new(
+            Lambertian::new(Color::new(0.9, 0.1, 0.1)),
+            alpha,
+        )));
+        let mut objects: Vec<Arc<dyn RayIntersection>> = vec![
+            Arc::new(Hittable::from(Sphere::new(
+                &Point3::default(),
+                1.0,
+                &sphere_material,
+            ))),
+            Arc::new(Hittable::from(Quad::new(
+                &Point3::new(-2.0, -2.0, 2.0),
+                &Vec3::new(4.0, 0.0, 0.0),
+                &Vec3::new(0.0, 4.0, 0.0),
+                &quad_material,
+            ))),
+        ];
+
+        let mut world = HittableCollection::default();
+        if use_bvh {
+            world.add(Arc::new(BvhNode::from(&mut objects)));
+        } else {
+            for object in objects {
+                world.add(object);
+            }
+        }
+        world
+    }
+
+    fn hit(world: &HittableCollection, r: &Ray) -> Option<HitRecord> {
+        let mut rec = HitRecord::default();
+        world
+            .hit(r, Interval::new(0.001, f64::INFINITY), &mut rec)
+            .then_some(rec)
+    }
+
+    #[test]
+    fn rays_pass_through_transparent_surfaces() {
+        let r = Ray::new(&Point3::new(0.1, 0.2, 5.0), &Vec3::new(0.0, 0.0, -1.0));
+        for use_bvh in [false, true] {
+            let rec = hit(&world(0.0, use_bvh), &r).unwrap();
+            assert!((rec.t() - 4.0).abs() < 0.1, "{}", rec.t());
+            assert!((rec.p().length() - 1.0).abs() < 1e-9);
+
+            let rec = hit(&world(1.0, use_bvh), &r).unwrap();
+            assert!((rec.t() - 3.0).abs() < 1e-9, "{}", rec.t());
+
+            // Rays that miss the sphere go on to miss everything
+            let past = Ray::new(&Point3::new(1.5, 1.5, 5.0), &Vec3::new(0.0, 0.0, -1.0));
+            assert!(hit(&world(0.0, use_bvh), &past).is_none());
+        }
+    }
+
+    // Shadow rays are traced the way direct lighting traces them, from the shaded point towards a
+    // light sample and stopping just short of it
+    #[test]
+    fn shadow_rays_pass_through_transparent_surfaces() {
+        let light = PointLight::new(Point3::new(0.0, 0.0, 4.0), Color::new(1.0, 1.0, 1.0));
+        let mut sampler = Sampler::new(SamplerKind::Independent, 0, 0, 0, 1);
+        let p = Point3::new(0.0, 0.0, 1.0);
+        let sample = light.sample(&p, 0.0, &mut sampler).unwrap();
+        let shadow_ray = Ray::with_time(&p, &sample.direction, 0.0);
+        let shadow_t = Interval::new(
+            interval::ERROR_CORRECTED_NON_NEGATIVE.min,
+            sample.distance * (1.0 - 1e-4),
+        );
+
+        for use_bvh in [false, true] {
+            let occluded =
+                |alpha| world(alpha, use_bvh).hit(&shadow_ray, shadow_t, &mut HitRecord::default());
+            assert!(!occluded(0.0));
+            assert!(occluded(1.0));
+        }
+    }
+
+    // Alpha textures cut holes only where they are 0, and partly covered hits are decided the same
+    // way every time a ray is traced
+    #[test]
+    fn alpha_masks_follow_their_texture() {
+        let material = Arc::new(Material::from(Masked::new(
+            Lambertian::new(Color::new(0.5, 0.5, 0.5)),
+            CheckerTexture::new(2.0, Color::default(), Color::new(1.0, 1.0, 1.0)),
+        )));
+        let quad = Quad::new(
+            &Point3::new(0.0, 0.0, 0.0),
+            &Vec3::new(1.0, 0.0, 0.0),
+            &Vec3::new(0.0, 1.0, 0.0),
+            &material,
+        );
+        let mut hits = 0;
+        for (x, y) in [(0.25, 0.25), (0.75, 0.25), (0.25, 0.75), (0.75, 0.75)] {
+            let r = Ray::new(&Point3::new(x, y, 1.0), &Vec3::new(0.0, 0.0, -1.0));
+            let alpha = material.alpha([x, y], &Point3::new(x, y, 0.0));
+            let is_hit = quad.hit(&r, Interval::new(0.001, 10.0), &mut HitRecord::default());
+            assert_eq!(is_hit, alpha > 0.0, "({x}, {y})");
+            hits += usize::from(is_hit);
+        }
+        assert_eq!(hits, 2);
+
+        let half = Arc::new(Material::from(Masked::new(Lambertian::default(), 0.5)));
+        let quad = Quad::new(
+            &Point3::new(0.0, 0.0, 0.0),
+            &Vec3::new(1.0, 0.0, 0.0),
+            &Vec3::new(0.0, 1.0, 0.0),
+            &half,
+        );
+        let mut hits = 0;
+        for i in 0..1000 {
+            let x = (f64::from(i) + 0.5) / 1000.0;
+            let r = Ray::new(&Point3::new(x, 0.5, 1.0), &Vec3::new(0.0, 0.0, -1.0));
+            let first = quad.hit(&r, Interval::new(0.001, 10.0), &mut HitRecord::default());
+            let second = quad.hit(&r, Interval::new(0.001, 10.0), &mut HitRecord::default());
+            assert_eq!(first, second);
+            hits += u32::from(first);
+        }
+        assert!((400..600).contains(&hits), "{hits}");
+    }
+
+    const LIGHT_SAMPLES: u32 = 4096;
+
+    // Solid angle of the triangle `a`, `b`, `c` seen from `p`, by Van Oosterom and Strackee's
+    // formula
+    fn triangle_solid_angle(p: &Point3, a: &Point3, b: &Point3, c: &Point3) -> f64 {
+        let (a, b, c) = (a - p, b - p, c - p);
+        let (la, lb, lc) = (a.length(), b.length(), c.length());
+        let numerator = a.dot(&b.cross(&c)).abs();
+        let denominator =
+            (la * lb).mul_add(lc, a.dot(&b) * lc) + a.dot(&c).mul_add(lb, b.dot(&c) * la);
+        2.0 * numerator.atan2(denominator)
+    }
+
+    // Samples directions towards `object` from `p` and checks each against the density the object
+    // reports for it. Averaging 1 / pdf over the samples estimates the solid angle they cover.
+    fn assert_sampling_covers(
+        object: &impl RayIntersection,
+        p: &Point3,
+        time: f64,
+        solid_angle: f64,
+    ) {
+        let mut sum = 0.0;
+        for i in 0..LIGHT_SAMPLES {
+            let mut sampler = Sampler::new(SamplerKind::Sobol, 1, 0, u64::from(i), LIGHT_SAMPLES);
+            let direction = object.random(p, time, &mut sampler);
+            let r = Ray::with_time(p, &direction, time);
+            assert!(
+                object.hit(
+                    &r,
+                    interval::ERROR_CORRECTED_NON_NEGATIVE,
+                    &mut HitRecord::default()
+                ),
+                "sampled direction {direction:?} misses"
+            );
+            let pdf = object.pdf_value(&r);
+            assert!(pdf > 0.0 && pdf.is_finite(), "{pdf}");
+
+            // The density doesn't depend on the length of the direction
+            let rescaled = Ray::with_time(p, &(3.0 * direction), time);
+            assert!((object.pdf_value(&rescaled) - pdf).abs() <= 1e-9 * pdf);
+            sum += pdf.recip();
+        }
+
+        let estimate = sum / f64::from(LIGHT_SAMPLES);
+        assert!(
+            (estimate - solid_angle).abs() < 1e-2 * solid_angle,
+            "estimated {estimate}, expected {solid_angle}"
+        );
+    }
+
+    #[test]
+    fn sphere_sampling_covers_its_solid_angle() {
+        let material = Arc::new(Material::default());
+        for (center, radius) in [
+            (Point3::new(0.0, 0.0, -3.0), 1.0),
+            (Point3::new(2.0, 5.0, 1.0), 0.3),
+        ] {
+            let sphere = Sphere::new(&center, radius, &material);
+            let distance = center.length();
+            let sin_theta_max = radius / distance;
+            let cos_theta_max = sin_theta_max.mul_add(-sin_theta_max, 1.0).sqrt();
+            assert_sampling_covers(
+                &sphere,
+                &Point3::default(),
+                0.0,
+                2.0 * PI * (1.0 - cos_theta_max),
+            );
+        }
+
+        // A moving sphere is sampled where it is at the ray's time
+        let moving = Sphere::with_motion(
+            &Point3::new(0.0, 0.0, -3.0),
+            &Point3::new(0.0, 0.0, -2.0),
+            1.0,
+            &material,
+        );
+        let sin_theta_max = 1.0f64 / 2.5;
+        let cos_theta_max = sin_theta_max.mul_add(-sin_theta_max, 1.0).sqrt();
+        assert_sampling_covers(
+            &moving,
+            &Point3::default(),
+            0.5,
+            2.0 * PI * (1.0 - cos_theta_max),
+        );
+    }
+
+    #[test]
+    fn quad_sampling_covers_its_solid_angle() {
+        let material = Arc::new(Material::default());
+        let (q, u, v) = (
+            Point3::new(-1.0, 0.5, -2.0),
+            Vec3::new(2.0, 0.0, 0.5),
+            Vec3::new(0.3, 1.5, 0.0),
+        );
+        let quad = Quad::new(&q, &u, &v, &material);
+        for p in [
+            Point3::default(),
+            Point3::new(0.5, 1.0, -0.5),
+            Point3::new(-3.0, 0.0, -6.0),
+        ] {
+            let solid_angle = triangle_solid_angle(&p, &q, &(q + u), &(q + u + v))
+                + triangle_solid_angle(&p, &q, &(q + u + v), &(q + v));
+            assert_sampling_covers(&quad, &p, 0.0, solid_angle);
+        }
+    }
+
+    #[test]
+    fn triangle_sampling_covers_its_solid_angle() {
+        let material = Arc::new(Material::default());
+        let (a, b, c) = (
+            Point3::new(-1.0, 0.0, -2.0),
+            Point3::new(1.5, 0.2, -2.5),
+            Point3::new(0.0, 2.0, -1.5),
+        );
+        let triangle = Triangle::new(&a, &b, &c, &material);
+        for p in [
+            Point3::default(),
+            Point3::new(0.2, 0.5, -1.0),
+            Point3::new(0.0, 0.5, -5.0),
+        ] {
+            assert_sampling_covers(&triangle, &p, 0.0, triangle_solid_angle(&p, &a, &b, &c));
+        }
+    }
+
+    // The tangents are the derivatives of the hit point along u and v, whichever way the
+    // coordinates run across the triangle
+    #[test]
+    fn triangle_tangents_follow_the_surface_coordinates() {
+        let material = Arc::new(Material::default());
+        let (a, b, c) = (
+            Point3::default(),
+            Point3::new(2.0, 0.0, 1.0),
+            Point3::new(0.0, 3.0, 0.0),
+        );
+        for (uvs, tangent, bitangent) in [
+            (
+                [[0.2, 0.1], [0.6, 0.1], [0.2, 0.7]],
+                Vec3::new(5.0, 0.0, 2.5),
+                Vec3::new(0.0, 5.0, 0.0),
+            ),
+            (
+                [[0.0, 0.0], [0.0, 1.0], [1.0, 0.0]],
+                Vec3::new(0.0, 3.0, 0.0),
+                Vec3::new(2.0, 0.0, 1.0),
+            ),
+            (
+                [[0.5, 0.5], [1.0, 1.0], [0.0, 1.0]],
+                Vec3::new(2.0, -3.0, 1.0),
+                Vec3::new(2.0, 3.0, 1.0),
+            ),
+        ] {
+            let triangle = Triangle::new(&a, &b, &c, &material).with_uvs(uvs);
+            let mut rec = HitRecord::default();
+            let r = Ray::new(&Point3::new(0.5, 0.5, 5.0), &Vec3::new(0.0, 0.0, -1.0));
+            assert!(triangle.hit(&r, interval::ERROR_CORRECTED_NON_NEGATIVE, &mut rec));
+            assert!(
+                (rec.tangent() - tangent).length() < 1e-12,
+                "{:?}",
+                rec.tangent()
+            );
+            assert!(
+                (rec.bitangent() - bitangent).length() < 1e-12,
+                "{:?}",
+                rec.bitangent()
+            );
+
+            // Moving along them changes the coordinates by one unit each
+            let uv = rec.uv();
+            for (step, expected) in [
+                (rec.tangent(), [uv[0] + 0.1, uv[1]]),
+                (rec.bitangent(), [uv[0], uv[1] + 0.1]),
+            ] {
+                let target = rec.p() + 0.1 * step;
+                let mut shifted = HitRecord::default();
+                let r = Ray::new(
+                    &(target + Vec3::new(0.0, 0.0, 5.0)),
+                    &Vec3::new(0.0, 0.0, -1.0),
+                );
+                assert!(triangle.hit(&r, interval::ERROR_CORRECTED_NON_NEGATIVE, &mut shifted));
+                let [shifted_u, shifted_v] = shifted.uv();
+                assert!(
+                    (shifted_u - expected[0]).abs() < 1e-9
+                        && (shifted_v - expected[1]).abs() < 1e-9
+                );
+            }
+        }
+    }
 }
@@ -28,10 +28,6 @@ impl Interval {
         x.clamp(self.min, self.max)
     }
 
-    #[expect(
-        dead_code,
-        reason = "Function referenced in chapter 3 but not referenced when introduced, only used later"
-    )]
     pub const fn expand(&self, delta: f64) -> Self {
         let padding = 0.5 * delta;
         Self::new(self.min - padding, self.max + padding)
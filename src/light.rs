@@ -0,0 +1,544 @@
+use crate::{
+    color::Color,
+    distribution::Distribution2D,
+    hdr_image::HdrImage,
+    hittable::{HitRecord, RayIntersection},
+    interval,
+    material::Scatter,
+    ray::Ray,
+    sampler::{SampleGenerator, Sampler},
+    vec3::{Onb, Point3, Vec3, sample_uniform_cone, uniform_cone_pdf},
+};
+use enum_dispatch::enum_dispatch;
+use std::{
+    f64::consts::{PI, TAU},
+    sync::Arc,
+};
+
+// Light arriving at a point from a sampled direction, before checking whether anything blocks it.
+// Delta lights can only be reached by sampling them, so they aren't weighted against the BSDF.
+#[derive(Clone, Copy, Debug)]
+pub struct LightSample {
+    pub direction: Vec3,
+    pub distance: f64,
+    pub radiance: Color,
+    pub pdf: f64,
+    pub delta: bool,
+}
+
+#[enum_dispatch(Light)]
+pub trait LightSource {
+    fn sample(&self, p: &Point3, time: f64, sampler: &mut Sampler) -> Option<LightSample>;
+
+    // Solid angle density of `sample` producing the light that arrived along `r`, which either hit
+    // the surface in `rec` or escaped the scene. Zero for anything this light can't have produced.
+    fn pdf(&self, _r: &Ray, _rec: Option<&HitRecord>) -> f64 {
+        0.0
+    }
+
+    // Radiance this light adds to rays that escape the scene
+    fn escaped_radiance(&self, _r: &Ray) -> Color {
+        Color::default()
+    }
+}
+
+// Emissive geometry. The object must also be part of the world so rays can hit it.
+#[derive(Clone, Debug)]
+pub struct AreaLight {
+    object: Arc<dyn RayIntersection>,
+}
+
+impl AreaLight {
+    pub fn new(object: Arc<dyn RayIntersection>) -> Self {
+        Self { object }
+    }
+}
+
+impl LightSource for AreaLight {
+    fn sample(&self, p: &Point3, time: f64, sampler: &mut Sampler) -> Option<LightSample> {
+        let direction = self.object.random(p, time, sampler);
+        let r = Ray::with_time(p, &direction, time);
+        let mut rec = HitRecord::default();
+        if !self
+            .object
+            .hit(&r, interval::ERROR_CORRECTED_NON_NEGATIVE, &mut rec)
+        {
+            return None;
+        }
+
+        Some(LightSample {
+            direction: direction.unit_vector(),
+            distance: rec.t() * direction.length(),
+            radiance: rec.material().emitted(&rec),
+            pdf: self.object.pdf_value(&r),
+            delta: false,
+        })
+    }
+
+    // Only counts when this light is the surface the ray hit, not something behind it
+    fn pdf(&self, r: &Ray, rec: Option<&HitRecord>) -> f64 {
+        let Some(rec) = rec else {
+            return 0.0;
+        };
+
+        let mut light_rec = HitRecord::default();
+        let hit_this_light =
+            self.object
+                .hit(r, interval::ERROR_CORRECTED_NON_NEGATIVE, &mut light_rec)
+                && (light_rec.t() - rec.t()).abs() <= 1e-9 * rec.t().max(1.0);
+
+        if hit_this_light {
+            self.object.pdf_value(r)
+        } else {
+            0.0
+        }
+    }
+}
+
+// Emits `intensity` in all directions, falling off with the squared distance
+#[derive(Clone, Debug)]
+pub struct PointLight {
+    position: Point3,
+    intensity: Color,
+}
+
+impl PointLight {
+    pub const fn new(position: Point3, intensity: Color) -> Self {
+        Self {
+            position,
+            intensity,
+        }
+    }
+}
+
+impl LightSource for PointLight {
+    fn sample(&self, p: &Point3, _time: f64, _sampler: &mut Sampler) -> Option<LightSample> {
+        let to_light = self.position - p;
+        let distance_squared = to_light.length_squared();
+
+        Some(LightSample {
+            direction: to_light.unit_vector(),
+            distance: distance_squared.sqrt(),
+            radiance: distance_squared.recip() * self.intensity,
+            pdf: 1.0,
+            delta: true,
+        })
+    }
+}
+
+// A point light restricted to a cone, fading out smoothly between the falloff start angle and the
+// total width
+#[derive(Clone, Debug)]
+pub struct SpotLight {
+    position: Point3,
+    direction: Vec3,
+    intensity: Color,
+    cos_total_width: f64,
+    cos_falloff_start: f64,
+}
+
+impl SpotLight {
+    pub fn new(
+        position: Point3,
+        look_at: Point3,
+        intensity: Color,
+        total_width_degrees: f64,
+        falloff_start_degrees: f64,
+    ) -> Self {
+        Self {
+            position,
+            direction: (look_at - position).unit_vector(),
+            intensity,
+            cos_total_width: total_width_degrees.to_radians().cos(),
+            cos_falloff_start: falloff_start_degrees
+                .min(total_width_degrees)
+                .to_radians()
+                .cos(),
+        }
+    }
+
+    fn falloff(&self, cos_theta: f64) -> f64 {
+        if cos_theta >= self.cos_falloff_start {
+            return 1.0;
+        }
+        if cos_theta <= self.cos_total_width {
+            return 0.0;
+        }
+
+        let t =
+            (cos_theta - self.cos_total_width) / (self.cos_falloff_start - self.cos_total_width);
+        t * t * 2.0f64.mul_add(-t, 3.0)
+    }
+}
+
+impl LightSource for SpotLight {
+    fn sample(&self, p: &Point3, _time: f64, _sampler: &mut Sampler) -> Option<LightSample> {
+        let to_light = self.position - p;
+        let distance_squared = to_light.length_squared();
+        let direction = to_light.unit_vector();
+        let falloff = self.falloff(-direction.dot(&self.direction));
+        if falloff <= 0.0 {
+            return None;
+        }
+
+        Some(LightSample {
+            direction,
+            distance: distance_squared.sqrt(),
+            radiance: (falloff / distance_squared) * self.intensity,
+            pdf: 1.0,
+            delta: true,
+        })
+    }
+}
+
+// A light infinitely far away, such as the sun. With an angular diameter it is a small disk in the
+// sky that casts soft shadows and shows up in reflections; without one it is a delta light. Either
+// way `irradiance` is the light it delivers to a surface facing it.
+#[derive(Clone, Debug)]
+pub struct DirectionalLight {
+    frame: Onb,
+    irradiance: Color,
+    cos_theta_max: f64,
+}
+
+impl DirectionalLight {
+    pub fn new(direction_to_light: Vec3, irradiance: Color, angular_diameter_degrees: f64) -> Self {
+        Self {
+            frame: Onb::new(&direction_to_light.unit_vector()),
+            irradiance,
+            cos_theta_max: (0.5 * angular_diameter_degrees.max(0.0)).to_radians().cos(),
+        }
+    }
+
+    const fn is_delta(&self) -> bool {
+        self.cos_theta_max >= 1.0
+    }
+
+    fn radiance(&self) -> Color {
+        uniform_cone_pdf(self.cos_theta_max) * self.irradiance
+    }
+}
+
+impl LightSource for DirectionalLight {
+    fn sample(&self, _p: &Point3, _time: f64, sampler: &mut Sampler) -> Option<LightSample> {
+        let u = sampler.get_2d();
+        if self.is_delta() {
+            return Some(LightSample {
+                direction: *self.frame.w(),
+                distance: f64::INFINITY,
+                radiance: self.irradiance,
+                pdf: 1.0,
+                delta: true,
+            });
+        }
+
+        Some(LightSample {
+            direction: self
+                .frame
+                .local_to_world(&sample_uniform_cone(u, self.cos_theta_max)),
+            distance: f64::INFINITY,
+            radiance: self.radiance(),
+            pdf: uniform_cone_pdf(self.cos_theta_max),
+            delta: false,
+        })
+    }
+
+    fn pdf(&self, r: &Ray, rec: Option<&HitRecord>) -> f64 {
+        if rec.is_some() || self.escaped_radiance(r).is_black() {
+            0.0
+        } else {
+            uniform_cone_pdf(self.cos_theta_max)
+        }
+    }
+
+    fn escaped_radiance(&self, r: &Ray) -> Color {
+        if self.is_delta() || r.dir().unit_vector().dot(self.frame.w()) < self.cos_theta_max {
+            Color::default()
+        } else {
+            self.radiance()
+        }
+    }
+}
+
+// Light arriving from every direction, looked up in an equirectangular image with +y at its top
+// row. Directions are importance sampled by the luminance of the pixels, weighted by the solid
+// angle each covers. `rotation_degrees` turns the image about the vertical axis.
+#[derive(Clone, Debug)]
+pub struct EnvironmentLight {
+    image: Arc<HdrImage>,
+    distribution: Arc<Distribution2D>,
+    rotation: f64,
+    intensity: f64,
+}
+
+impl EnvironmentLight {
+    #[allow(clippy::cast_precision_loss)]
+    pub fn new(image: HdrImage, rotation_degrees: f64, intensity: f64) -> Self {
+        let (width, height) = (image.width(), image.height());
+        let function: Vec<_> = (0..height)
+            .flat_map(|y| {
+                let sin_theta = (PI * (y as f64 + 0.5) / height as f64).sin();
+                let image = &image;
+                (0..width).map(move |x| image.pixel(x, y).luminance() * sin_theta)
+            })
+            .collect();
+
+        Self {
+            distribution: Arc::new(Distribution2D::new(&function, width, height)),
+            image: Arc::new(image),
+            rotation: rotation_degrees.to_radians(),
+            intensity,
+        }
+    }
+
+    fn direction(&self, [u, v]: [f64; 2]) -> Vec3 {
+        equirectangular_direction([u + self.rotation / TAU, v])
+    }
+
+    fn image_coordinates(&self, direction: &Vec3) -> [f64; 2] {
+        let direction = direction.unit_vector();
+        let theta = direction.y().clamp(-1.0, 1.0).acos();
+        let phi = (direction.z().atan2(direction.x()) - self.rotation).rem_euclid(TAU);
+        [phi / TAU, theta / PI]
+    }
+
+    #[allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_precision_loss,
+        clippy::cast_sign_loss
+    )]
+    fn lookup(&self, [u, v]: [f64; 2]) -> Color {
+        let (width, height) = (self.image.width(), self.image.height());
+        let x = ((u * width as f64) as usize).min(width - 1);
+        let y = ((v * height as f64) as usize).min(height - 1);
+        self.intensity * self.image.pixel(x, y)
+    }
+
+    // Converts a density over the image to solid angle. Each row covers a band of the sphere whose
+    // area shrinks with sin θ towards the poles.
+    fn solid_angle_pdf(map_pdf: f64, v: f64) -> f64 {
+        let sin_theta = (PI * v).sin();
+        if sin_theta <= 0.0 {
+            0.0
+        } else {
+            map_pdf / (2.0 * PI * PI * sin_theta)
+        }
+    }
+}
+
+impl LightSource for EnvironmentLight {
+    fn sample(&self, _p: &Point3, _time: f64, sampler: &mut Sampler) -> Option<LightSample> {
+        let (uv, map_pdf) = self.distribution.sample(sampler.get_2d());
+        let pdf = Self::solid_angle_pdf(map_pdf, uv[1]);
+        if pdf <= 0.0 {
+            return None;
+        }
+
+        Some(LightSample {
+            direction: self.direction(uv),
+            distance: f64::INFINITY,
+            radiance: self.lookup(uv),
+            pdf,
+            delta: false,
+        })
+    }
+
+    fn pdf(&self, r: &Ray, rec: Option<&HitRecord>) -> f64 {
+        if rec.is_some() {
+            return 0.0;
+        }
+
+        let uv = self.image_coordinates(r.dir());
+        Self::solid_angle_pdf(self.distribution.pdf(uv), uv[1])
+    }
+
+    fn escaped_radiance(&self, r: &Ray) -> Color {
+        self.lookup(self.image_coordinates(r.dir()))
+    }
+}
+
+// The direction at image coordinates in [0, 1)² of an equirectangular map, unrotated
+pub fn equirectangular_direction([u, v]: [f64; 2]) -> Vec3 {
+    let theta = PI * v;
+    let phi = TAU * u;
+    Vec3::new(
+        theta.sin() * phi.cos(),
+        theta.cos(),
+        theta.sin() * phi.sin(),
+    )
+}
+
+#[derive(Clone, Debug)]
+#[enum_dispatch]
+pub enum Light {
+    Area(AreaLight),
+    Point(PointLight),
+    Spot(SpotLight),
+    Directional(DirectionalLight),
+    Environment(EnvironmentLight),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        hittable::{Quad, Sphere},
+        material::{DiffuseLight, Material},
+        sampler::SamplerKind,
+    };
+
+    const SAMPLES: u32 = 4096;
+
+    fn samplers() -> impl Iterator<Item = Sampler> {
+        (0..SAMPLES).map(|i| Sampler::new(SamplerKind::Sobol, 1, 0, u64::from(i), SAMPLES))
+    }
+
+    fn assert_close(actual: f64, expected: f64, tolerance: f64) {
+        assert!(
+            (actual - expected).abs() <= tolerance * expected.abs(),
+            "{actual} {expected}"
+        );
+    }
+
+    // Checks every sample's pdf against the one `pdf` gives for the same direction, and returns the
+    // mean of 1 / pdf, which estimates the solid angle the samples cover
+    fn sampled_solid_angle(light: &Light, p: &Point3, world: Option<&dyn RayIntersection>) -> f64 {
+        let mut sum = 0.0;
+        for mut sampler in samplers() {
+            let sample = light.sample(p, 0.0, &mut sampler).unwrap();
+            assert!(!sample.delta);
+            assert!(sample.pdf > 0.0 && sample.pdf.is_finite(), "{}", sample.pdf);
+
+            let r = Ray::new(p, &sample.direction);
+            let mut rec = HitRecord::default();
+            let rec = world
+                .filter(|world| world.hit(&r, interval::ERROR_CORRECTED_NON_NEGATIVE, &mut rec))
+                .map(|_| &rec);
+            assert_close(light.pdf(&r, rec), sample.pdf, 1e-9);
+            sum += sample.pdf.recip();
+        }
+        sum / f64::from(SAMPLES)
+    }
+
+    fn emitter() -> Arc<Material> {
+        Arc::new(DiffuseLight::new(Color::new(1.0, 1.0, 1.0)).into())
+    }
+
+    #[test]
+    fn area_lights_match_their_pdf() {
+        let p = Point3::new(0.2, -0.5, 0.0);
+
+        let sphere: Arc<dyn RayIntersection> =
+            Arc::new(Sphere::new(&Point3::new(0.0, 0.0, -4.0), 1.5, &emitter()));
+        let light = Light::from(AreaLight::new(sphere.clone()));
+        let distance = (Point3::new(0.0, 0.0, -4.0) - p).length();
+        let sin_theta_max = 1.5 / distance;
+        let cos_theta_max = sin_theta_max.mul_add(-sin_theta_max, 1.0).sqrt();
+        assert_close(
+            sampled_solid_angle(&light, &p, Some(sphere.as_ref())),
+            TAU * (1.0 - cos_theta_max),
+            1e-2,
+        );
+
+        let quad: Arc<dyn RayIntersection> = Arc::new(Quad::new(
+            &Point3::new(-1.0, 1.0, -2.0),
+            &Vec3::new(2.0, 0.0, 0.0),
+            &Vec3::new(0.0, 0.5, 1.0),
+            &emitter(),
+        ));
+        let light = Light::from(AreaLight::new(quad.clone()));
+        sampled_solid_angle(&light, &p, Some(quad.as_ref()));
+
+        // Rays that escape, or that hit something in front of the light, weren't sampled from it
+        let r = Ray::new(&p, &Vec3::new(0.0, 1.0, -1.0));
+        assert!(light.pdf(&r, None) == 0.0);
+        let mut blocker = HitRecord::default();
+        let occluder = Sphere::new(&Point3::new(0.2, 0.0, -0.5), 0.2, &emitter());
+        assert!(occluder.hit(&r, interval::ERROR_CORRECTED_NON_NEGATIVE, &mut blocker));
+        assert!(light.pdf(&r, Some(&blocker)) == 0.0);
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp)]
+    fn directional_lights_match_their_pdf() {
+        let direction = Vec3::new(1.0, 2.0, -0.5);
+        let irradiance = Color::new(3.0, 3.0, 3.0);
+        let p = Point3::default();
+
+        let light = Light::from(DirectionalLight::new(direction, irradiance, 10.0));
+        let cos_theta_max = 5.0f64.to_radians().cos();
+        assert_close(
+            sampled_solid_angle(&light, &p, None),
+            TAU * (1.0 - cos_theta_max),
+            1e-9,
+        );
+
+        // Only rays that escape inside the disk can have come from it
+        let r = Ray::new(&p, &direction);
+        assert!(light.pdf(&r, Some(&HitRecord::default())) == 0.0);
+        assert!(light.pdf(&Ray::new(&p, &-direction), None) == 0.0);
+
+        let delta = Light::from(DirectionalLight::new(direction, irradiance, 0.0));
+        let sample = delta
+            .sample(&p, 0.0, &mut samplers().next().unwrap())
+            .unwrap();
+        assert!(sample.delta && sample.pdf == 1.0);
+        assert_close(sample.direction.dot(&direction.unit_vector()), 1.0, 1e-12);
+        assert!(delta.pdf(&r, None) == 0.0);
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp)]
+    fn point_and_spot_lights_are_deltas() {
+        let position = Point3::new(1.0, 4.0, -2.0);
+        let p = Point3::new(1.0, 0.0, 1.0);
+        let intensity = Color::new(10.0, 10.0, 10.0);
+        let spot = SpotLight::new(position, Point3::new(1.0, 0.0, -2.0), intensity, 45.0, 30.0);
+
+        for light in [
+            Light::from(PointLight::new(position, intensity)),
+            Light::from(spot.clone()),
+        ] {
+            let sample = light
+                .sample(&p, 0.0, &mut samplers().next().unwrap())
+                .unwrap();
+            assert!(sample.delta && sample.pdf == 1.0);
+            assert_close(sample.distance, 5.0, 1e-12);
+            assert_close(sample.direction.dot(&Vec3::new(0.0, 0.8, -0.6)), 1.0, 1e-12);
+            assert!(light.pdf(&Ray::new(&p, &sample.direction), None) == 0.0);
+        }
+
+        // Outside the cone the spot light has nothing to sample
+        let outside = Point3::new(6.0, 0.0, -2.0);
+        assert!(
+            spot.sample(&outside, 0.0, &mut samplers().next().unwrap())
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn environment_lights_match_their_pdf() {
+        // Dim everywhere except a bright spot, so sampling is far from uniform
+        let (width, height) = (16, 8);
+        let pixels = (0..width * height)
+            .map(|i| {
+                if i == 2 * width + 5 {
+                    Color::new(20.0, 15.0, 10.0)
+                } else {
+                    Color::new(0.5, 1.0, 1.5)
+                }
+            })
+            .collect();
+        let light = Light::from(EnvironmentLight::new(
+            HdrImage::new(width, height, pixels),
+            70.0,
+            2.0,
+        ));
+
+        assert_close(
+            sampled_solid_angle(&light, &Point3::default(), None),
+            4.0 * PI,
+            1e-2,
+        );
+    }
+}
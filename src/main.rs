@@ -6,44 +6,163 @@
 #![warn(clippy::exit)]
 
 mod aabb;
+mod adaptive_sampling;
 mod bvh_node;
 mod camera;
+mod checkpoint;
 mod color;
+mod distribution;
+mod film;
+mod filter;
+mod hdr_image;
 mod hittable;
 mod hittable_collection;
 mod interval;
+mod light;
 mod material;
+mod microfacet;
+mod options;
+mod perlin;
 mod ray;
+mod running_stats;
+mod sampler;
+mod scene;
 mod scope_timer;
+mod sky;
+mod spectrum;
+mod texture;
+mod tile;
 mod vec3;
 
+use adaptive_sampling::AdaptiveSampling;
 use camera::CameraBuilder;
 use color::Color;
-use hittable::{Hittable, RayIntersection, Sphere};
+use filter::{Filter, MitchellNetravaliFilter};
+use hdr_image::HdrImage;
+use hittable::{Hittable, Quad, RayIntersection, Sphere, Triangle};
 use hittable_collection::HittableCollection;
-use material::{Dielectric, Lambertian, Material, Metal};
-use rand::{RngExt, rng};
-use std::sync::Arc;
+use light::{AreaLight, DirectionalLight, EnvironmentLight, Light, PointLight, SpotLight};
+use material::{
+    Add, CoatedDiffuse, Conductor, Dielectric, DiffuseLight, Lambertian, Masked, Material, Metal,
+    Mix, PrincipledBuilder, RoughDielectric, SoapBubble, ThinFilm,
+};
+use microfacet::{ComplexIor, RefractiveIndex};
+use options::Options;
+use rand::{RngExt, SeedableRng, rngs::Xoshiro256PlusPlus};
+use sampler::SamplerKind;
+use scene::{Background, Scene, SceneKind};
+use sky::PreethamSky;
+use std::{env, io, path::PathBuf, sync::Arc};
+use texture::{CheckerTexture, ImageTexture, NoiseTexture, NormalMap, Texture};
+use tile::TileOrder;
 use vec3::{Point3, Vec3};
 
 use crate::bvh_node::BvhNode;
 
-fn main() {
-    const ASPECT_RATIO: f64 = 16.0 / 9.0;
+fn main() -> io::Result<()> {
     const IMAGE_WIDTH: i32 = 1200;
     const SAMPLES_PER_PIXEL: i32 = 500;
     const MAX_DEPTH: u32 = 50;
-    const VERTICAL_FOV: f64 = 20.0;
-    const LOOK_FROM: Point3 = Point3::new(13.0, 2.0, 3.0);
-    const LOOK_AT: Point3 = Point3::new(0.0, 0.0, 0.0);
-    const V_UP: Vec3 = Vec3::new(0.0, 1.0, 0.0);
-    const DEFOCUS_ANGLE: f64 = 0.6;
-    const FOCUS_DIST: f64 = 10.0;
+    const RUSSIAN_ROULETTE_DEPTH: u32 = 5;
+    const DEFAULT_TILE_SIZE: usize = 32;
+    const DEFAULT_TILE_ORDER: TileOrder = TileOrder::Spiral;
+    const DEFAULT_SAMPLES_PER_PASS: i32 = 10;
+    const DEFAULT_SEED: u64 = 0;
+    const DEFAULT_SAMPLER: SamplerKind = SamplerKind::Sobol;
+    const DEFAULT_FILTER: Filter =
+        Filter::MitchellNetravali(MitchellNetravaliFilter::new(2.0, 1.0 / 3.0, 1.0 / 3.0));
+    const ADAPTIVE_MIN_SAMPLES_PER_PIXEL: u32 = 32;
+    const ADAPTIVE_MAX_SAMPLES_PER_PIXEL: u32 = 2000;
+    const SKY_RESOLUTION: (usize, usize) = (512, 256);
+    const DEFAULT_SUN_ELEVATION: f64 = 35.0;
+    const DEFAULT_SUN_AZIMUTH: f64 = 60.0;
+    const DEFAULT_TURBIDITY: f64 = 3.0;
 
     // Initialize Logging
     colog::init();
 
-    // World setup
+    let options = Options::parse(env::args().skip(1))?;
+
+    let (mut scene, camera_builder) = match options.scene_kind().unwrap_or_default() {
+        SceneKind::Spheres => spheres_scene(),
+        SceneKind::CornellBox => cornell_box_scene(),
+        SceneKind::Lookdev => lookdev_scene(&TextureMaps::load(&options)?),
+        SceneKind::Iridescence => iridescence_scene(),
+    };
+    if let Some(path) = options.environment_path() {
+        scene = scene.with_environment(EnvironmentLight::new(
+            HdrImage::load(path)?,
+            options.environment_rotation().unwrap_or(0.0),
+            options.environment_intensity().unwrap_or(1.0),
+        ));
+    }
+    if options.sky() {
+        let sky = PreethamSky::new(
+            options.sun_elevation().unwrap_or(DEFAULT_SUN_ELEVATION),
+            options.sun_azimuth().unwrap_or(DEFAULT_SUN_AZIMUTH),
+            options.turbidity().unwrap_or(DEFAULT_TURBIDITY),
+        );
+        scene = scene.with_environment(sky.environment_light(SKY_RESOLUTION.0, SKY_RESOLUTION.1));
+        if let Some(sun) = sky.sun_light() {
+            scene = scene.with_light(sun.into());
+        }
+    }
+
+    let mut camera_builder = camera_builder
+        .image_width(IMAGE_WIDTH)
+        .samples_per_pixel(SAMPLES_PER_PIXEL)
+        .max_depth(MAX_DEPTH)
+        .russian_roulette_depth(RUSSIAN_ROULETTE_DEPTH)
+        .tile_size(options.tile_size().unwrap_or(DEFAULT_TILE_SIZE))
+        .tile_order(options.tile_order().unwrap_or(DEFAULT_TILE_ORDER))
+        .samples_per_pass(
+            options
+                .samples_per_pass()
+                .unwrap_or(DEFAULT_SAMPLES_PER_PASS),
+        )
+        .resume(options.resume())
+        .scene_fingerprint(options.scene_fingerprint())
+        .seed(options.seed().unwrap_or(DEFAULT_SEED))
+        .sampler(options.sampler_kind().unwrap_or(DEFAULT_SAMPLER))
+        .filter(options.filter().unwrap_or(DEFAULT_FILTER))
+        .spectral(options.spectral());
+
+    if let Some(time_limit) = options.time_limit() {
+        camera_builder = camera_builder.time_limit(time_limit);
+    }
+    if let Some(target_relative_error) = options.target_relative_error() {
+        camera_builder = camera_builder.target_relative_error(target_relative_error);
+    }
+    if let Some(threshold) = options.adaptive_threshold() {
+        camera_builder = camera_builder.adaptive_sampling(AdaptiveSampling::new(
+            ADAPTIVE_MIN_SAMPLES_PER_PIXEL,
+            ADAPTIVE_MAX_SAMPLES_PER_PIXEL,
+            threshold,
+        ));
+    }
+    if let Some(path) = options.snapshot_path() {
+        camera_builder = camera_builder.snapshot_path(path);
+    }
+    if let Some(interval) = options.snapshot_interval() {
+        camera_builder = camera_builder.snapshot_interval(interval);
+    }
+    if let Some(path) = options.checkpoint_path() {
+        camera_builder = camera_builder.checkpoint_path(path);
+    }
+    if let Some(interval) = options.checkpoint_interval() {
+        camera_builder = camera_builder.checkpoint_interval(interval);
+    }
+    if let Some(path) = options.sample_count_map_path() {
+        camera_builder = camera_builder.sample_count_map_path(path);
+    }
+
+    camera_builder.build().render(&scene)
+}
+
+fn spheres_scene() -> (Scene, CameraBuilder) {
+    // The scene is generated from a fixed seed so a resumed render continues the same scene
+    const SCENE_SEED: u64 = 0x5EED;
+
     let mut hittables: Vec<Arc<dyn RayIntersection>> = Vec::new();
 
     let ground_material = Arc::new(Material::from(Lambertian::new(Color::new(0.5, 0.5, 0.5))));
@@ -53,7 +172,7 @@ fn main() {
         &ground_material,
     ))));
 
-    let mut rng = rng();
+    let mut rng = Xoshiro256PlusPlus::seed_from_u64(SCENE_SEED);
 
     for a in -11..11 {
         for b in -11..11 {
@@ -97,7 +216,9 @@ fn main() {
         }
     }
 
-    let material1 = Arc::new(Material::from(Dielectric::new(1.5)));
+    let material1 = Arc::new(Material::from(Dielectric::with_refractive_index(
+        RefractiveIndex::CROWN_GLASS,
+    )));
     let material2 = Arc::new(Material::from(Lambertian::new(Color::new(0.4, 0.2, 0.1))));
     let material3 = Arc::new(Material::from(Metal::new(Color::new(0.7, 0.6, 0.5), 0.0)));
 
@@ -120,18 +241,447 @@ fn main() {
     let mut world = HittableCollection::default();
     world.add(Arc::new(BvhNode::from(&mut hittables)));
 
-    let camera = CameraBuilder::default()
-        .aspect_ratio(ASPECT_RATIO)
-        .image_width(IMAGE_WIDTH)
-        .samples_per_pixel(SAMPLES_PER_PIXEL)
-        .max_depth(MAX_DEPTH)
-        .vertical_fov(VERTICAL_FOV)
-        .look_from(&LOOK_FROM)
-        .look_at(&LOOK_AT)
-        .v_up(&V_UP)
-        .defocus_angle(DEFOCUS_ANGLE)
-        .focus_distance(FOCUS_DIST)
-        .build();
-
-    camera.render(&world);
+    let camera_builder = CameraBuilder::default()
+        .aspect_ratio(16.0 / 9.0)
+        .vertical_fov(20.0)
+        .look_from(&Point3::new(13.0, 2.0, 3.0))
+        .look_at(&Point3::new(0.0, 0.0, 0.0))
+        .v_up(&Vec3::new(0.0, 1.0, 0.0))
+        .defocus_angle(0.6)
+        .focus_distance(10.0);
+
+    (
+        Scene::new(world, Vec::new(), Background::Sky),
+        camera_builder,
+    )
+}
+
+fn cornell_box_scene() -> (Scene, CameraBuilder) {
+    let red = Arc::new(Material::from(Lambertian::new(Color::new(
+        0.65, 0.05, 0.05,
+    ))));
+    let white = Arc::new(Material::from(Lambertian::new(Color::new(
+        0.73, 0.73, 0.73,
+    ))));
+    let green = Arc::new(Material::from(Lambertian::new(Color::new(
+        0.12, 0.45, 0.15,
+    ))));
+    let light = Arc::new(Material::from(DiffuseLight::new(Color::new(
+        15.0, 15.0, 15.0,
+    ))));
+    let glass = Arc::new(Material::from(Dielectric::with_refractive_index(
+        RefractiveIndex::DENSE_FLINT,
+    )));
+    let aluminium = Arc::new(Material::from(Metal::new(Color::new(0.8, 0.85, 0.88), 0.0)));
+
+    let quad = |q: Point3, u: Vec3, v: Vec3, mat: &Arc<Material>| -> Arc<dyn RayIntersection> {
+        Arc::new(Hittable::from(Quad::new(&q, &u, &v, mat)))
+    };
+
+    // The light faces down into the box, so its edges are ordered to point its normal at the floor
+    let ceiling_light = quad(
+        Point3::new(213.0, 554.0, 227.0),
+        Vec3::new(130.0, 0.0, 0.0),
+        Vec3::new(0.0, 0.0, 105.0),
+        &light,
+    );
+
+    let mut hittables: Vec<Arc<dyn RayIntersection>> = vec![
+        quad(
+            Point3::new(555.0, 0.0, 0.0),
+            Vec3::new(0.0, 555.0, 0.0),
+            Vec3::new(0.0, 0.0, 555.0),
+            &green,
+        ),
+        quad(
+            Point3::new(0.0, 0.0, 0.0),
+            Vec3::new(0.0, 555.0, 0.0),
+            Vec3::new(0.0, 0.0, 555.0),
+            &red,
+        ),
+        quad(
+            Point3::new(0.0, 0.0, 0.0),
+            Vec3::new(555.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, 555.0),
+            &white,
+        ),
+        quad(
+            Point3::new(555.0, 555.0, 555.0),
+            Vec3::new(-555.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, -555.0),
+            &white,
+        ),
+        quad(
+            Point3::new(0.0, 0.0, 555.0),
+            Vec3::new(555.0, 0.0, 0.0),
+            Vec3::new(0.0, 555.0, 0.0),
+            &white,
+        ),
+        Arc::new(Hittable::from(Sphere::new(
+            &Point3::new(190.0, 90.0, 190.0),
+            90.0,
+            &glass,
+        ))),
+        Arc::new(Hittable::from(Sphere::new(
+            &Point3::new(370.0, 120.0, 370.0),
+            120.0,
+            &aluminium,
+        ))),
+        ceiling_light.clone(),
+    ];
+
+    let mut world = HittableCollection::default();
+    world.add(Arc::new(BvhNode::from(&mut hittables)));
+
+    let lights = vec![Light::from(AreaLight::new(ceiling_light))];
+
+    let camera_builder = CameraBuilder::default()
+        .aspect_ratio(1.0)
+        .vertical_fov(40.0)
+        .look_from(&Point3::new(278.0, 278.0, -800.0))
+        .look_at(&Point3::new(278.0, 278.0, 0.0))
+        .v_up(&Vec3::new(0.0, 1.0, 0.0))
+        .defocus_angle(0.0);
+
+    (
+        Scene::new(world, lights, Background::Solid(Color::default())),
+        camera_builder,
+    )
+}
+
+// Analytic lights only: a spot light as the key, a point light as the fill and a soft sun from
+// behind
+fn lookdev_scene(maps: &TextureMaps) -> (Scene, CameraBuilder) {
+    let ground = Arc::new(Material::from(CoatedDiffuse::new(
+        Color::new(0.45, 0.3, 0.18),
+        1.5,
+        0.1,
+    )));
+    let painted = Arc::new(painted_material(maps));
+    // Gold hammered into ripples
+    let glossy = Arc::new(Material::from(
+        Conductor::new(ComplexIor::GOLD, 0.2, 0.2).with_normal_map(NormalMap::bump(
+            NoiseTexture::new(3.0, Color::default(), Color::new(1.0, 1.0, 1.0)),
+            0.01,
+        )),
+    ));
+    let sandstone = Arc::new(sandstone_material());
+    let screen = Arc::new(screen_material());
+    let rusted = Arc::new(rusted_iron_material());
+    let glass = Arc::new(Material::from(RoughDielectric::new(
+        1.5,
+        0.15,
+        Color::new(0.6, 0.1, 0.4),
+    )));
+
+    let mut hittables: Vec<Arc<dyn RayIntersection>> = vec![
+        Arc::new(Hittable::from(Quad::new(
+            &Point3::new(-20.0, 0.0, 20.0),
+            &Vec3::new(40.0, 0.0, 0.0),
+            &Vec3::new(0.0, 0.0, -40.0),
+            &ground,
+        ))),
+        Arc::new(Hittable::from(Sphere::new(
+            &Point3::new(-2.2, 1.0, 0.0),
+            1.0,
+            &painted,
+        ))),
+        Arc::new(Hittable::from(Sphere::new(
+            &Point3::new(0.0, 1.0, 0.0),
+            1.0,
+            &glossy,
+        ))),
+        Arc::new(Hittable::from(Sphere::new(
+            &Point3::new(2.2, 1.0, 0.0),
+            1.0,
+            &glass,
+        ))),
+    ];
+
+    hittables.push(Arc::new(Hittable::from(Sphere::new(
+        &Point3::new(4.0, 0.7, 0.6),
+        0.7,
+        &rusted,
+    ))));
+    hittables.push(Arc::new(Hittable::from(Quad::new(
+        &Point3::new(-3.0, 0.0, -3.0),
+        &Vec3::new(6.0, 0.0, 0.0),
+        &Vec3::new(0.0, 3.0, 0.0),
+        &screen,
+    ))));
+    hittables.extend(
+        pyramid(&Point3::new(-4.0, 0.0, -1.0), 0.8, 1.5, &sandstone)
+            .map(|face| Arc::new(Hittable::from(face)) as Arc<dyn RayIntersection>),
+    );
+
+    // A row of small swatches in front of the main spheres
+    for (i, swatch) in (0u8..).zip(swatch_materials()) {
+        hittables.push(Arc::new(Hittable::from(Sphere::new(
+            &Point3::new(f64::from(i) - 3.0, 0.4, 2.2),
+            0.4,
+            &Arc::new(swatch),
+        ))));
+    }
+
+    let mut world = HittableCollection::default();
+    world.add(Arc::new(BvhNode::from(&mut hittables)));
+
+    let lights = vec![
+        Light::from(SpotLight::new(
+            Point3::new(1.0, 6.0, 5.0),
+            Point3::new(0.0, 0.5, 0.0),
+            Color::new(60.0, 57.0, 50.0),
+            30.0,
+            20.0,
+        )),
+        Light::from(PointLight::new(
+            Point3::new(-5.0, 3.0, 4.0),
+            Color::new(8.0, 9.0, 12.0),
+        )),
+        Light::from(DirectionalLight::new(
+            Vec3::new(-1.0, 1.5, -2.0),
+            Color::new(0.8, 0.7, 0.6),
+            3.0,
+        )),
+    ];
+
+    let camera_builder = CameraBuilder::default()
+        .aspect_ratio(16.0 / 9.0)
+        .vertical_fov(35.0)
+        .look_from(&Point3::new(0.0, 3.0, 9.0))
+        .look_at(&Point3::new(0.0, 0.8, 0.0))
+        .v_up(&Vec3::new(0.0, 1.0, 0.0))
+        .defocus_angle(0.0);
+
+    (
+        Scene::new(
+            world,
+            lights,
+            Background::Solid(Color::new(0.02, 0.02, 0.03)),
+        ),
+        camera_builder,
+    )
+}
+
+// Thin films under an open sky: soap bubbles whose walls swirl between thicknesses, coated
+// glass and tinted metal
+fn iridescence_scene() -> (Scene, CameraBuilder) {
+    let ground = Arc::new(Material::from(Lambertian::new(Color::new(
+        0.35, 0.35, 0.38,
+    ))));
+
+    // Film thicknesses are in nanometres, swirling across each bubble with the noise
+    let bubble_film = |scale| {
+        ThinFilm::new(
+            NoiseTexture::new(
+                scale,
+                Color::new(150.0, 150.0, 150.0),
+                Color::new(650.0, 650.0, 650.0),
+            ),
+            1.33,
+        )
+    };
+    let bubbles = [
+        (Point3::new(-1.6, 1.3, 0.0), 0.9, 1.5),
+        (Point3::new(0.4, 1.7, -1.5), 1.1, 1.0),
+        (Point3::new(0.9, 0.75, 1.2), 0.55, 2.5),
+    ];
+
+    // A quarter-wave magnesium fluoride coating on crown glass, like a camera lens
+    let coated_glass = Arc::new(Material::from(
+        Dielectric::with_refractive_index(RefractiveIndex::CROWN_GLASS)
+            .with_thin_film(ThinFilm::new(100.0, 1.38)),
+    ));
+    // Aluminium under a titanium dioxide layer, coloured like heat-tinted metal
+    let tinted = Arc::new(Material::from(
+        Conductor::new(ComplexIor::ALUMINIUM, 0.1, 0.1).with_thin_film(ThinFilm::new(300.0, 2.2)),
+    ));
+
+    let mut hittables: Vec<Arc<dyn RayIntersection>> = vec![
+        Arc::new(Hittable::from(Sphere::new(
+            &Point3::new(0.0, -1000.0, 0.0),
+            1000.0,
+            &ground,
+        ))),
+        Arc::new(Hittable::from(Sphere::new(
+            &Point3::new(-2.4, 0.6, 2.0),
+            0.6,
+            &coated_glass,
+        ))),
+        Arc::new(Hittable::from(Sphere::new(
+            &Point3::new(2.6, 0.8, 0.2),
+            0.8,
+            &tinted,
+        ))),
+    ];
+    for (center, radius, scale) in bubbles {
+        let material = Arc::new(Material::from(SoapBubble::new(bubble_film(scale))));
+        hittables.push(Arc::new(Hittable::from(Sphere::new(
+            &center, radius, &material,
+        ))));
+    }
+
+    let mut world = HittableCollection::default();
+    world.add(Arc::new(BvhNode::from(&mut hittables)));
+
+    let camera_builder = CameraBuilder::default()
+        .aspect_ratio(16.0 / 9.0)
+        .vertical_fov(35.0)
+        .look_from(&Point3::new(0.0, 1.5, 7.0))
+        .look_at(&Point3::new(0.0, 1.0, 0.0))
+        .v_up(&Vec3::new(0.0, 1.0, 0.0))
+        .defocus_angle(0.0);
+
+    (
+        Scene::new(world, Vec::new(), Background::Sky),
+        camera_builder,
+    )
+}
+
+// The four sides of a square pyramid standing on `base_center`, each mapped with v running up
+// to the apex
+fn pyramid(
+    base_center: &Point3,
+    half_width: f64,
+    height: f64,
+    mat: &Arc<Material>,
+) -> [Triangle; 4] {
+    let apex = base_center + Vec3::new(0.0, height, 0.0);
+    let corners = [(-1.0, 1.0), (1.0, 1.0), (1.0, -1.0), (-1.0, -1.0)]
+        .map(|(x, z)| base_center + half_width * Vec3::new(x, 0.0, z));
+
+    std::array::from_fn(|i| {
+        Triangle::new(&corners[i], &corners[(i + 1) % 4], &apex, mat).with_uvs([
+            [0.0, 0.0],
+            [1.0, 0.0],
+            [0.5, 1.0],
+        ])
+    })
+}
+
+// Bare metals, frosted glass, glazed ceramic with a slight orange peel and a glowing ember under
+// glaze
+fn swatch_materials() -> [Material; 7] {
+    [
+        Material::from(Conductor::new(ComplexIor::COPPER, 0.05, 0.05)),
+        Material::from(Conductor::new(ComplexIor::ALUMINIUM, 0.4, 0.05)),
+        Material::from(Conductor::new(ComplexIor::SILVER, 0.0, 0.0)),
+        Material::from(Conductor::new(ComplexIor::GOLD, 0.5, 0.5)),
+        Material::from(
+            PrincipledBuilder::default()
+                .base_color(Color::new(0.75, 0.9, 1.0))
+                .roughness(0.3)
+                .transmission(1.0)
+                .ior(1.45)
+                .build(),
+        ),
+        Material::from(
+            CoatedDiffuse::new(Color::new(0.9, 0.88, 0.82), 1.5, 0.0).with_normal_map(
+                NormalMap::bump(
+                    NoiseTexture::new(40.0, Color::default(), Color::new(1.0, 1.0, 1.0)),
+                    0.0005,
+                ),
+            ),
+        ),
+        Material::from(Add::new(
+            CoatedDiffuse::new(Color::new(0.05, 0.05, 0.05), 1.5, 0.0),
+            DiffuseLight::new(Color::new(4.0, 1.2, 0.3)),
+        )),
+    ]
+}
+
+// A perforated sheet of brushed aluminium, cut out where its checker alpha is 0. In the lookdev
+// scene the sun behind it shines through the holes onto the floor.
+fn screen_material() -> Material {
+    Material::from(Masked::new(
+        Conductor::new(ComplexIor::ALUMINIUM, 0.3, 0.3),
+        CheckerTexture::new(10.0, Color::default(), Color::new(1.0, 1.0, 1.0)),
+    ))
+}
+
+// Patches of rust eating into bare iron, where the noise is bright
+fn rusted_iron_material() -> Material {
+    Material::from(Mix::new(
+        Metal::new(Color::new(0.56, 0.57, 0.58), 0.15),
+        Lambertian::new(Color::new(0.42, 0.18, 0.07)),
+        NoiseTexture::new(4.0, Color::default(), Color::new(1.0, 1.0, 1.0)),
+    ))
+}
+
+// Rough stone with a grainy surface
+fn sandstone_material() -> Material {
+    Material::from(
+        PrincipledBuilder::default()
+            .base_color(Color::new(0.76, 0.6, 0.42))
+            .roughness(0.9)
+            .specular(0.3)
+            .normal_map(NormalMap::bump(
+                NoiseTexture::new(12.0, Color::default(), Color::new(1.0, 1.0, 1.0)),
+                0.005,
+            ))
+            .build(),
+    )
+}
+
+// Image maps given on the command line, which replace the painted sphere's textures in the lookdev
+// scene so exported assets can be checked under its lights
+#[derive(Clone, Debug, Default)]
+struct TextureMaps {
+    base_color: Option<Texture>,
+    metallic: Option<Texture>,
+    roughness: Option<Texture>,
+    normal: Option<Texture>,
+}
+
+impl TextureMaps {
+    fn load(options: &Options) -> io::Result<Self> {
+        let load = |path: Option<&PathBuf>| {
+            path.map(|path| ImageTexture::load(path).map(Texture::from))
+                .transpose()
+        };
+
+        Ok(Self {
+            base_color: load(options.base_color_map())?,
+            metallic: load(options.metallic_map())?,
+            roughness: load(options.roughness_map())?,
+            normal: load(options.normal_map())?,
+        })
+    }
+}
+
+// Lacquered red paint with gold leaf squares, like an exported asset with texture maps
+fn painted_material(maps: &TextureMaps) -> Material {
+    let mut builder = PrincipledBuilder::default();
+    if let Some(normals) = &maps.normal {
+        builder = builder.normal_map(NormalMap::tangent_space(normals.clone()));
+    }
+
+    Material::from(
+        builder
+            .base_color(maps.base_color.clone().unwrap_or_else(|| {
+                Texture::from(CheckerTexture::new(
+                    8.0,
+                    Color::new(0.7, 0.15, 0.1),
+                    Color::new(1.0, 0.78, 0.34),
+                ))
+            }))
+            .metallic(maps.metallic.clone().unwrap_or_else(|| {
+                Texture::from(CheckerTexture::new(
+                    8.0,
+                    Color::default(),
+                    Color::new(1.0, 1.0, 1.0),
+                ))
+            }))
+            .roughness(
+                maps.roughness
+                    .clone()
+                    .unwrap_or_else(|| Texture::from(0.35)),
+            )
+            .specular(0.6)
+            .sheen(0.5)
+            .sheen_tint(0.8)
+            .clearcoat(1.0)
+            .clearcoat_gloss(0.9)
+            .build(),
+    )
 }
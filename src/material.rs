@@ -1,16 +1,99 @@
-use crate::{color::Color, hittable::HitRecord, ray::Ray, vec3::random_unit_vector};
+use crate::{
+    color::Color,
+    hittable::HitRecord,
+    microfacet::{
+        ComplexIor, RefractiveIndex, TrowbridgeReitz, diffuse_fresnel_reflectance,
+        fresnel_dielectric, fresnel_thin_film, refract,
+    },
+    sampler::{SampleGenerator, Sampler},
+    spectrum::reflectance_to_color,
+    texture::{NormalMap, Texture, TextureLookup},
+    vec3::{
+        Onb, Point3, Vec3, cosine_hemisphere_pdf, sample_cosine_hemisphere, sample_uniform_sphere,
+        uniform_sphere_pdf,
+    },
+};
 use enum_dispatch::enum_dispatch;
-use rand::RngExt;
+use std::{f64::consts::FRAC_1_PI, ops::BitOr, sync::Arc};
 
+// Kinds of scattering a sampled direction came from. Specular lobes are delta distributions, so
+// they can't be evaluated for arbitrary directions or lit by sampling lights.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Lobe(u8);
+
+impl Lobe {
+    pub const REFLECTION: Self = Self(1);
+    pub const TRANSMISSION: Self = Self(1 << 1);
+    pub const DIFFUSE: Self = Self(1 << 2);
+    pub const GLOSSY: Self = Self(1 << 3);
+    pub const SPECULAR: Self = Self(1 << 4);
+
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl BitOr for Lobe {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+// A direction chosen by a material. `weight` is the BSDF times the cosine term divided by `pdf`,
+// i.e. the factor the path throughput is multiplied by. For specular lobes `pdf` is the discrete
+// probability of picking the lobe.
+#[derive(Clone, Copy, Debug)]
+pub struct BsdfSample {
+    pub direction: Vec3,
+    pub weight: Color,
+    pub pdf: f64,
+    pub lobe: Lobe,
+}
+
+// Directions are unit vectors pointing away from the surface: `wo` towards where the light is
+// going, i.e. back along the incoming ray, and `wi` towards where it comes from
 #[enum_dispatch(Material)]
 pub trait Scatter {
-    fn scatter(
-        &self,
-        r_in: &Ray,
-        rec: &HitRecord,
-        attenuation: &mut Color,
-        scattered: &mut Ray,
-    ) -> bool;
+    fn scatter(&self, wo: &Vec3, rec: &HitRecord, sampler: &mut Sampler) -> Option<BsdfSample>;
+
+    // The BSDF times the cosine between `wi` and the normal. Specular lobes are not included.
+    fn eval(&self, _wo: &Vec3, _wi: &Vec3, _rec: &HitRecord) -> Color {
+        Color::default()
+    }
+
+    // Solid angle density of `scatter` choosing `wi`. Specular lobes are not included.
+    fn pdf(&self, _wo: &Vec3, _wi: &Vec3, _rec: &HitRecord) -> f64 {
+        0.0
+    }
+
+    fn emitted(&self, _rec: &HitRecord) -> Color {
+        Color::default()
+    }
+
+    // Coverage of the surface, from 0 where it is cut away to 1 where it is solid
+    fn alpha(&self, _uv: [f64; 2], _p: &Point3) -> f64 {
+        1.0
+    }
+
+    // The normal to scatter about in place of the geometric one, from a normal or bump map, on the
+    // outward side of the surface
+    fn shading_normal(&self, _rec: &HitRecord) -> Option<Vec3> {
+        None
+    }
+
+    // Fraction of light surviving `distance` through the material's interior, applied to rays that
+    // reach its surface from inside at `rec`
+    fn transmittance(&self, _rec: &HitRecord, _distance: f64) -> Color {
+        Color::new(1.0, 1.0, 1.0)
+    }
+
+    // Whether scattering depends on the wavelength in a way RGB colors can't describe, so a
+    // spectral path can only carry its hero wavelength from here on
+    fn depends_on_wavelength(&self) -> bool {
+        false
+    }
 }
 
 #[derive(Clone, Debug, Default)]
@@ -31,22 +114,24 @@ impl From<Color> for Lambertian {
 }
 
 impl Scatter for Lambertian {
-    fn scatter(
-        &self,
-        r_in: &Ray,
-        rec: &HitRecord,
-        attenuation: &mut Color,
-        scattered: &mut Ray,
-    ) -> bool {
-        let mut scatter_direction = rec.normal() + random_unit_vector();
+    fn scatter(&self, wo: &Vec3, rec: &HitRecord, sampler: &mut Sampler) -> Option<BsdfSample> {
+        let wi = rec
+            .shading_frame()
+            .local_to_world(&sample_cosine_hemisphere(sampler.get_2d()));
+        Some(BsdfSample {
+            direction: wi,
+            weight: self.albedo,
+            pdf: self.pdf(wo, &wi, rec),
+            lobe: Lobe::DIFFUSE | Lobe::REFLECTION,
+        })
+    }
 
-        if scatter_direction.near_zero() {
-            scatter_direction = *rec.normal();
-        }
+    fn eval(&self, wo: &Vec3, wi: &Vec3, rec: &HitRecord) -> Color {
+        self.pdf(wo, wi, rec) * self.albedo
+    }
 
-        *scattered = Ray::with_time(rec.p(), &scatter_direction, r_in.time());
-        *attenuation = self.albedo;
-        true
+    fn pdf(&self, _wo: &Vec3, wi: &Vec3, rec: &HitRecord) -> f64 {
+        cosine_hemisphere_pdf(rec.shading_normal().dot(wi))
     }
 }
 
@@ -66,29 +151,241 @@ impl Metal {
 }
 
 impl Scatter for Metal {
-    fn scatter(
+    fn scatter(&self, wo: &Vec3, rec: &HitRecord, sampler: &mut Sampler) -> Option<BsdfSample> {
+        let reflected = (-wo).reflect(rec.shading_normal());
+        let fuzzed = reflected + (self.fuzz * sample_uniform_sphere(sampler.get_2d()));
+
+        // Fuzzed directions pointing into the surface are absorbed
+        if fuzzed.dot(rec.shading_normal()) <= 0.0 {
+            return None;
+        }
+
+        let wi = fuzzed.unit_vector();
+        Some(if self.fuzz > 0.0 {
+            BsdfSample {
+                direction: wi,
+                weight: self.albedo,
+                pdf: self.pdf(wo, &wi, rec),
+                lobe: Lobe::GLOSSY | Lobe::REFLECTION,
+            }
+        } else {
+            BsdfSample {
+                direction: wi,
+                weight: self.albedo,
+                pdf: 1.0,
+                lobe: Lobe::SPECULAR | Lobe::REFLECTION,
+            }
+        })
+    }
+
+    fn eval(&self, wo: &Vec3, wi: &Vec3, rec: &HitRecord) -> Color {
+        self.pdf(wo, wi, rec) * self.albedo
+    }
+
+    // Fuzzed directions are uniform on a sphere of radius `fuzz` around the tip of the unit mirror
+    // direction. A direction's density sums the area density of both points where it crosses that
+    // sphere, converted to solid angle, which simplifies to a closed form in the cosine between the
+    // direction and the mirror direction.
+    fn pdf(&self, wo: &Vec3, wi: &Vec3, rec: &HitRecord) -> f64 {
+        if self.fuzz <= 0.0 || wi.dot(rec.shading_normal()) <= 0.0 {
+            return 0.0;
+        }
+
+        let reflected = (-wo).reflect(rec.shading_normal());
+        let cosine = wi.dot(&reflected);
+        let one_minus_fuzz_squared = self.fuzz.mul_add(-self.fuzz, 1.0);
+        let discriminant = cosine.mul_add(cosine, -one_minus_fuzz_squared);
+        if cosine <= 0.0 || discriminant <= 0.0 {
+            return 0.0;
+        }
+
+        let squared_distance_sum = (4.0 * cosine).mul_add(cosine, -2.0 * one_minus_fuzz_squared);
+        uniform_sphere_pdf() * squared_distance_sum / (self.fuzz * discriminant.sqrt())
+    }
+}
+
+// A transparent film a few hundred nm thick on top of a surface, like soap, oil or an
+// antireflection coating. Light reflected off its top and bottom interferes, so its reflectance
+// changes color with the film's thickness in nm, which can be textured, and the viewing angle.
+#[derive(Clone, Debug)]
+pub struct ThinFilm {
+    thickness: Arc<Texture>,
+    ior: f64,
+}
+
+impl ThinFilm {
+    pub fn new(thickness: impl Into<Texture>, ior: f64) -> Self {
+        Self {
+            thickness: Arc::new(thickness.into()),
+            ior,
+        }
+    }
+
+    // Reflectance for a ray arriving at `cos_theta`, where `indices` gives the refractive index on
+    // the ray's side and the complex one beneath the film at a wavelength. Spectral paths see it at
+    // their hero wavelength, while RGB integrates it over the spectrum.
+    fn reflectance(
         &self,
-        r_in: &Ray,
         rec: &HitRecord,
-        attenuation: &mut Color,
-        scattered: &mut Ray,
-    ) -> bool {
-        let reflected = r_in.dir().reflect(rec.normal());
-        let fuzzed = reflected.unit_vector() + (self.fuzz * random_unit_vector());
-        *scattered = Ray::with_time(rec.p(), &fuzzed, r_in.time());
-        *attenuation = self.albedo;
-        scattered.dir().dot(rec.normal()) > 0.0
+        cos_theta: f64,
+        indices: impl Fn(f64) -> (f64, (f64, f64)),
+    ) -> Color {
+        let thickness = self.thickness.scalar(rec.uv(), rec.p()).max(0.0);
+        let at = |lambda: f64| {
+            let (outer, substrate) = indices(lambda);
+            fresnel_thin_film(cos_theta, lambda, outer, self.ior, thickness, substrate)
+        };
+        rec.wavelength().map_or_else(
+            || reflectance_to_color(at),
+            |lambda| {
+                let reflectance = at(lambda);
+                Color::new(reflectance, reflectance, reflectance)
+            },
+        )
+    }
+}
+
+// A metal with microfacet roughness, reflecting by the Fresnel equations for its complex index of
+// refraction. `alpha_x` and `alpha_y` are the roughness along the surface's u and v tangents, so
+// brushing follows the surface coordinates.
+#[derive(Clone, Debug, Default)]
+pub struct Conductor {
+    ior: ComplexIor,
+    distribution: TrowbridgeReitz,
+    film: Option<ThinFilm>,
+    normal_map: Option<NormalMap>,
+}
+
+impl Conductor {
+    pub const fn new(ior: ComplexIor, alpha_x: f64, alpha_y: f64) -> Self {
+        Self {
+            ior,
+            distribution: TrowbridgeReitz::new(alpha_x, alpha_y),
+            film: None,
+            normal_map: None,
+        }
+    }
+
+    // An oxide or coating on the metal, like the colors of heat-tinted steel
+    pub fn with_thin_film(mut self, film: ThinFilm) -> Self {
+        self.film = Some(film);
+        self
+    }
+
+    pub fn with_normal_map(mut self, normal_map: NormalMap) -> Self {
+        self.normal_map = Some(normal_map);
+        self
+    }
+
+    fn fresnel(&self, rec: &HitRecord, cos_theta: f64) -> Color {
+        self.film.as_ref().map_or_else(
+            || self.ior.fresnel(cos_theta),
+            |film| film.reflectance(rec, cos_theta, |lambda| (1.0, self.ior.at(lambda))),
+        )
+    }
+}
+
+impl Scatter for Conductor {
+    fn scatter(&self, wo: &Vec3, rec: &HitRecord, sampler: &mut Sampler) -> Option<BsdfSample> {
+        let u = sampler.get_2d();
+        let frame = rec.shading_frame();
+        let outgoing = frame.world_to_local(wo);
+        if outgoing.z() <= 0.0 {
+            return None;
+        }
+
+        if self.distribution.is_smooth() {
+            return Some(BsdfSample {
+                direction: frame.local_to_world(&Vec3::new(
+                    -outgoing.x(),
+                    -outgoing.y(),
+                    outgoing.z(),
+                )),
+                weight: self.fresnel(rec, outgoing.z()),
+                pdf: 1.0,
+                lobe: Lobe::SPECULAR | Lobe::REFLECTION,
+            });
+        }
+
+        let incoming = self.distribution.sample_reflection(&outgoing, u)?;
+        let wi = frame.local_to_world(&incoming);
+        let pdf = self.pdf(wo, &wi, rec);
+        if pdf <= 0.0 {
+            return None;
+        }
+
+        Some(BsdfSample {
+            direction: wi,
+            weight: self.eval(wo, &wi, rec) * pdf.recip(),
+            pdf,
+            lobe: Lobe::GLOSSY | Lobe::REFLECTION,
+        })
+    }
+
+    fn eval(&self, wo: &Vec3, wi: &Vec3, rec: &HitRecord) -> Color {
+        if self.distribution.is_smooth() {
+            return Color::default();
+        }
+
+        let frame = rec.shading_frame();
+        let wo = frame.world_to_local(wo);
+        let wi = frame.world_to_local(wi);
+        if wo.z() <= 0.0 || wi.z() <= 0.0 {
+            return Color::default();
+        }
+
+        let wm = (wo + wi).unit_vector();
+        self.distribution.reflection_eval(&wo, &wi, &wm) * self.fresnel(rec, wo.dot(&wm))
+    }
+
+    fn pdf(&self, wo: &Vec3, wi: &Vec3, rec: &HitRecord) -> f64 {
+        if self.distribution.is_smooth() {
+            return 0.0;
+        }
+
+        let frame = rec.shading_frame();
+        let wo = frame.world_to_local(wo);
+        let wi = frame.world_to_local(wi);
+        if wo.z() <= 0.0 || wi.z() <= 0.0 {
+            return 0.0;
+        }
+
+        let wm = (wo + wi).unit_vector();
+        self.distribution.reflection_pdf(&wo, &wm)
+    }
+
+    fn depends_on_wavelength(&self) -> bool {
+        self.film.is_some()
+    }
+
+    fn shading_normal(&self, rec: &HitRecord) -> Option<Vec3> {
+        self.normal_map.as_ref().map(|map| map.shading_normal(rec))
     }
 }
 
 #[derive(Clone, Debug, Default)]
 pub struct Dielectric {
-    refraction_index: f64,
+    refraction_index: RefractiveIndex,
+    film: Option<ThinFilm>,
 }
 
 impl Dielectric {
     pub const fn new(refraction_index: f64) -> Self {
-        Self { refraction_index }
+        Self::with_refractive_index(RefractiveIndex::Constant(refraction_index))
+    }
+
+    // Glass whose index varies with wavelength splits white light into its colors when rendering
+    // spectrally
+    pub const fn with_refractive_index(refraction_index: RefractiveIndex) -> Self {
+        Self {
+            refraction_index,
+            film: None,
+        }
+    }
+
+    pub fn with_thin_film(mut self, film: ThinFilm) -> Self {
+        self.film = Some(film);
+        self
     }
 }
 
@@ -99,41 +396,1018 @@ impl From<f64> for Dielectric {
 }
 
 impl Scatter for Dielectric {
-    fn scatter(
-        &self,
-        r_in: &Ray,
-        rec: &HitRecord,
-        attenuation: &mut Color,
-        scattered: &mut Ray,
-    ) -> bool {
+    fn scatter(&self, wo: &Vec3, rec: &HitRecord, sampler: &mut Sampler) -> Option<BsdfSample> {
+        let refraction_index = self.refraction_index.at(rec.wavelength());
         let refraction_index_ratio = if rec.front_face() {
-            self.refraction_index.recip()
+            refraction_index.recip()
         } else {
-            self.refraction_index
+            refraction_index
         };
 
-        let unit_direction = r_in.dir().unit_vector();
-        let cos_theta = (-unit_direction.dot(rec.normal())).min(1.0);
+        let unit_direction = -wo;
+        let cos_theta = wo.dot(rec.shading_normal()).min(1.0);
         let sin_theta = (cos_theta.mul_add(-cos_theta, 1.0)).sqrt();
         let can_refract = (refraction_index_ratio * sin_theta) <= 1.0;
+        let (reflect_probability, reflect_weight, refract_weight) = match &self.film {
+            // The film sits on the outside, and whatever it doesn't reflect is refracted
+            Some(film) if can_refract => {
+                let reflectance = film.reflectance(rec, cos_theta, |lambda| {
+                    let n = self.refraction_index.at(Some(lambda));
+                    if rec.front_face() {
+                        (1.0, (n, 0.0))
+                    } else {
+                        (n, (1.0, 0.0))
+                    }
+                });
+                let probability = (reflectance.r() + reflectance.g() + reflectance.b()) / 3.0;
+                let transmittance = Color::new(
+                    1.0 - reflectance.r(),
+                    1.0 - reflectance.g(),
+                    1.0 - reflectance.b(),
+                );
+                (
+                    probability,
+                    reflectance * probability.recip(),
+                    transmittance * (1.0 - probability).recip(),
+                )
+            }
+            _ => (
+                if can_refract {
+                    fresnel_dielectric(cos_theta, refraction_index_ratio.recip())
+                } else {
+                    1.0
+                },
+                Color::new(1.0, 1.0, 1.0),
+                Color::new(1.0, 1.0, 1.0),
+            ),
+        };
 
-        let mut rng = rand::rng();
-        let refracted =
-            if !can_refract || reflectance(cos_theta, refraction_index_ratio) > rng.random() {
-                unit_direction.reflect(rec.normal())
-            } else {
-                unit_direction.refract(rec.normal(), refraction_index_ratio)
-            };
+        // Refraction squeezes radiance into the smaller solid angle on the denser side, scaling it
+        // by the squared ratio of the indices, like the transmission of the rough dielectric
+        let refract_weight = refraction_index_ratio * refraction_index_ratio * refract_weight;
 
-        *attenuation = Color::new(1.0, 1.0, 1.0);
-        *scattered = Ray::with_time(rec.p(), &refracted, r_in.time());
+        Some(if reflect_probability > sampler.get_1d() {
+            BsdfSample {
+                direction: unit_direction.reflect(rec.shading_normal()),
+                weight: reflect_weight,
+                pdf: reflect_probability,
+                lobe: Lobe::SPECULAR | Lobe::REFLECTION,
+            }
+        } else {
+            BsdfSample {
+                direction: unit_direction
+                    .refract(rec.shading_normal(), refraction_index_ratio)
+                    .unit_vector(),
+                weight: refract_weight,
+                pdf: 1.0 - reflect_probability,
+                lobe: Lobe::SPECULAR | Lobe::TRANSMISSION,
+            }
+        })
+    }
+
+    fn depends_on_wavelength(&self) -> bool {
+        self.refraction_index.is_dispersive() || self.film.is_some()
+    }
+}
+
+// A soap bubble, or any film with air on both sides. Light either reflects off the film or passes
+// straight through, as the film is far too thin to offset it.
+#[derive(Clone, Debug)]
+pub struct SoapBubble {
+    film: ThinFilm,
+}
+
+impl SoapBubble {
+    pub const fn new(film: ThinFilm) -> Self {
+        Self { film }
+    }
+}
+
+impl Scatter for SoapBubble {
+    fn scatter(&self, wo: &Vec3, rec: &HitRecord, sampler: &mut Sampler) -> Option<BsdfSample> {
+        let reflectance = self
+            .film
+            .reflectance(rec, wo.dot(rec.shading_normal()), |_| (1.0, (1.0, 0.0)));
+        let reflect_probability = (reflectance.r() + reflectance.g() + reflectance.b()) / 3.0;
+
+        Some(if reflect_probability > sampler.get_1d() {
+            BsdfSample {
+                direction: (-wo).reflect(rec.shading_normal()),
+                weight: reflectance * reflect_probability.recip(),
+                pdf: reflect_probability,
+                lobe: Lobe::SPECULAR | Lobe::REFLECTION,
+            }
+        } else {
+            BsdfSample {
+                direction: -wo,
+                weight: Color::new(
+                    1.0 - reflectance.r(),
+                    1.0 - reflectance.g(),
+                    1.0 - reflectance.b(),
+                ) * (1.0 - reflect_probability).recip(),
+                pdf: 1.0 - reflect_probability,
+                lobe: Lobe::SPECULAR | Lobe::TRANSMISSION,
+            }
+        })
+    }
+
+    fn depends_on_wavelength(&self) -> bool {
         true
     }
 }
 
-fn reflectance(cosine: f64, refraction_index: f64) -> f64 {
-    let r0 = ((1.0 - refraction_index) / (1.0 + refraction_index)).powi(2);
-    (1.0 - r0).mul_add((1.0 - cosine).powi(5), r0)
+// Glass with microfacet roughness that both reflects and refracts (Walter et al., "Microfacet
+// Models for Refraction through Rough Surfaces", 2007). `absorption` is the fraction of light
+// absorbed per unit distance inside, tinting thick glass by the Beer-Lambert law.
+#[derive(Clone, Debug, Default)]
+pub struct RoughDielectric {
+    refraction_index: f64,
+    distribution: TrowbridgeReitz,
+    absorption: Color,
+}
+
+impl RoughDielectric {
+    pub const fn new(refraction_index: f64, alpha: f64, absorption: Color) -> Self {
+        Self {
+            refraction_index,
+            distribution: TrowbridgeReitz::new(alpha, alpha),
+            absorption,
+        }
+    }
+
+    // The shading frame, and the refractive index across the surface relative to the side the
+    // normal faces
+    fn frame(&self, rec: &HitRecord) -> (Onb, f64) {
+        let eta = if rec.front_face() {
+            self.refraction_index
+        } else {
+            self.refraction_index.recip()
+        };
+        (rec.shading_frame(), eta)
+    }
+}
+
+impl Scatter for RoughDielectric {
+    fn scatter(&self, wo: &Vec3, rec: &HitRecord, sampler: &mut Sampler) -> Option<BsdfSample> {
+        let u = sampler.get_2d();
+        let choice = sampler.get_1d();
+        let (frame, eta) = self.frame(rec);
+        let outgoing = frame.world_to_local(wo);
+        if outgoing.z() <= 0.0 {
+            return None;
+        }
+
+        if self.distribution.is_smooth() {
+            let reflectance = fresnel_dielectric(outgoing.z(), eta);
+            let normal = Vec3::new(0.0, 0.0, 1.0);
+            return Some(if choice < reflectance {
+                BsdfSample {
+                    direction: (-wo).reflect(rec.shading_normal()),
+                    weight: Color::new(1.0, 1.0, 1.0),
+                    pdf: reflectance,
+                    lobe: Lobe::SPECULAR | Lobe::REFLECTION,
+                }
+            } else {
+                BsdfSample {
+                    direction: frame.local_to_world(&refract(&outgoing, &normal, eta)?),
+                    weight: (eta * eta).recip() * Color::new(1.0, 1.0, 1.0),
+                    pdf: 1.0 - reflectance,
+                    lobe: Lobe::SPECULAR | Lobe::TRANSMISSION,
+                }
+            });
+        }
+
+        let incoming = self
+            .distribution
+            .sample_dielectric(&outgoing, eta, u, choice)?;
+        let pdf = self.distribution.dielectric_pdf(&outgoing, &incoming, eta);
+        if pdf <= 0.0 {
+            return None;
+        }
+
+        let lobe = if incoming.z() > 0.0 {
+            Lobe::GLOSSY | Lobe::REFLECTION
+        } else {
+            Lobe::GLOSSY | Lobe::TRANSMISSION
+        };
+        Some(BsdfSample {
+            direction: frame.local_to_world(&incoming),
+            weight: (self.distribution.dielectric_eval(&outgoing, &incoming, eta) / pdf)
+                * Color::new(1.0, 1.0, 1.0),
+            pdf,
+            lobe,
+        })
+    }
+
+    fn eval(&self, wo: &Vec3, wi: &Vec3, rec: &HitRecord) -> Color {
+        if self.distribution.is_smooth() {
+            return Color::default();
+        }
+
+        let (frame, eta) = self.frame(rec);
+        let f = self.distribution.dielectric_eval(
+            &frame.world_to_local(wo),
+            &frame.world_to_local(wi),
+            eta,
+        );
+        f * Color::new(1.0, 1.0, 1.0)
+    }
+
+    fn pdf(&self, wo: &Vec3, wi: &Vec3, rec: &HitRecord) -> f64 {
+        if self.distribution.is_smooth() {
+            return 0.0;
+        }
+
+        let (frame, eta) = self.frame(rec);
+        self.distribution
+            .dielectric_pdf(&frame.world_to_local(wo), &frame.world_to_local(wi), eta)
+    }
+
+    fn transmittance(&self, _rec: &HitRecord, distance: f64) -> Color {
+        Color::new(
+            (-self.absorption.r() * distance).exp(),
+            (-self.absorption.g() * distance).exp(),
+            (-self.absorption.b() * distance).exp(),
+        )
+    }
+}
+
+// A diffuse base under a clear dielectric coat, as in plastics, varnished wood and ceramics
+// (Weidlich and Wilkie, "Arbitrarily Layered Micro-Facet Surfaces", 2007). The coat reflects by
+// the Fresnel equations, sharply or with microfacet roughness. Light it lets through scatters off
+// the base and bounces between base and coat, with the coat reflecting back a hemispherical
+// average of the light that reaches it from below.
+#[derive(Clone, Debug, Default)]
+pub struct CoatedDiffuse {
+    albedo: Color,
+    refraction_index: f64,
+    distribution: TrowbridgeReitz,
+    internal_reflectance: f64,
+    normal_map: Option<NormalMap>,
+}
+
+impl CoatedDiffuse {
+    pub fn new(albedo: Color, refraction_index: f64, alpha: f64) -> Self {
+        Self {
+            albedo,
+            refraction_index,
+            distribution: TrowbridgeReitz::new(alpha, alpha),
+            internal_reflectance: diffuse_fresnel_reflectance(refraction_index.recip()),
+            normal_map: None,
+        }
+    }
+
+    pub fn with_normal_map(mut self, normal_map: NormalMap) -> Self {
+        self.normal_map = Some(normal_map);
+        self
+    }
+
+    // Chance of sampling the coat rather than the base, from how much of the light the coat
+    // reflects and how bright the base is. An invisible coat over a black base reflects nothing,
+    // so either will do.
+    fn coat_probability(&self, cos_theta: f64) -> f64 {
+        let coat = fresnel_dielectric(cos_theta, self.refraction_index);
+        let base = (1.0 - coat) * self.albedo.luminance();
+        if coat + base > 0.0 {
+            coat / (coat + base)
+        } else {
+            0.0
+        }
+    }
+
+    // The base seen through the coat, in the shading frame and without the coat's own reflection
+    fn base_eval(&self, wo: &Vec3, wi: &Vec3) -> Color {
+        let eta = self.refraction_index;
+        let transmitted = (1.0 - fresnel_dielectric(wo.z(), eta))
+            * (1.0 - fresnel_dielectric(wi.z(), eta))
+            / (eta * eta);
+
+        // Light trapped under the coat adds up as a geometric series, one term per round trip
+        let bounces = Color::new(
+            self.albedo.r() / self.albedo.r().mul_add(-self.internal_reflectance, 1.0),
+            self.albedo.g() / self.albedo.g().mul_add(-self.internal_reflectance, 1.0),
+            self.albedo.b() / self.albedo.b().mul_add(-self.internal_reflectance, 1.0),
+        );
+        (transmitted * FRAC_1_PI * wi.z()) * bounces
+    }
+
+    fn eval_local(&self, wo: &Vec3, wi: &Vec3) -> Color {
+        if wo.z() <= 0.0 || wi.z() <= 0.0 {
+            return Color::default();
+        }
+
+        let base = self.base_eval(wo, wi);
+        if self.distribution.is_smooth() {
+            return base;
+        }
+
+        let wm = (wo + wi).unit_vector();
+        let coat = fresnel_dielectric(wo.dot(&wm), self.refraction_index)
+            * self.distribution.reflection_eval(wo, wi, &wm);
+        base + coat * Color::new(1.0, 1.0, 1.0)
+    }
+
+    fn pdf_local(&self, wo: &Vec3, wi: &Vec3) -> f64 {
+        if wo.z() <= 0.0 || wi.z() <= 0.0 {
+            return 0.0;
+        }
+
+        let coat_probability = self.coat_probability(wo.z());
+        let base = (1.0 - coat_probability) * cosine_hemisphere_pdf(wi.z());
+        if self.distribution.is_smooth() {
+            return base;
+        }
+
+        let wm = (wo + wi).unit_vector();
+        coat_probability.mul_add(self.distribution.reflection_pdf(wo, &wm), base)
+    }
+}
+
+impl Scatter for CoatedDiffuse {
+    fn scatter(&self, wo: &Vec3, rec: &HitRecord, sampler: &mut Sampler) -> Option<BsdfSample> {
+        let u = sampler.get_2d();
+        let choice = sampler.get_1d();
+        let frame = rec.shading_frame();
+        let outgoing = frame.world_to_local(wo);
+        if outgoing.z() <= 0.0 {
+            return None;
+        }
+
+        let coat_probability = self.coat_probability(outgoing.z());
+        let coat = choice < coat_probability;
+        if coat && self.distribution.is_smooth() {
+            let fresnel = fresnel_dielectric(outgoing.z(), self.refraction_index);
+            return Some(BsdfSample {
+                direction: frame.local_to_world(&Vec3::new(
+                    -outgoing.x(),
+                    -outgoing.y(),
+                    outgoing.z(),
+                )),
+                weight: (fresnel / coat_probability) * Color::new(1.0, 1.0, 1.0),
+                pdf: coat_probability,
+                lobe: Lobe::SPECULAR | Lobe::REFLECTION,
+            });
+        }
+
+        let (incoming, lobe) = if coat {
+            (
+                self.distribution.sample_reflection(&outgoing, u)?,
+                Lobe::GLOSSY | Lobe::REFLECTION,
+            )
+        } else {
+            (
+                sample_cosine_hemisphere(u),
+                Lobe::DIFFUSE | Lobe::REFLECTION,
+            )
+        };
+        let pdf = self.pdf_local(&outgoing, &incoming);
+        if pdf <= 0.0 {
+            return None;
+        }
+
+        Some(BsdfSample {
+            direction: frame.local_to_world(&incoming),
+            weight: self.eval_local(&outgoing, &incoming) * pdf.recip(),
+            pdf,
+            lobe,
+        })
+    }
+
+    fn eval(&self, wo: &Vec3, wi: &Vec3, rec: &HitRecord) -> Color {
+        let frame = rec.shading_frame();
+        self.eval_local(&frame.world_to_local(wo), &frame.world_to_local(wi))
+    }
+
+    fn pdf(&self, wo: &Vec3, wi: &Vec3, rec: &HitRecord) -> f64 {
+        let frame = rec.shading_frame();
+        self.pdf_local(&frame.world_to_local(wo), &frame.world_to_local(wi))
+    }
+
+    fn shading_normal(&self, rec: &HitRecord) -> Option<Vec3> {
+        self.normal_map.as_ref().map(|map| map.shading_normal(rec))
+    }
+}
+
+// The principled material popularized by Disney (Burley, "Physically Based Shading at Disney",
+// 2012, and "Extending the Disney BRDF to a BSDF with Integrated Subsurface Scattering", 2015). A
+// diffuse base with sheen is mixed with a GGX specular layer, a clearcoat and rough glass
+// transmission, all driven by artist-facing parameters in [0, 1] that can be textured.
+#[derive(Clone, Debug, Default)]
+pub struct Principled {
+    base_color: Arc<Texture>,
+    metallic: Arc<Texture>,
+    roughness: Arc<Texture>,
+    specular: Arc<Texture>,
+    sheen: Arc<Texture>,
+    sheen_tint: Arc<Texture>,
+    clearcoat: Arc<Texture>,
+    clearcoat_gloss: Arc<Texture>,
+    transmission: Arc<Texture>,
+    ior: Arc<Texture>,
+    normal_map: Option<NormalMap>,
+}
+
+impl Principled {
+    // Looks up every parameter at the hit point
+    fn lobes(&self, rec: &HitRecord) -> PrincipledLobes {
+        let (uv, p) = (rec.uv(), rec.p());
+        let base_color = self.base_color.value(uv, p);
+        let metallic = self.metallic.scalar(uv, p).clamp(0.0, 1.0);
+        let roughness = self.roughness.scalar(uv, p).clamp(0.0, 1.0);
+        let specular = self.specular.scalar(uv, p).max(0.0);
+        let sheen = self.sheen.scalar(uv, p).max(0.0);
+        let sheen_tint = self.sheen_tint.scalar(uv, p).clamp(0.0, 1.0);
+        let clearcoat = self.clearcoat.scalar(uv, p).clamp(0.0, 1.0);
+        let clearcoat_gloss = self.clearcoat_gloss.scalar(uv, p).clamp(0.0, 1.0);
+        let transmission = self.transmission.scalar(uv, p).clamp(0.0, 1.0);
+        let ior = self.ior.scalar(uv, p).max(1.0);
+
+        // Tints are the base color's hue and saturation at unit luminance
+        let white = Color::new(1.0, 1.0, 1.0);
+        let luminance = base_color.luminance();
+        let tint = if luminance > 0.0 {
+            luminance.recip() * base_color
+        } else {
+            white
+        };
+
+        let transmission_weight = (1.0 - metallic) * transmission;
+        let specular_weight = 1.0 - transmission_weight;
+        let diffuse_weight = (1.0 - metallic) * (1.0 - transmission);
+
+        // Dielectrics reflect 8% at most head on, scaled by `specular`; metals reflect their color
+        let f0 = ((1.0 - metallic) * 0.08 * specular) * white + metallic * base_color;
+
+        // Lobes are picked roughly by how much light they reflect. Specular gets a floor since
+        // Fresnel makes even weak reflection strong at grazing angles.
+        let probabilities = [
+            diffuse_weight,
+            specular_weight * f0.luminance().max(0.25),
+            0.25 * clearcoat,
+            transmission_weight,
+        ];
+        let total: f64 = probabilities.iter().sum();
+
+        PrincipledLobes {
+            base_color,
+            f0,
+            sheen: sheen * ((1.0 - sheen_tint) * white + sheen_tint * tint),
+            roughness,
+            clearcoat,
+            diffuse_weight,
+            specular_weight,
+            transmission_weight,
+            eta: if rec.front_face() { ior } else { ior.recip() },
+            specular: TrowbridgeReitz::new(
+                (roughness * roughness).max(1e-3),
+                (roughness * roughness).max(1e-3),
+            ),
+            clearcoat_distribution: TrowbridgeReitz::new(
+                0.099f64.mul_add(-clearcoat_gloss, 0.1),
+                0.099f64.mul_add(-clearcoat_gloss, 0.1),
+            ),
+            probabilities: probabilities.map(|p| p / total),
+        }
+    }
+}
+
+impl Scatter for Principled {
+    fn scatter(&self, wo: &Vec3, rec: &HitRecord, sampler: &mut Sampler) -> Option<BsdfSample> {
+        let u = sampler.get_2d();
+        let choice = sampler.get_1d();
+        let frame = rec.shading_frame();
+        let outgoing = frame.world_to_local(wo);
+        if outgoing.z() <= 0.0 {
+            return None;
+        }
+
+        let lobes = self.lobes(rec);
+        let (incoming, lobe) = lobes.sample(&outgoing, u, choice)?;
+        let pdf = lobes.pdf(&outgoing, &incoming);
+        if pdf <= 0.0 {
+            return None;
+        }
+
+        Some(BsdfSample {
+            direction: frame.local_to_world(&incoming),
+            weight: lobes.eval(&outgoing, &incoming) * pdf.recip(),
+            pdf,
+            lobe,
+        })
+    }
+
+    fn eval(&self, wo: &Vec3, wi: &Vec3, rec: &HitRecord) -> Color {
+        let frame = rec.shading_frame();
+        self.lobes(rec)
+            .eval(&frame.world_to_local(wo), &frame.world_to_local(wi))
+    }
+
+    fn pdf(&self, wo: &Vec3, wi: &Vec3, rec: &HitRecord) -> f64 {
+        let frame = rec.shading_frame();
+        self.lobes(rec)
+            .pdf(&frame.world_to_local(wo), &frame.world_to_local(wi))
+    }
+
+    fn shading_normal(&self, rec: &HitRecord) -> Option<Vec3> {
+        self.normal_map.as_ref().map(|map| map.shading_normal(rec))
+    }
+}
+
+// The principled material's parameters at one point, in the shading frame
+struct PrincipledLobes {
+    base_color: Color,
+    f0: Color,
+    sheen: Color,
+    roughness: f64,
+    clearcoat: f64,
+    diffuse_weight: f64,
+    specular_weight: f64,
+    transmission_weight: f64,
+    eta: f64,
+    specular: TrowbridgeReitz,
+    clearcoat_distribution: TrowbridgeReitz,
+    probabilities: [f64; 4],
+}
+
+impl PrincipledLobes {
+    const DIFFUSE: usize = 0;
+    const SPECULAR: usize = 1;
+    const CLEARCOAT: usize = 2;
+    const TRANSMISSION: usize = 3;
+
+    fn eval(&self, wo: &Vec3, wi: &Vec3) -> Color {
+        if wo.z() <= 0.0 || wi.z() == 0.0 {
+            return Color::default();
+        }
+
+        let glass = self.transmission_weight * self.specular.dielectric_eval(wo, wi, self.eta);
+        if wi.z() < 0.0 {
+            return glass * self.base_color;
+        }
+
+        let wm = (wo + wi).unit_vector();
+        let cos_d = wi.dot(&wm);
+
+        // Burley's diffuse adds retroreflection on rough surfaces and darkens smooth ones at
+        // grazing angles. As renormalized by Lagarde and de Rousiers ("Moving Frostbite to
+        // Physically Based Rendering", 2014) it no longer reflects more light than it receives.
+        let fd90 = (2.0 * self.roughness * cos_d).mul_add(cos_d, 0.5 * self.roughness);
+        let energy_factor = self.roughness.mul_add(1.0 / 1.51 - 1.0, 1.0);
+        let diffuse = ((fd90 - 1.0).mul_add(schlick_weight(wi.z()), 1.0)
+            * (fd90 - 1.0).mul_add(schlick_weight(wo.z()), 1.0)
+            * energy_factor
+            * FRAC_1_PI)
+            * self.base_color
+            + schlick_weight(cos_d) * self.sheen;
+
+        let specular =
+            schlick_fresnel(self.f0, wo.dot(&wm)) * self.specular.reflection_eval(wo, wi, &wm);
+        let clearcoat = 0.25
+            * self.clearcoat
+            * clearcoat_fresnel(wo.dot(&wm))
+            * self.clearcoat_distribution.reflection_eval(wo, wi, &wm);
+
+        // Each layer only gets the light the layers above it let through
+        let white = Color::new(1.0, 1.0, 1.0);
+        let below_specular = (1.0 - schlick_weight(wo.z()))
+            * Color::new(1.0 - self.f0.r(), 1.0 - self.f0.g(), 1.0 - self.f0.b());
+        let below_clearcoat = (0.25 * self.clearcoat).mul_add(-clearcoat_fresnel(wo.z()), 1.0);
+
+        below_clearcoat
+            * ((self.diffuse_weight * wi.z()) * (below_specular * diffuse)
+                + self.specular_weight * specular
+                + glass * white)
+            + clearcoat * white
+    }
+
+    fn pdf(&self, wo: &Vec3, wi: &Vec3) -> f64 {
+        if wo.z() <= 0.0 || wi.z() == 0.0 {
+            return 0.0;
+        }
+
+        let glass =
+            self.probabilities[Self::TRANSMISSION] * self.specular.dielectric_pdf(wo, wi, self.eta);
+        if wi.z() < 0.0 {
+            return glass;
+        }
+
+        let wm = (wo + wi).unit_vector();
+        self.probabilities[Self::DIFFUSE].mul_add(
+            cosine_hemisphere_pdf(wi.z()),
+            self.probabilities[Self::SPECULAR].mul_add(
+                self.specular.reflection_pdf(wo, &wm),
+                self.probabilities[Self::CLEARCOAT]
+                    .mul_add(self.clearcoat_distribution.reflection_pdf(wo, &wm), glass),
+            ),
+        )
+    }
+
+    // Picks a lobe with `choice` and samples a direction from it. What is left of `choice` after
+    // picking decides between reflection and refraction for glass.
+    fn sample(&self, wo: &Vec3, u: [f64; 2], choice: f64) -> Option<(Vec3, Lobe)> {
+        let mut remaining = choice;
+        let mut index = 0;
+        while index < Self::TRANSMISSION && remaining >= self.probabilities[index] {
+            remaining -= self.probabilities[index];
+            index += 1;
+        }
+
+        match index {
+            Self::DIFFUSE => Some((
+                sample_cosine_hemisphere(u),
+                Lobe::DIFFUSE | Lobe::REFLECTION,
+            )),
+            Self::SPECULAR => Some((
+                self.specular.sample_reflection(wo, u)?,
+                Lobe::GLOSSY | Lobe::REFLECTION,
+            )),
+            Self::CLEARCOAT => Some((
+                self.clearcoat_distribution.sample_reflection(wo, u)?,
+                Lobe::GLOSSY | Lobe::REFLECTION,
+            )),
+            _ => {
+                let remaining = (remaining / self.probabilities[Self::TRANSMISSION]).min(1.0);
+                let wi = self
+                    .specular
+                    .sample_dielectric(wo, self.eta, u, remaining)?;
+                let lobe = if wi.z() > 0.0 {
+                    Lobe::GLOSSY | Lobe::REFLECTION
+                } else {
+                    Lobe::GLOSSY | Lobe::TRANSMISSION
+                };
+                Some((wi, lobe))
+            }
+        }
+    }
+}
+
+pub struct PrincipledBuilder(Principled);
+
+impl PrincipledBuilder {
+    pub fn base_color(mut self, base_color: impl Into<Texture>) -> Self {
+        self.0.base_color = Arc::new(base_color.into());
+        self
+    }
+
+    pub fn metallic(mut self, metallic: impl Into<Texture>) -> Self {
+        self.0.metallic = Arc::new(metallic.into());
+        self
+    }
+
+    pub fn roughness(mut self, roughness: impl Into<Texture>) -> Self {
+        self.0.roughness = Arc::new(roughness.into());
+        self
+    }
+
+    pub fn specular(mut self, specular: impl Into<Texture>) -> Self {
+        self.0.specular = Arc::new(specular.into());
+        self
+    }
+
+    pub fn sheen(mut self, sheen: impl Into<Texture>) -> Self {
+        self.0.sheen = Arc::new(sheen.into());
+        self
+    }
+
+    pub fn sheen_tint(mut self, sheen_tint: impl Into<Texture>) -> Self {
+        self.0.sheen_tint = Arc::new(sheen_tint.into());
+        self
+    }
+
+    pub fn clearcoat(mut self, clearcoat: impl Into<Texture>) -> Self {
+        self.0.clearcoat = Arc::new(clearcoat.into());
+        self
+    }
+
+    pub fn clearcoat_gloss(mut self, clearcoat_gloss: impl Into<Texture>) -> Self {
+        self.0.clearcoat_gloss = Arc::new(clearcoat_gloss.into());
+        self
+    }
+
+    pub fn transmission(mut self, transmission: impl Into<Texture>) -> Self {
+        self.0.transmission = Arc::new(transmission.into());
+        self
+    }
+
+    pub fn ior(mut self, ior: impl Into<Texture>) -> Self {
+        self.0.ior = Arc::new(ior.into());
+        self
+    }
+
+    pub fn normal_map(mut self, normal_map: NormalMap) -> Self {
+        self.0.normal_map = Some(normal_map);
+        self
+    }
+
+    pub fn build(self) -> Principled {
+        self.0
+    }
+}
+
+impl Default for PrincipledBuilder {
+    fn default() -> Self {
+        Self(Principled {
+            base_color: Arc::new(Texture::from(0.8)),
+            metallic: Arc::new(Texture::from(0.0)),
+            roughness: Arc::new(Texture::from(0.5)),
+            specular: Arc::new(Texture::from(0.5)),
+            sheen: Arc::new(Texture::from(0.0)),
+            sheen_tint: Arc::new(Texture::from(0.5)),
+            clearcoat: Arc::new(Texture::from(0.0)),
+            clearcoat_gloss: Arc::new(Texture::from(1.0)),
+            transmission: Arc::new(Texture::from(0.0)),
+            ior: Arc::new(Texture::from(1.5)),
+            normal_map: None,
+        })
+    }
+}
+
+fn schlick_weight(cosine: f64) -> f64 {
+    (1.0 - cosine.clamp(0.0, 1.0)).powi(5)
+}
+
+fn schlick_fresnel(f0: Color, cosine: f64) -> Color {
+    let weight = schlick_weight(cosine);
+    (1.0 - weight) * f0 + weight * Color::new(1.0, 1.0, 1.0)
+}
+
+// The clearcoat is a polyurethane layer with an index of refraction of 1.5
+fn clearcoat_fresnel(cosine: f64) -> f64 {
+    schlick_fresnel(Color::new(0.04, 0.04, 0.04), cosine).r()
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct DiffuseLight {
+    emit: Color,
+}
+
+impl DiffuseLight {
+    pub const fn new(emit: Color) -> Self {
+        Self { emit }
+    }
+}
+
+impl From<Color> for DiffuseLight {
+    fn from(emit: Color) -> Self {
+        Self::new(emit)
+    }
+}
+
+impl Scatter for DiffuseLight {
+    fn scatter(&self, _wo: &Vec3, _rec: &HitRecord, _sampler: &mut Sampler) -> Option<BsdfSample> {
+        None
+    }
+
+    // Lights only emit from their front side
+    fn emitted(&self, rec: &HitRecord) -> Color {
+        if rec.front_face() {
+            self.emit
+        } else {
+            Color::default()
+        }
+    }
+}
+
+// Blends two materials by `amount`, from all `first` where it is 0 to all `second` where it is 1,
+// like rust eating into bare metal. Emission and scattering are blended as they are, so specular
+// and rough materials mix without bias, as is absorption inside them. The surface takes the normal
+// map of whichever material dominates.
+#[derive(Clone, Debug)]
+pub struct Mix {
+    first: Arc<Material>,
+    second: Arc<Material>,
+    amount: Arc<Texture>,
+}
+
+impl Mix {
+    pub fn new(
+        first: impl Into<Material>,
+        second: impl Into<Material>,
+        amount: impl Into<Texture>,
+    ) -> Self {
+        Self {
+            first: Arc::new(first.into()),
+            second: Arc::new(second.into()),
+            amount: Arc::new(amount.into()),
+        }
+    }
+
+    fn amount(&self, uv: [f64; 2], p: &Point3) -> f64 {
+        self.amount.scalar(uv, p).clamp(0.0, 1.0)
+    }
+}
+
+impl Scatter for Mix {
+    fn scatter(&self, wo: &Vec3, rec: &HitRecord, sampler: &mut Sampler) -> Option<BsdfSample> {
+        let amount = self.amount(rec.uv(), rec.p());
+        scatter_either(
+            [&self.first, &self.second],
+            [1.0 - amount, amount],
+            amount,
+            wo,
+            rec,
+            sampler,
+        )
+    }
+
+    fn eval(&self, wo: &Vec3, wi: &Vec3, rec: &HitRecord) -> Color {
+        let amount = self.amount(rec.uv(), rec.p());
+        (1.0 - amount) * self.first.eval(wo, wi, rec) + amount * self.second.eval(wo, wi, rec)
+    }
+
+    fn pdf(&self, wo: &Vec3, wi: &Vec3, rec: &HitRecord) -> f64 {
+        let amount = self.amount(rec.uv(), rec.p());
+        (1.0 - amount).mul_add(
+            self.first.pdf(wo, wi, rec),
+            amount * self.second.pdf(wo, wi, rec),
+        )
+    }
+
+    fn emitted(&self, rec: &HitRecord) -> Color {
+        let amount = self.amount(rec.uv(), rec.p());
+        (1.0 - amount) * self.first.emitted(rec) + amount * self.second.emitted(rec)
+    }
+
+    fn transmittance(&self, rec: &HitRecord, distance: f64) -> Color {
+        let amount = self.amount(rec.uv(), rec.p());
+        (1.0 - amount) * self.first.transmittance(rec, distance)
+            + amount * self.second.transmittance(rec, distance)
+    }
+
+    fn alpha(&self, uv: [f64; 2], p: &Point3) -> f64 {
+        let amount = self.amount(uv, p);
+        (1.0 - amount).mul_add(self.first.alpha(uv, p), amount * self.second.alpha(uv, p))
+    }
+
+    fn shading_normal(&self, rec: &HitRecord) -> Option<Vec3> {
+        if self.amount(rec.uv(), rec.p()) < 0.5 {
+            self.first.shading_normal(rec)
+        } else {
+            self.second.shading_normal(rec)
+        }
+    }
+
+    fn depends_on_wavelength(&self) -> bool {
+        self.first.depends_on_wavelength() || self.second.depends_on_wavelength()
+    }
+}
+
+// The sum of two materials, for layering emission onto a surface that also scatters, like a
+// glowing panel with a glossy face. Emission and scattering add up, so the result only conserves
+// energy when its materials together do. Normal maps come from `first` where it has one.
+#[derive(Clone, Debug)]
+pub struct Add {
+    first: Arc<Material>,
+    second: Arc<Material>,
+}
+
+impl Add {
+    pub fn new(first: impl Into<Material>, second: impl Into<Material>) -> Self {
+        Self {
+            first: Arc::new(first.into()),
+            second: Arc::new(second.into()),
+        }
+    }
+}
+
+impl Scatter for Add {
+    fn scatter(&self, wo: &Vec3, rec: &HitRecord, sampler: &mut Sampler) -> Option<BsdfSample> {
+        scatter_either(
+            [&self.first, &self.second],
+            [1.0, 1.0],
+            0.5,
+            wo,
+            rec,
+            sampler,
+        )
+    }
+
+    fn eval(&self, wo: &Vec3, wi: &Vec3, rec: &HitRecord) -> Color {
+        self.first.eval(wo, wi, rec) + self.second.eval(wo, wi, rec)
+    }
+
+    fn pdf(&self, wo: &Vec3, wi: &Vec3, rec: &HitRecord) -> f64 {
+        0.5 * (self.first.pdf(wo, wi, rec) + self.second.pdf(wo, wi, rec))
+    }
+
+    fn emitted(&self, rec: &HitRecord) -> Color {
+        self.first.emitted(rec) + self.second.emitted(rec)
+    }
+
+    fn transmittance(&self, rec: &HitRecord, distance: f64) -> Color {
+        self.first.transmittance(rec, distance) * self.second.transmittance(rec, distance)
+    }
+
+    // Either layer covers the surface
+    fn alpha(&self, uv: [f64; 2], p: &Point3) -> f64 {
+        let (first, second) = (self.first.alpha(uv, p), self.second.alpha(uv, p));
+        first.mul_add(-second, first + second)
+    }
+
+    fn shading_normal(&self, rec: &HitRecord) -> Option<Vec3> {
+        self.first
+            .shading_normal(rec)
+            .or_else(|| self.second.shading_normal(rec))
+    }
+
+    fn depends_on_wavelength(&self) -> bool {
+        self.first.depends_on_wavelength() || self.second.depends_on_wavelength()
+    }
+}
+
+// Cuts holes in any material, for foliage and fences modeled as flat cards. Where `alpha` is 0 the
+// surface isn't there and rays pass through it.
+#[derive(Clone, Debug)]
+pub struct Masked {
+    material: Arc<Material>,
+    alpha: Arc<Texture>,
+}
+
+impl Masked {
+    pub fn new(material: impl Into<Material>, alpha: impl Into<Texture>) -> Self {
+        Self {
+            material: Arc::new(material.into()),
+            alpha: Arc::new(alpha.into()),
+        }
+    }
+}
+
+impl Scatter for Masked {
+    fn scatter(&self, wo: &Vec3, rec: &HitRecord, sampler: &mut Sampler) -> Option<BsdfSample> {
+        self.material.scatter(wo, rec, sampler)
+    }
+
+    fn eval(&self, wo: &Vec3, wi: &Vec3, rec: &HitRecord) -> Color {
+        self.material.eval(wo, wi, rec)
+    }
+
+    fn pdf(&self, wo: &Vec3, wi: &Vec3, rec: &HitRecord) -> f64 {
+        self.material.pdf(wo, wi, rec)
+    }
+
+    fn emitted(&self, rec: &HitRecord) -> Color {
+        self.material.emitted(rec)
+    }
+
+    fn transmittance(&self, rec: &HitRecord, distance: f64) -> Color {
+        self.material.transmittance(rec, distance)
+    }
+
+    fn alpha(&self, uv: [f64; 2], p: &Point3) -> f64 {
+        self.alpha.scalar(uv, p).clamp(0.0, 1.0) * self.material.alpha(uv, p)
+    }
+
+    fn shading_normal(&self, rec: &HitRecord) -> Option<Vec3> {
+        self.material.shading_normal(rec)
+    }
+
+    fn depends_on_wavelength(&self) -> bool {
+        self.material.depends_on_wavelength()
+    }
+}
+
+// Samples one of two materials, the second with `second_probability`, for a combination that
+// scales them by `weights`. The sampled direction is weighed by both materials together, except
+// that specular directions can only have come from the material that sampled them.
+fn scatter_either(
+    materials: [&Material; 2],
+    weights: [f64; 2],
+    second_probability: f64,
+    wo: &Vec3,
+    rec: &HitRecord,
+    sampler: &mut Sampler,
+) -> Option<BsdfSample> {
+    let (index, probability) = if sampler.get_1d() < second_probability {
+        (1, second_probability)
+    } else {
+        (0, 1.0 - second_probability)
+    };
+    let sample = materials[index].scatter(wo, rec, sampler)?;
+
+    if sample.lobe.contains(Lobe::SPECULAR) {
+        return Some(BsdfSample {
+            weight: (weights[index] / probability) * sample.weight,
+            pdf: probability * sample.pdf,
+            ..sample
+        });
+    }
+
+    let wi = sample.direction;
+    let pdf = (1.0 - second_probability).mul_add(
+        materials[0].pdf(wo, &wi, rec),
+        second_probability * materials[1].pdf(wo, &wi, rec),
+    );
+    if pdf <= 0.0 {
+        return None;
+    }
+
+    let eval =
+        weights[0] * materials[0].eval(wo, &wi, rec) + weights[1] * materials[1].eval(wo, &wi, rec);
+    Some(BsdfSample {
+        weight: pdf.recip() * eval,
+        pdf,
+        ..sample
+    })
 }
 
 #[derive(Clone, Debug)]
@@ -141,7 +1415,16 @@ fn reflectance(cosine: f64, refraction_index: f64) -> f64 {
 pub enum Material {
     Lambertian(Lambertian),
     Metal(Metal),
+    Conductor(Conductor),
     Dielectric(Dielectric),
+    RoughDielectric(RoughDielectric),
+    SoapBubble(SoapBubble),
+    CoatedDiffuse(CoatedDiffuse),
+    Principled(Principled),
+    Mix(Mix),
+    Add(Add),
+    Masked(Masked),
+    DiffuseLight(DiffuseLight),
 }
 
 impl Default for Material {
@@ -149,3 +1432,212 @@ impl Default for Material {
         Self::Lambertian(Lambertian::default())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        hittable::{Quad, RayIntersection},
+        interval::Interval,
+        ray::Ray,
+        sampler::SamplerKind,
+    };
+
+    const SAMPLES: u32 = 1000;
+    const FURNACE_SAMPLES: u32 = 4096;
+
+    // Hits a square in the xy plane, facing +z, seen from `wo`. Directions below the plane hit its
+    // back face.
+    fn hit(material: impl Into<Material>, wo: &Vec3) -> HitRecord {
+        let quad = Quad::new(
+            &Point3::new(-1.0, -1.0, 0.0),
+            &Vec3::new(2.0, 0.0, 0.0),
+            &Vec3::new(0.0, 2.0, 0.0),
+            &Arc::new(material.into()),
+        );
+        let mut rec = HitRecord::default();
+        let r = Ray::new(wo, &-wo);
+        assert!(quad.hit(&r, Interval::new(1e-6, f64::INFINITY), &mut rec));
+        rec
+    }
+
+    fn direction(cos_theta: f64) -> Vec3 {
+        Vec3::new(cos_theta.mul_add(-cos_theta, 1.0).sqrt(), 0.0, cos_theta)
+    }
+
+    fn samplers() -> impl Iterator<Item = Sampler> {
+        (0..SAMPLES).map(|i| Sampler::new(SamplerKind::Independent, 7, 0, u64::from(i), SAMPLES))
+    }
+
+    // The direction and BSDF of each specular lobe a material samples from `wo`. For a delta lobe
+    // the weight times the probability of choosing it is the light it carries.
+    fn specular_lobes(material: &Material, wo: &Vec3) -> Vec<(Vec3, Color)> {
+        let rec = hit(material.clone(), wo);
+        let mut lobes: Vec<(Vec3, Color)> = Vec::new();
+        for mut sampler in samplers() {
+            let Some(sample) = material.scatter(wo, &rec, &mut sampler) else {
+                continue;
+            };
+            assert!(sample.lobe.contains(Lobe::SPECULAR));
+            let lobe = (sample.direction, sample.pdf * sample.weight);
+            if !lobes
+                .iter()
+                .any(|(direction, _)| (*direction - lobe.0).length() < 1e-9)
+            {
+                lobes.push(lobe);
+            }
+        }
+        lobes.sort_by(|a, b| a.0.z().total_cmp(&b.0.z()));
+        lobes
+    }
+
+    // Smooth glass refracts the same light whether it is modelled as a dielectric or as a rough
+    // dielectric without roughness, entering or leaving it, and up to total internal reflection
+    #[test]
+    fn smooth_dielectrics_agree() {
+        let smooth = Material::from(Dielectric::new(1.5));
+        let rough = Material::from(RoughDielectric::new(1.5, 0.0, Color::default()));
+        for cos_theta in [1.0, 0.7, 0.3, -1.0, -0.9, -0.5] {
+            let wo = direction(cos_theta);
+            let expected = specular_lobes(&smooth, &wo);
+            let actual = specular_lobes(&rough, &wo);
+            assert_eq!(expected.len(), actual.len(), "{cos_theta}");
+            for ((expected_direction, expected), (actual_direction, actual)) in
+                expected.iter().zip(&actual)
+            {
+                assert!((*expected_direction - *actual_direction).length() < 1e-9);
+                assert!(
+                    (Vec3::from(*expected) - Vec3::from(*actual)).length() < 1e-9,
+                    "{cos_theta}: {expected:?} {actual:?}"
+                );
+            }
+        }
+    }
+
+    // The mean weight of the directions a material scatters to from `wo`, which is the fraction of
+    // uniform white light it reflects or transmits. Also checks that `eval` and `pdf` agree with
+    // every sample they can describe.
+    fn albedo(material: &Material, wo: &Vec3) -> Color {
+        let rec = hit(material.clone(), wo);
+        let mut sum = Color::default();
+        for i in 0..FURNACE_SAMPLES {
+            let mut sampler = Sampler::new(SamplerKind::Sobol, 7, 0, u64::from(i), FURNACE_SAMPLES);
+            let Some(sample) = material.scatter(wo, &rec, &mut sampler) else {
+                continue;
+            };
+            if !sample.lobe.contains(Lobe::SPECULAR) {
+                let pdf = material.pdf(wo, &sample.direction, &rec);
+                let weight = Vec3::from(pdf.recip() * material.eval(wo, &sample.direction, &rec));
+                assert!(
+                    (pdf - sample.pdf).abs() <= 1e-6 * pdf,
+                    "{pdf} {}",
+                    sample.pdf
+                );
+                assert!(
+                    (weight - Vec3::from(sample.weight)).length() <= 1e-6 * weight.length(),
+                    "{weight:?} {:?}",
+                    sample.weight
+                );
+            }
+            sum += sample.weight;
+        }
+        (1.0 / f64::from(FURNACE_SAMPLES)) * sum
+    }
+
+    // A white furnace: lit evenly from every direction, a surface that doesn't create energy
+    // reflects at most the light it receives
+    fn assert_conserves_energy(material: impl Into<Material>, description: &str) {
+        let material = material.into();
+        for cos_theta in [1.0, 0.5, 0.1] {
+            let albedo = albedo(&material, &direction(cos_theta));
+            assert!(
+                [albedo.r(), albedo.g(), albedo.b()]
+                    .iter()
+                    .all(|&albedo| albedo <= 1.0 + 1e-2),
+                "{description} at {cos_theta}: {albedo:?}"
+            );
+        }
+    }
+
+    // Every combination of the parameters at their extremes
+    #[test]
+    fn principled_conserves_energy() {
+        for extremes in 0..64u32 {
+            let extreme = |bit: u32| f64::from((extremes >> bit) & 1);
+            let material = PrincipledBuilder::default()
+                .base_color(Color::new(1.0, 1.0, 1.0))
+                .metallic(extreme(0))
+                .roughness(extreme(1))
+                .specular(extreme(2))
+                .sheen(extreme(3))
+                .clearcoat(extreme(4))
+                .clearcoat_gloss(1.0 - extreme(1))
+                .transmission(extreme(5))
+                .build();
+            assert_conserves_energy(material, &format!("principled {extremes:06b}"));
+        }
+    }
+
+    #[test]
+    fn coated_diffuse_conserves_energy() {
+        for albedo in [0.0, 1.0] {
+            for refraction_index in [1.0, 1.5, 3.0] {
+                for alpha in [0.0, 0.3, 1.0] {
+                    let material = CoatedDiffuse::new(
+                        Color::new(albedo, albedo, albedo),
+                        refraction_index,
+                        alpha,
+                    );
+                    assert_conserves_energy(
+                        material,
+                        &format!("coated diffuse {albedo} {refraction_index} {alpha}"),
+                    );
+                }
+            }
+        }
+
+        // A coat matching the outside is invisible, leaving a white base to reflect everything
+        for alpha in [0.0, 0.5] {
+            let material =
+                Material::from(CoatedDiffuse::new(Color::new(1.0, 1.0, 1.0), 1.0, alpha));
+            for cos_theta in [1.0, 0.3] {
+                let albedo = albedo(&material, &direction(cos_theta));
+                assert!(
+                    (albedo.r() - 1.0).abs() < 1e-2,
+                    "{alpha} {cos_theta}: {albedo:?}"
+                );
+            }
+        }
+    }
+
+    // Mixing energy-conserving materials conserves energy, and reflects the blend of what they
+    // reflect on their own. Dielectrics mix a specular lobe into the rough one.
+    #[test]
+    fn mixes_conserve_energy() {
+        let white = Color::new(1.0, 1.0, 1.0);
+        let lambertian = Material::from(Lambertian::new(white));
+        for second in [
+            Material::from(Metal::new(white, 0.5)),
+            Material::from(Dielectric::new(1.5)),
+            Material::from(CoatedDiffuse::new(white, 1.5, 0.2)),
+        ] {
+            for amount in [0.0, 0.3, 1.0] {
+                let mix = Material::from(Mix::new(lambertian.clone(), second.clone(), amount));
+                assert_conserves_energy(mix.clone(), &format!("mix {amount} of {second:?}"));
+
+                for cos_theta in [1.0, 0.3] {
+                    let wo = direction(cos_theta);
+                    let expected = (1.0 - amount).mul_add(
+                        albedo(&lambertian, &wo).r(),
+                        amount * albedo(&second, &wo).r(),
+                    );
+                    let actual = albedo(&mix, &wo).r();
+                    assert!(
+                        (actual - expected).abs() < 2e-2,
+                        "{amount} of {second:?} at {cos_theta}: {actual} {expected}"
+                    );
+                }
+            }
+        }
+    }
+}
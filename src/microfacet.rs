@@ -0,0 +1,698 @@
+use crate::{
+    color::Color,
+    vec3::{Vec3, sample_uniform_disk},
+};
+use std::f64::consts::PI;
+
+// The Trowbridge-Reitz (GGX) distribution of microfacet normals, with separate roughness along the
+// x and y axes of the shading frame. Directions are in that frame, with the normal along +z.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TrowbridgeReitz {
+    alpha_x: f64,
+    alpha_y: f64,
+}
+
+impl TrowbridgeReitz {
+    pub const fn new(alpha_x: f64, alpha_y: f64) -> Self {
+        Self {
+            alpha_x: alpha_x.max(0.0),
+            alpha_y: alpha_y.max(0.0),
+        }
+    }
+
+    // Below this roughness the distribution is treated as a perfect mirror, since the sharp peak
+    // can't be evaluated reliably
+    pub const fn is_smooth(&self) -> bool {
+        self.alpha_x.max(self.alpha_y) < 1e-3
+    }
+
+    // Density of microfacet normals, per unit projected area of the surface
+    pub fn d(&self, wm: &Vec3) -> f64 {
+        let cos_2_theta = wm.z() * wm.z();
+        if cos_2_theta <= 0.0 {
+            return 0.0;
+        }
+
+        let x = wm.x() / self.alpha_x;
+        let y = wm.y() / self.alpha_y;
+        let e = x.mul_add(x, y * y) / cos_2_theta;
+        (PI * self.alpha_x * self.alpha_y * cos_2_theta * cos_2_theta * (1.0 + e) * (1.0 + e))
+            .recip()
+    }
+
+    // Smith's auxiliary function, the area of backfacing microfacets seen from `w` per unit of
+    // visible area
+    fn lambda(&self, w: &Vec3) -> f64 {
+        let cos_2_theta = w.z() * w.z();
+        if cos_2_theta <= 0.0 {
+            return f64::INFINITY;
+        }
+
+        let x = w.x() * self.alpha_x;
+        let y = w.y() * self.alpha_y;
+        let alpha_2_tan_2_theta = x.mul_add(x, y * y) / cos_2_theta;
+        0.5 * ((1.0 + alpha_2_tan_2_theta).sqrt() - 1.0)
+    }
+
+    // Fraction of microfacets visible from `w`
+    pub fn g1(&self, w: &Vec3) -> f64 {
+        (1.0 + self.lambda(w)).recip()
+    }
+
+    // Fraction of microfacets visible from both directions, with height-correlated masking and
+    // shadowing
+    pub fn g(&self, wo: &Vec3, wi: &Vec3) -> f64 {
+        (1.0 + self.lambda(wo) + self.lambda(wi)).recip()
+    }
+
+    // Density of the microfacet normals visible from `w`
+    pub fn visible_pdf(&self, w: &Vec3, wm: &Vec3) -> f64 {
+        let cos_theta = w.z().abs();
+        if cos_theta <= 0.0 {
+            return 0.0;
+        }
+
+        self.g1(w) / cos_theta * self.d(wm) * w.dot(wm).abs()
+    }
+
+    // The microfacet normal that reflects or refracts `wo` into `wi`, where `eta` is the refractive
+    // index across the surface. For refraction this is the generalized half vector, weighted by the
+    // refractive index. None when no microfacet facing both directions connects them.
+    pub fn half_vector(wo: &Vec3, wi: &Vec3, eta: f64) -> Option<Vec3> {
+        let eta = if wi.z() > 0.0 { 1.0 } else { eta };
+        let wm = eta * wi + wo;
+        if wm.length_squared() == 0.0 {
+            return None;
+        }
+
+        let wm = wm.unit_vector();
+        let wm = if wm.z() < 0.0 { -wm } else { wm };
+        if wm.dot(wi) * wi.z() < 0.0 || wm.dot(wo) * wo.z() < 0.0 {
+            return None;
+        }
+        Some(wm)
+    }
+
+    // The reflection BSDF times cos θi, without the Fresnel factor
+    pub fn reflection_eval(&self, wo: &Vec3, wi: &Vec3, wm: &Vec3) -> f64 {
+        self.d(wm) * self.g(wo, wi) / (4.0 * wo.z().abs())
+    }
+
+    // Solid angle density of reflecting off a visible microfacet normal into `wi`
+    pub fn reflection_pdf(&self, wo: &Vec3, wm: &Vec3) -> f64 {
+        self.visible_pdf(wo, wm) / (4.0 * wo.dot(wm).abs())
+    }
+
+    // The transmission BSDF times cos θi, without the Fresnel factor. It includes the change of
+    // variables from microfacet normal to refracted direction and the compression of radiance by
+    // the squared refractive index.
+    fn transmission_eval(&self, wo: &Vec3, wi: &Vec3, wm: &Vec3, eta: f64) -> f64 {
+        let denominator = wi.dot(wm) + wo.dot(wm) / eta;
+        self.d(wm) * self.g(wo, wi) * (wi.dot(wm) * wo.dot(wm)).abs()
+            / (denominator * denominator * wo.z().abs() * eta * eta)
+    }
+
+    // Solid angle density of refracting through a visible microfacet normal into `wi`
+    fn transmission_pdf(&self, wo: &Vec3, wi: &Vec3, wm: &Vec3, eta: f64) -> f64 {
+        let denominator = wi.dot(wm) + wo.dot(wm) / eta;
+        self.visible_pdf(wo, wm) * wi.dot(wm).abs() / (denominator * denominator)
+    }
+
+    // A rough dielectric interface, reflecting and refracting by the Fresnel equations: the BSDF
+    // times |cos θi|
+    pub fn dielectric_eval(&self, wo: &Vec3, wi: &Vec3, eta: f64) -> f64 {
+        if wo.z() <= 0.0 || wi.z() == 0.0 {
+            return 0.0;
+        }
+        let Some(wm) = Self::half_vector(wo, wi, eta) else {
+            return 0.0;
+        };
+
+        let reflectance = fresnel_dielectric(wo.dot(&wm), eta);
+        if wi.z() > 0.0 {
+            reflectance * self.reflection_eval(wo, wi, &wm)
+        } else {
+            (1.0 - reflectance) * self.transmission_eval(wo, wi, &wm, eta)
+        }
+    }
+
+    // Solid angle density of `sample_dielectric` choosing `wi`
+    pub fn dielectric_pdf(&self, wo: &Vec3, wi: &Vec3, eta: f64) -> f64 {
+        if wo.z() <= 0.0 || wi.z() == 0.0 {
+            return 0.0;
+        }
+        let Some(wm) = Self::half_vector(wo, wi, eta) else {
+            return 0.0;
+        };
+
+        let reflectance = fresnel_dielectric(wo.dot(&wm), eta);
+        if wi.z() > 0.0 {
+            reflectance * self.reflection_pdf(wo, &wm)
+        } else {
+            (1.0 - reflectance) * self.transmission_pdf(wo, wi, &wm, eta)
+        }
+    }
+
+    // Samples a direction reflected off a visible microfacet. None when it points into the surface.
+    pub fn sample_reflection(&self, wo: &Vec3, u: [f64; 2]) -> Option<Vec3> {
+        let wi = (-wo).reflect(&self.sample_visible(wo, u));
+        (wi.z() > 0.0).then_some(wi)
+    }
+
+    // Samples a direction reflected or refracted by a visible microfacet, choosing between them by
+    // their Fresnel weights with `choice`
+    pub fn sample_dielectric(&self, wo: &Vec3, eta: f64, u: [f64; 2], choice: f64) -> Option<Vec3> {
+        let wm = self.sample_visible(wo, u);
+        if choice < fresnel_dielectric(wo.dot(&wm), eta) {
+            let wi = (-wo).reflect(&wm);
+            (wi.z() > 0.0).then_some(wi)
+        } else {
+            refract(wo, &wm, eta).filter(|wi| wi.z() < 0.0)
+        }
+    }
+
+    // Samples a microfacet normal visible from `w` (Heitz, "Sampling the GGX Distribution of
+    // Visible Normals", 2018). The view is stretched to make the distribution a hemisphere, whose
+    // visible projection is sampled as a disk warped towards `w`.
+    pub fn sample_visible(&self, w: &Vec3, u: [f64; 2]) -> Vec3 {
+        let mut wh = Vec3::new(self.alpha_x * w.x(), self.alpha_y * w.y(), w.z()).unit_vector();
+        if wh.z() < 0.0 {
+            wh = -wh;
+        }
+
+        let t1 = if wh.z() < 0.999_99 {
+            Vec3::new(0.0, 0.0, 1.0).cross(&wh).unit_vector()
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let t2 = wh.cross(&t1);
+
+        let disk = sample_uniform_disk(u);
+        let px = disk.x();
+        let half_width = px.mul_add(-px, 1.0).sqrt();
+        let blend = 0.5 * (1.0 + wh.z());
+        let py = (1.0 - blend).mul_add(half_width, blend * disk.y());
+        let pz = px.mul_add(-px, py.mul_add(-py, 1.0)).max(0.0).sqrt();
+        let nh = px * t1 + py * t2 + pz * wh;
+
+        Vec3::new(
+            self.alpha_x * nh.x(),
+            self.alpha_y * nh.y(),
+            nh.z().max(1e-6),
+        )
+        .unit_vector()
+    }
+}
+
+// Refractive indices are quoted at the sodium D line when light isn't split into wavelengths
+const D_LINE_WAVELENGTH: f64 = 589.3;
+
+// The refractive index of a dielectric, constant or varying with wavelength. Dispersion formulas
+// take the wavelength in µm, so their coefficients match published glass catalogs.
+#[derive(Clone, Copy, Debug)]
+pub enum RefractiveIndex {
+    Constant(f64),
+    // n = a + b / λ²
+    Cauchy { a: f64, b: f64 },
+    // n² = 1 + Σ bᵢ λ² / (λ² - cᵢ)
+    Sellmeier { b: [f64; 3], c: [f64; 3] },
+}
+
+impl RefractiveIndex {
+    // Borosilicate crown glass (BK7)
+    pub const CROWN_GLASS: Self = Self::Cauchy {
+        a: 1.5046,
+        b: 0.004_20,
+    };
+    // Dense flint glass (SF11), which splits light several times more strongly
+    pub const DENSE_FLINT: Self = Self::Sellmeier {
+        b: [1.737_596_95, 0.313_747_346, 1.898_781_01],
+        c: [0.013_188_707, 0.062_306_814_2, 155.236_29],
+    };
+
+    // The index at a wavelength in nm, or at the D line for RGB rendering
+    pub fn at(&self, wavelength: Option<f64>) -> f64 {
+        let micrometres = 1e-3 * wavelength.unwrap_or(D_LINE_WAVELENGTH);
+        let lambda_2 = micrometres * micrometres;
+        match *self {
+            Self::Constant(n) => n,
+            Self::Cauchy { a, b } => a + b / lambda_2,
+            Self::Sellmeier { b, c } => b
+                .iter()
+                .zip(c)
+                .fold(1.0, |n_2, (b, c)| n_2 + b * lambda_2 / (lambda_2 - c))
+                .sqrt(),
+        }
+    }
+
+    pub const fn is_dispersive(&self) -> bool {
+        !matches!(self, Self::Constant(_))
+    }
+}
+
+impl Default for RefractiveIndex {
+    fn default() -> Self {
+        Self::Constant(1.0)
+    }
+}
+
+impl From<f64> for RefractiveIndex {
+    fn from(n: f64) -> Self {
+        Self::Constant(n)
+    }
+}
+
+// Wavelengths in nm that red, green and blue stand for when a color is treated as a spectrum
+const CHANNEL_WAVELENGTHS: [f64; 3] = [650.0, 550.0, 450.0];
+
+// The complex index of refraction η + ik of a conductor, for each of red, green and blue
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ComplexIor {
+    eta: Color,
+    k: Color,
+}
+
+impl ComplexIor {
+    pub const GOLD: Self = Self::new(
+        Color::new(0.143, 0.374, 1.442),
+        Color::new(3.983, 2.386, 1.603),
+    );
+    pub const COPPER: Self = Self::new(
+        Color::new(0.200, 0.924, 1.102),
+        Color::new(3.912, 2.452, 2.142),
+    );
+    pub const ALUMINIUM: Self = Self::new(
+        Color::new(1.657, 0.880, 0.521),
+        Color::new(9.224, 6.270, 4.837),
+    );
+    pub const SILVER: Self = Self::new(
+        Color::new(0.155, 0.117, 0.138),
+        Color::new(4.828, 3.122, 2.147),
+    );
+
+    pub const fn new(eta: Color, k: Color) -> Self {
+        Self { eta, k }
+    }
+
+    // Fresnel reflectance of an unpolarized ray arriving at `cos_theta` to the normal
+    pub fn fresnel(&self, cos_theta: f64) -> Color {
+        Color::new(
+            fresnel_conductor(cos_theta, self.eta.r(), self.k.r()),
+            fresnel_conductor(cos_theta, self.eta.g(), self.k.g()),
+            fresnel_conductor(cos_theta, self.eta.b(), self.k.b()),
+        )
+    }
+
+    // η and k at a wavelength in nm, interpolated between the values for red, green and blue as
+    // if they were measured at single wavelengths
+    pub fn at(&self, wavelength: f64) -> (f64, f64) {
+        let interpolate = |channels: Color| {
+            let [red, green, blue] = CHANNEL_WAVELENGTHS;
+            if wavelength >= green {
+                let t = ((wavelength - green) / (red - green)).min(1.0);
+                (channels.r() - channels.g()).mul_add(t, channels.g())
+            } else {
+                let t = ((green - wavelength) / (green - blue)).min(1.0);
+                (channels.b() - channels.g()).mul_add(t, channels.g())
+            }
+        };
+        (interpolate(self.eta), interpolate(self.k))
+    }
+}
+
+// The exact Fresnel equations for a conductor, averaging the s and p polarizations
+fn fresnel_conductor(cos_theta: f64, eta: f64, k: f64) -> f64 {
+    let cos_theta = cos_theta.clamp(0.0, 1.0);
+    let cos_2_theta = cos_theta * cos_theta;
+    let sin_2_theta = 1.0 - cos_2_theta;
+
+    let t0 = eta.mul_add(eta, k.mul_add(-k, -sin_2_theta));
+    let a_2_plus_b_2 = t0.mul_add(t0, 4.0 * eta * eta * k * k).sqrt();
+    let a = (0.5 * (a_2_plus_b_2 + t0)).max(0.0).sqrt();
+
+    let t1 = a_2_plus_b_2 + cos_2_theta;
+    let t2 = 2.0 * a * cos_theta;
+    let r_s = (t1 - t2) / (t1 + t2);
+
+    let t3 = cos_2_theta.mul_add(a_2_plus_b_2, sin_2_theta * sin_2_theta);
+    let t4 = t2 * sin_2_theta;
+    let r_p = r_s * (t3 - t4) / (t3 + t4);
+
+    0.5 * (r_p + r_s)
+}
+
+// Fresnel reflectance of a dielectric interface for an unpolarized ray arriving at `cos_theta` to
+// the normal, where `eta` is the refractive index across the interface relative to the ray's side.
+// Rays arriving from below the normal see the inverse ratio.
+pub fn fresnel_dielectric(cos_theta: f64, eta: f64) -> f64 {
+    let (cos_theta_i, eta) = if cos_theta < 0.0 {
+        (-cos_theta.max(-1.0), eta.recip())
+    } else {
+        (cos_theta.min(1.0), eta)
+    };
+
+    let sin_2_theta_t = cos_theta_i.mul_add(-cos_theta_i, 1.0) / (eta * eta);
+    if sin_2_theta_t >= 1.0 {
+        return 1.0;
+    }
+
+    let cos_theta_t = (1.0 - sin_2_theta_t).sqrt();
+    let r_parallel = eta.mul_add(cos_theta_i, -cos_theta_t) / eta.mul_add(cos_theta_i, cos_theta_t);
+    let r_perpendicular =
+        eta.mul_add(-cos_theta_t, cos_theta_i) / eta.mul_add(cos_theta_t, cos_theta_i);
+    0.5 * r_parallel.mul_add(r_parallel, r_perpendicular * r_perpendicular)
+}
+
+// Fraction of diffuse light, arriving uniformly over the hemisphere with a cosine weight, that
+// `fresnel_dielectric` reflects. Integrated numerically over cos² θ, where the weight is constant.
+pub fn diffuse_fresnel_reflectance(eta: f64) -> f64 {
+    const STEPS: u32 = 256;
+    (0..STEPS)
+        .map(|i| fresnel_dielectric(((f64::from(i) + 0.5) / f64::from(STEPS)).sqrt(), eta))
+        .sum::<f64>()
+        / f64::from(STEPS)
+}
+
+// Refracts `wi`, pointing away from the surface, through an interface with normal `n` and relative
+// index `eta`. None under total internal reflection.
+pub fn refract(wi: &Vec3, n: &Vec3, eta: f64) -> Option<Vec3> {
+    let mut cos_theta_i = n.dot(wi);
+    let (n, eta) = if cos_theta_i < 0.0 {
+        cos_theta_i = -cos_theta_i;
+        (-n, eta.recip())
+    } else {
+        (*n, eta)
+    };
+
+    let sin_2_theta_t = cos_theta_i.mul_add(-cos_theta_i, 1.0).max(0.0) / (eta * eta);
+    if sin_2_theta_t >= 1.0 {
+        return None;
+    }
+
+    let cos_theta_t = (1.0 - sin_2_theta_t).sqrt();
+    Some(-wi / eta + (cos_theta_i / eta - cos_theta_t) * n)
+}
+
+// Reflectance of a transparent film `thickness` nm thick with index `film_ior`, lying between the
+// medium a ray arrives from and a substrate with complex index η + ik (Born and Wolf, "Principles
+// of Optics", §1.6). Waves reflected at the top and bottom of the film interfere, by a phase that
+// depends on the wavelength in nm and the angle.
+pub fn fresnel_thin_film(
+    cos_theta: f64,
+    wavelength: f64,
+    outer_ior: f64,
+    film_ior: f64,
+    thickness: f64,
+    (eta, k): (f64, f64),
+) -> f64 {
+    let cos_theta = cos_theta.clamp(0.0, 1.0);
+    let outer = Complex::real(outer_ior);
+    let film = Complex::real(film_ior);
+    let substrate = Complex::new(eta, k);
+
+    // Snell's law fixes n sin θ across the layers; past the critical angle the cosine is imaginary
+    let sin_2_theta = outer_ior * outer_ior * cos_theta.mul_add(-cos_theta, 1.0);
+    let cos_in = |n: Complex| (Complex::real(1.0) - Complex::real(sin_2_theta) / (n * n)).sqrt();
+    let cos_outer = Complex::real(cos_theta);
+    let cos_film = cos_in(film);
+    let cos_substrate = cos_in(substrate);
+
+    let phase = Complex::real(4.0 * PI * thickness / wavelength) * film * cos_film;
+    let delay = Complex::exp_i(phase);
+    let airy = |top: Complex, bottom: Complex| {
+        ((top + bottom * delay) / (Complex::real(1.0) + top * bottom * delay)).norm_sqr()
+    };
+
+    let s = |n_i: Complex, cos_i: Complex, n_t: Complex, cos_t: Complex| {
+        (n_i * cos_i - n_t * cos_t) / (n_i * cos_i + n_t * cos_t)
+    };
+    let p = |n_i: Complex, cos_i: Complex, n_t: Complex, cos_t: Complex| {
+        (n_t * cos_i - n_i * cos_t) / (n_t * cos_i + n_i * cos_t)
+    };
+    0.5 * (airy(
+        s(outer, cos_outer, film, cos_film),
+        s(film, cos_film, substrate, cos_substrate),
+    ) + airy(
+        p(outer, cos_outer, film, cos_film),
+        p(film, cos_film, substrate, cos_substrate),
+    ))
+}
+
+// Just enough complex arithmetic for the amplitudes and phases of waves in thin films
+#[derive(Clone, Copy, Debug)]
+struct Complex {
+    re: f64,
+    im: f64,
+}
+
+impl Complex {
+    const fn new(re: f64, im: f64) -> Self {
+        Self { re, im }
+    }
+
+    const fn real(re: f64) -> Self {
+        Self::new(re, 0.0)
+    }
+
+    fn norm_sqr(self) -> f64 {
+        self.re.mul_add(self.re, self.im * self.im)
+    }
+
+    // The principal square root, with a non-negative real part
+    fn sqrt(self) -> Self {
+        let norm = self.norm_sqr().sqrt();
+        let re = (0.5 * (norm + self.re)).max(0.0).sqrt();
+        let im = (0.5 * (norm - self.re)).max(0.0).sqrt();
+        Self::new(re, if self.im < 0.0 { -im } else { im })
+    }
+
+    // e^(i z), which decays for z with a positive imaginary part
+    fn exp_i(z: Self) -> Self {
+        let magnitude = (-z.im).exp();
+        Self::new(magnitude * z.re.cos(), magnitude * z.re.sin())
+    }
+}
+
+impl std::ops::Add for Complex {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        Self::new(self.re + rhs.re, self.im + rhs.im)
+    }
+}
+
+impl std::ops::Sub for Complex {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self::Output {
+        Self::new(self.re - rhs.re, self.im - rhs.im)
+    }
+}
+
+impl std::ops::Mul for Complex {
+    type Output = Self;
+
+    fn mul(self, rhs: Self) -> Self::Output {
+        Self::new(
+            self.re.mul_add(rhs.re, -self.im * rhs.im),
+            self.re.mul_add(rhs.im, self.im * rhs.re),
+        )
+    }
+}
+
+impl std::ops::Div for Complex {
+    type Output = Self;
+
+    fn div(self, rhs: Self) -> Self::Output {
+        let denominator = rhs.norm_sqr();
+        Self::new(
+            self.re.mul_add(rhs.re, self.im * rhs.im) / denominator,
+            self.im.mul_add(rhs.re, -self.re * rhs.im) / denominator,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const THETA_STEPS: u32 = 1000;
+    const PHI_STEPS: u32 = 200;
+    const SAMPLE_GRID: u32 = 200;
+    const ROUGHNESSES: [(f64, f64); 3] = [(0.3, 0.3), (0.6, 0.6), (0.2, 0.5)];
+    const COS_THETAS: [f64; 3] = [1.0, 0.5, 0.1];
+
+    // Integrates `f` over the upper hemisphere with the midpoint rule in cos θ and φ
+    fn integrate_hemisphere(f: impl Fn(&Vec3) -> f64) -> f64 {
+        let mut sum = 0.0;
+        for i in 0..THETA_STEPS {
+            let cos_theta = (f64::from(i) + 0.5) / f64::from(THETA_STEPS);
+            let sin_theta = cos_theta.mul_add(-cos_theta, 1.0).sqrt();
+            for j in 0..PHI_STEPS {
+                let phi = 2.0 * PI * (f64::from(j) + 0.5) / f64::from(PHI_STEPS);
+                sum += f(&Vec3::new(
+                    sin_theta * phi.cos(),
+                    sin_theta * phi.sin(),
+                    cos_theta,
+                ));
+            }
+        }
+        sum * 2.0 * PI / f64::from(THETA_STEPS * PHI_STEPS)
+    }
+
+    // Averages `f` over a regular grid of points in the unit square
+    fn average_over_square(f: impl Fn([f64; 2]) -> f64) -> f64 {
+        let mut sum = 0.0;
+        for i in 0..SAMPLE_GRID {
+            for j in 0..SAMPLE_GRID {
+                sum += f([
+                    (f64::from(i) + 0.5) / f64::from(SAMPLE_GRID),
+                    (f64::from(j) + 0.5) / f64::from(SAMPLE_GRID),
+                ]);
+            }
+        }
+        sum / f64::from(SAMPLE_GRID * SAMPLE_GRID)
+    }
+
+    fn direction(cos_theta: f64) -> Vec3 {
+        Vec3::new(cos_theta.mul_add(-cos_theta, 1.0).sqrt(), 0.0, cos_theta)
+    }
+
+    // Sampled directions weighted by BSDF over density, as a path tracer uses them. Averaged over the
+    // square, this is the fraction of light the surface reflects or transmits.
+    fn reflection_weight(distribution: &TrowbridgeReitz, wo: &Vec3, u: [f64; 2]) -> f64 {
+        distribution.sample_reflection(wo, u).map_or(0.0, |wi| {
+            let wm = TrowbridgeReitz::half_vector(wo, &wi, 1.0).unwrap();
+            distribution.reflection_eval(wo, &wi, &wm) / distribution.reflection_pdf(wo, &wm)
+        })
+    }
+
+    // The lobe choice is averaged exactly, by weighting both lobes with the Fresnel reflectance of
+    // the sampled microfacet. Transmitted radiance is expanded back by the squared index, which
+    // `dielectric_eval` divides out.
+    fn dielectric_weight(distribution: &TrowbridgeReitz, wo: &Vec3, eta: f64, u: [f64; 2]) -> f64 {
+        let weight = |choice| {
+            distribution
+                .sample_dielectric(wo, eta, u, choice)
+                .map_or(0.0, |wi| {
+                    let radiance_scale = if wi.z() < 0.0 { eta * eta } else { 1.0 };
+                    radiance_scale * distribution.dielectric_eval(wo, &wi, eta)
+                        / distribution.dielectric_pdf(wo, &wi, eta)
+                })
+        };
+        let wm = distribution.sample_visible(wo, u);
+        let reflectance = fresnel_dielectric(wo.dot(&wm), eta);
+        reflectance.mul_add(weight(0.0), (1.0 - reflectance) * weight(1.0))
+    }
+
+    #[test]
+    fn microfacets_cover_the_surface() {
+        for (alpha_x, alpha_y) in ROUGHNESSES {
+            let distribution = TrowbridgeReitz::new(alpha_x, alpha_y);
+            let projected_area = integrate_hemisphere(|wm| distribution.d(wm) * wm.z());
+            assert!((projected_area - 1.0).abs() < 1e-3, "{projected_area}");
+        }
+    }
+
+    // The weak white furnace test: visible microfacets, projected onto the view, cover as much as
+    // the surface does
+    #[test]
+    fn visible_microfacets_cover_the_view() {
+        for (alpha_x, alpha_y) in ROUGHNESSES {
+            let distribution = TrowbridgeReitz::new(alpha_x, alpha_y);
+            for cos_theta in COS_THETAS {
+                let wo = direction(cos_theta);
+                let projected = integrate_hemisphere(|wm| {
+                    distribution.g1(&wo) * distribution.d(wm) * wo.dot(wm).max(0.0)
+                });
+                assert!((projected - cos_theta).abs() < 1e-3, "{projected}");
+            }
+        }
+    }
+
+    // A perfect mirror under uniform white light never reflects more than arrives. Rough mirrors
+    // lose the light that would bounce between microfacets more than once, so only nearly smooth
+    // ones come close to reflecting everything.
+    #[test]
+    fn white_furnace_reflection() {
+        for (alpha_x, alpha_y) in ROUGHNESSES {
+            let distribution = TrowbridgeReitz::new(alpha_x, alpha_y);
+            for cos_theta in COS_THETAS {
+                let wo = direction(cos_theta);
+                let sampled = average_over_square(|u| reflection_weight(&distribution, &wo, u));
+                let integrated = integrate_hemisphere(|wi| {
+                    TrowbridgeReitz::half_vector(&wo, wi, 1.0)
+                        .map_or(0.0, |wm| distribution.reflection_eval(&wo, wi, &wm))
+                });
+                assert!(sampled > 0.0 && sampled <= 1.0, "{sampled}");
+                assert!(
+                    (sampled - integrated).abs() < 1e-2,
+                    "{sampled} {integrated}"
+                );
+            }
+        }
+
+        let smooth = TrowbridgeReitz::new(0.05, 0.05);
+        for cos_theta in COS_THETAS {
+            let wo = direction(cos_theta);
+            let albedo = average_over_square(|u| reflection_weight(&smooth, &wo, u));
+            assert!(albedo > 0.9 && albedo <= 1.0, "{albedo}");
+        }
+    }
+
+    // Light is split between reflection and transmission without being created, and a nearly
+    // smooth interface seen head on loses almost none
+    #[test]
+    fn white_furnace_dielectric() {
+        for eta in [1.5, 1.0 / 1.5] {
+            for (alpha_x, alpha_y) in ROUGHNESSES {
+                let distribution = TrowbridgeReitz::new(alpha_x, alpha_y);
+                for cos_theta in COS_THETAS {
+                    let wo = direction(cos_theta);
+                    let albedo =
+                        average_over_square(|u| dielectric_weight(&distribution, &wo, eta, u));
+                    assert!(albedo > 0.0 && albedo <= 1.0 + 1e-9, "{albedo}");
+                }
+            }
+
+            let smooth = TrowbridgeReitz::new(0.05, 0.05);
+            let wo = direction(1.0);
+            let albedo = average_over_square(|u| dielectric_weight(&smooth, &wo, eta, u));
+            assert!((albedo - 1.0).abs() < 1e-3, "{albedo}");
+        }
+    }
+
+    #[test]
+    fn fresnel_reflectance() {
+        // ((η - 1) / (η + 1))² head on, and everything at grazing angles
+        assert!((fresnel_dielectric(1.0, 1.5) - 0.04).abs() < 1e-12);
+        assert!((fresnel_dielectric(0.0, 1.5) - 1.0).abs() < 1e-12);
+
+        // Light crossing an interface in either direction is reflected equally
+        for cos_theta_i in [0.9, 0.5, 0.2] {
+            let wi = direction(cos_theta_i);
+            let wt = refract(&wi, &Vec3::new(0.0, 0.0, 1.0), 1.5).unwrap();
+            let (outside, inside) = (
+                fresnel_dielectric(cos_theta_i, 1.5),
+                fresnel_dielectric(wt.z(), 1.5),
+            );
+            assert!((outside - inside).abs() < 1e-12, "{outside} {inside}");
+        }
+
+        // Without absorption a conductor is a dielectric
+        for cos_theta in COS_THETAS {
+            let conductor = fresnel_conductor(cos_theta, 1.5, 0.0);
+            assert!((conductor - fresnel_dielectric(cos_theta, 1.5)).abs() < 1e-9);
+        }
+
+        // A film of no thickness leaves the bare substrate
+        for cos_theta in COS_THETAS {
+            let film = fresnel_thin_film(cos_theta, 550.0, 1.0, 1.33, 0.0, (1.5, 0.0));
+            assert!((film - fresnel_dielectric(cos_theta, 1.5)).abs() < 1e-9);
+        }
+
+        // The hemispherical average for glass, as tabulated for diffuse Fresnel reflectance
+        let diffuse = diffuse_fresnel_reflectance(1.5);
+        assert!((diffuse - 0.0918).abs() < 1e-3, "{diffuse}");
+    }
+}
@@ -0,0 +1,337 @@
+use crate::{
+    checkpoint::fingerprint,
+    filter::{
+        BoxFilter, Filter, GaussianFilter, LanczosFilter, MitchellNetravaliFilter, TentFilter,
+    },
+    sampler::SamplerKind,
+    scene::SceneKind,
+    tile::TileOrder,
+};
+use std::{io, path::PathBuf, str::FromStr, time::Duration};
+
+const SAMPLERS: [(&str, SamplerKind); 4] = [
+    ("independent", SamplerKind::Independent),
+    ("stratified", SamplerKind::Stratified),
+    ("halton", SamplerKind::Halton),
+    ("sobol", SamplerKind::Sobol),
+];
+
+const TILE_ORDERS: [(&str, TileOrder); 3] = [
+    ("scanline", TileOrder::Scanline),
+    ("spiral", TileOrder::Spiral),
+    ("hilbert", TileOrder::Hilbert),
+];
+
+const SCENES: [(&str, SceneKind); 4] = [
+    ("spheres", SceneKind::Spheres),
+    ("cornell", SceneKind::CornellBox),
+    ("lookdev", SceneKind::Lookdev),
+    ("iridescence", SceneKind::Iridescence),
+];
+
+#[derive(Clone, Debug, Default)]
+pub struct Options {
+    samples_per_pass: Option<i32>,
+    snapshot_path: Option<PathBuf>,
+    snapshot_interval: Option<Duration>,
+    checkpoint_path: Option<PathBuf>,
+    checkpoint_interval: Option<Duration>,
+    resume: bool,
+    time_limit: Option<Duration>,
+    target_relative_error: Option<f64>,
+    adaptive_threshold: Option<f64>,
+    sample_count_map_path: Option<PathBuf>,
+    seed: Option<u64>,
+    sampler_kind: Option<SamplerKind>,
+    filter: Option<Filter>,
+    tile_size: Option<usize>,
+    tile_order: Option<TileOrder>,
+    scene_kind: Option<SceneKind>,
+    environment_path: Option<PathBuf>,
+    environment_rotation: Option<f64>,
+    environment_intensity: Option<f64>,
+    sky: bool,
+    sun_elevation: Option<f64>,
+    sun_azimuth: Option<f64>,
+    turbidity: Option<f64>,
+    spectral: bool,
+    base_color_map: Option<PathBuf>,
+    metallic_map: Option<PathBuf>,
+    roughness_map: Option<PathBuf>,
+    normal_map: Option<PathBuf>,
+}
+
+impl Options {
+    pub fn parse(mut args: impl Iterator<Item = String>) -> io::Result<Self> {
+        let mut options = Self::default();
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--samples-per-pass" => {
+                    let samples = parse_value(&arg, args.next())?;
+                    if samples < 1 {
+                        return Err(invalid_input(format!("{arg} must be at least 1")));
+                    }
+                    options.samples_per_pass = Some(samples);
+                }
+                "--snapshot" => options.snapshot_path = Some(parse_path(&arg, args.next())?),
+                "--snapshot-interval" => {
+                    options.snapshot_interval = Some(parse_seconds(&arg, args.next())?);
+                }
+                "--checkpoint" => options.checkpoint_path = Some(parse_path(&arg, args.next())?),
+                "--checkpoint-interval" => {
+                    options.checkpoint_interval = Some(parse_seconds(&arg, args.next())?);
+                }
+                "--resume" => options.resume = true,
+                "--time-limit" => options.time_limit = Some(parse_seconds(&arg, args.next())?),
+                "--target-error" => {
+                    options.target_relative_error = Some(parse_value(&arg, args.next())?);
+                }
+                "--adaptive" => {
+                    options.adaptive_threshold = Some(parse_value(&arg, args.next())?);
+                }
+                "--spp-map" => {
+                    let path = args
+                        .next()
+                        .ok_or_else(|| invalid_input(format!("Missing value for {arg}")))?;
+                    options.sample_count_map_path = Some(PathBuf::from(path));
+                }
+                "--seed" => options.seed = Some(parse_value(&arg, args.next())?),
+                "--sampler" => {
+                    options.sampler_kind = Some(parse_choice(&arg, args.next(), &SAMPLERS)?);
+                }
+                "--filter" => {
+                    options.filter = Some(parse_choice(
+                        &arg,
+                        args.next(),
+                        &[
+                            ("box", Filter::from(BoxFilter::default())),
+                            ("tent", Filter::from(TentFilter::default())),
+                            ("gaussian", Filter::from(GaussianFilter::default())),
+                            ("mitchell", Filter::from(MitchellNetravaliFilter::default())),
+                            ("lanczos", Filter::from(LanczosFilter::default())),
+                        ],
+                    )?);
+                }
+                "--tile-size" => {
+                    let size = parse_value(&arg, args.next())?;
+                    if size == 0 {
+                        return Err(invalid_input(format!("{arg} must be at least 1")));
+                    }
+                    options.tile_size = Some(size);
+                }
+                "--tile-order" => {
+                    options.tile_order = Some(parse_choice(&arg, args.next(), &TILE_ORDERS)?);
+                }
+                "--scene" => {
+                    options.scene_kind = Some(parse_choice(&arg, args.next(), &SCENES)?);
+                }
+                "--environment" => {
+                    let path = args
+                        .next()
+                        .ok_or_else(|| invalid_input(format!("Missing value for {arg}")))?;
+                    options.environment_path = Some(PathBuf::from(path));
+                }
+                "--environment-rotation" => {
+                    options.environment_rotation = Some(parse_value(&arg, args.next())?);
+                }
+                "--environment-intensity" => {
+                    options.environment_intensity = Some(parse_value(&arg, args.next())?);
+                }
+                "--sky" => options.sky = true,
+                "--sun-elevation" => options.sun_elevation = Some(parse_value(&arg, args.next())?),
+                "--sun-azimuth" => options.sun_azimuth = Some(parse_value(&arg, args.next())?),
+                "--turbidity" => options.turbidity = Some(parse_value(&arg, args.next())?),
+                "--spectral" => options.spectral = true,
+                "--base-color-map" => options.base_color_map = Some(parse_path(&arg, args.next())?),
+                "--metallic-map" => options.metallic_map = Some(parse_path(&arg, args.next())?),
+                "--roughness-map" => options.roughness_map = Some(parse_path(&arg, args.next())?),
+                "--normal-map" => options.normal_map = Some(parse_path(&arg, args.next())?),
+                _ => return Err(invalid_input(format!("Unknown argument {arg}"))),
+            }
+        }
+
+        if options.resume && options.checkpoint_path.is_none() {
+            return Err(invalid_input("--resume requires --checkpoint".to_string()));
+        }
+
+        if options.sky && options.environment_path.is_some() {
+            return Err(invalid_input(
+                "--sky and --environment can't be used together".to_string(),
+            ));
+        }
+
+        Ok(options)
+    }
+
+    pub const fn samples_per_pass(&self) -> Option<i32> {
+        self.samples_per_pass
+    }
+
+    pub const fn snapshot_path(&self) -> Option<&PathBuf> {
+        self.snapshot_path.as_ref()
+    }
+
+    pub const fn snapshot_interval(&self) -> Option<Duration> {
+        self.snapshot_interval
+    }
+
+    pub const fn checkpoint_path(&self) -> Option<&PathBuf> {
+        self.checkpoint_path.as_ref()
+    }
+
+    pub const fn checkpoint_interval(&self) -> Option<Duration> {
+        self.checkpoint_interval
+    }
+
+    pub const fn resume(&self) -> bool {
+        self.resume
+    }
+
+    pub const fn time_limit(&self) -> Option<Duration> {
+        self.time_limit
+    }
+
+    pub const fn target_relative_error(&self) -> Option<f64> {
+        self.target_relative_error
+    }
+
+    pub const fn adaptive_threshold(&self) -> Option<f64> {
+        self.adaptive_threshold
+    }
+
+    pub const fn sampler_kind(&self) -> Option<SamplerKind> {
+        self.sampler_kind
+    }
+
+    pub const fn scene_kind(&self) -> Option<SceneKind> {
+        self.scene_kind
+    }
+
+    pub const fn filter(&self) -> Option<Filter> {
+        self.filter
+    }
+
+    pub const fn tile_size(&self) -> Option<usize> {
+        self.tile_size
+    }
+
+    pub const fn tile_order(&self) -> Option<TileOrder> {
+        self.tile_order
+    }
+
+    pub const fn environment_path(&self) -> Option<&PathBuf> {
+        self.environment_path.as_ref()
+    }
+
+    pub const fn environment_rotation(&self) -> Option<f64> {
+        self.environment_rotation
+    }
+
+    pub const fn environment_intensity(&self) -> Option<f64> {
+        self.environment_intensity
+    }
+
+    pub const fn sky(&self) -> bool {
+        self.sky
+    }
+
+    pub const fn sun_elevation(&self) -> Option<f64> {
+        self.sun_elevation
+    }
+
+    pub const fn sun_azimuth(&self) -> Option<f64> {
+        self.sun_azimuth
+    }
+
+    pub const fn turbidity(&self) -> Option<f64> {
+        self.turbidity
+    }
+
+    pub const fn spectral(&self) -> bool {
+        self.spectral
+    }
+
+    pub const fn base_color_map(&self) -> Option<&PathBuf> {
+        self.base_color_map.as_ref()
+    }
+
+    pub const fn metallic_map(&self) -> Option<&PathBuf> {
+        self.metallic_map.as_ref()
+    }
+
+    pub const fn roughness_map(&self) -> Option<&PathBuf> {
+        self.roughness_map.as_ref()
+    }
+
+    pub const fn normal_map(&self) -> Option<&PathBuf> {
+        self.normal_map.as_ref()
+    }
+
+    // Identifies everything the scene is built from, for checkpoints. Image files are identified by
+    // their paths only.
+    pub fn scene_fingerprint(&self) -> u64 {
+        let description = format!(
+            "{:?}",
+            (
+                self.scene_kind.unwrap_or_default(),
+                &self.environment_path,
+                self.environment_rotation,
+                self.environment_intensity,
+                self.sky,
+                (self.sun_elevation, self.sun_azimuth, self.turbidity),
+                [
+                    &self.base_color_map,
+                    &self.metallic_map,
+                    &self.roughness_map,
+                    &self.normal_map,
+                ],
+            )
+        );
+        fingerprint(description.as_bytes())
+    }
+
+    pub const fn seed(&self) -> Option<u64> {
+        self.seed
+    }
+
+    pub const fn sample_count_map_path(&self) -> Option<&PathBuf> {
+        self.sample_count_map_path.as_ref()
+    }
+}
+
+fn parse_value<T>(arg: &str, value: Option<String>) -> io::Result<T>
+where
+    T: FromStr,
+    T::Err: std::fmt::Display,
+{
+    let value = value.ok_or_else(|| invalid_input(format!("Missing value for {arg}")))?;
+    value
+        .parse()
+        .map_err(|e| invalid_input(format!("Invalid value {value} for {arg}: {e}")))
+}
+
+fn parse_seconds(arg: &str, value: Option<String>) -> io::Result<Duration> {
+    Duration::try_from_secs_f64(parse_value(arg, value)?)
+        .map_err(|e| invalid_input(format!("Invalid value for {arg}: {e}")))
+}
+
+// One of a fixed set of named values
+fn parse_choice<T: Copy>(arg: &str, value: Option<String>, choices: &[(&str, T)]) -> io::Result<T> {
+    let value = value.ok_or_else(|| invalid_input(format!("Missing value for {arg}")))?;
+    choices
+        .iter()
+        .find(|(name, _)| *name == value)
+        .map(|&(_, choice)| choice)
+        .ok_or_else(|| invalid_input(format!("Unknown value {value} for {arg}")))
+}
+
+fn parse_path(arg: &str, value: Option<String>) -> io::Result<PathBuf> {
+    value
+        .map(PathBuf::from)
+        .ok_or_else(|| invalid_input(format!("Missing value for {arg}")))
+}
+
+fn invalid_input(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
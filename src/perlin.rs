@@ -0,0 +1,81 @@
+use crate::vec3::{Point3, Vec3, sample_uniform_sphere};
+use rand::{RngExt, SeedableRng, rngs::Xoshiro256PlusPlus};
+
+const POINT_COUNT: usize = 256;
+
+// Perlin gradient noise, as in "Ray Tracing: The Next Week". Random unit gradients sit on the
+// integer lattice, indexed by hashing each coordinate through its own permutation.
+#[derive(Clone, Debug)]
+pub struct Perlin {
+    gradients: Vec<Vec3>,
+    permutations: [Vec<usize>; 3],
+}
+
+impl Perlin {
+    pub fn new(seed: u64) -> Self {
+        let mut rng = Xoshiro256PlusPlus::seed_from_u64(seed);
+        let gradients = (0..POINT_COUNT)
+            .map(|_| sample_uniform_sphere([rng.random(), rng.random()]))
+            .collect();
+        let permutations = std::array::from_fn(|_| {
+            let mut permutation: Vec<usize> = (0..POINT_COUNT).collect();
+            for i in (1..POINT_COUNT).rev() {
+                permutation.swap(i, rng.random_range(0..=i));
+            }
+            permutation
+        });
+
+        Self {
+            gradients,
+            permutations,
+        }
+    }
+
+    // Smoothly varying noise in about [-1, 1]
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    pub fn noise(&self, p: &Point3) -> f64 {
+        let cell = [p.x().floor(), p.y().floor(), p.z().floor()];
+        let offset = [p.x() - cell[0], p.y() - cell[1], p.z() - cell[2]];
+
+        // Hermite smoothing hides the lattice
+        let weight = offset.map(|t| t * t * 2.0f64.mul_add(-t, 3.0));
+
+        let mut sum = 0.0;
+        for corner in 0..8u8 {
+            let d = [corner & 1, (corner >> 1) & 1, (corner >> 2) & 1];
+            let hash = (0..3).fold(0, |hash, axis| {
+                let index = (cell[axis] as i64 + i64::from(d[axis])) as usize & (POINT_COUNT - 1);
+                hash ^ self.permutations[axis][index]
+            });
+            let to_point = Vec3::new(
+                offset[0] - f64::from(d[0]),
+                offset[1] - f64::from(d[1]),
+                offset[2] - f64::from(d[2]),
+            );
+            let falloff = (0..3)
+                .map(|axis| {
+                    if d[axis] == 1 {
+                        weight[axis]
+                    } else {
+                        1.0 - weight[axis]
+                    }
+                })
+                .product::<f64>();
+            sum += falloff * self.gradients[hash].dot(&to_point);
+        }
+        sum
+    }
+
+    // Noise summed over `depth` octaves, each at twice the frequency and half the amplitude
+    pub fn turbulence(&self, p: &Point3, depth: u32) -> f64 {
+        let mut sum = 0.0;
+        let mut point = *p;
+        let mut weight = 1.0;
+        for _ in 0..depth {
+            sum += weight * self.noise(&point);
+            weight *= 0.5;
+            point *= 2.0;
+        }
+        sum.abs()
+    }
+}
@@ -0,0 +1,119 @@
+// Relative error is measured against at least this luminance so nearly black pixels don't need
+// an unbounded number of samples to converge
+const MIN_RELATIVE_ERROR_LUMINANCE: f64 = 0.01;
+
+// Welford's online algorithm for the mean and variance of a stream of samples
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RunningStats {
+    count: u32,
+    mean: f64,
+    m2: f64,
+}
+
+impl RunningStats {
+    pub const fn new(count: u32, mean: f64, m2: f64) -> Self {
+        Self { count, mean, m2 }
+    }
+
+    pub const fn count(&self) -> u32 {
+        self.count
+    }
+
+    pub const fn mean(&self) -> f64 {
+        self.mean
+    }
+
+    pub const fn m2(&self) -> f64 {
+        self.m2
+    }
+
+    pub fn push(&mut self, x: f64) {
+        self.count += 1;
+        let delta = x - self.mean;
+        self.mean += delta / f64::from(self.count);
+        self.m2 += delta * (x - self.mean);
+    }
+
+    // Chan et al.'s parallel update, so per-tile statistics can be folded into the film
+    pub fn merge(&mut self, other: &Self) {
+        if other.count == 0 {
+            return;
+        }
+        if self.count == 0 {
+            *self = *other;
+            return;
+        }
+
+        let count = self.count + other.count;
+        let delta = other.mean - self.mean;
+        let other_weight = f64::from(other.count) / f64::from(count);
+
+        self.mean += delta * other_weight;
+        self.m2 += (delta * delta).mul_add(f64::from(self.count) * other_weight, other.m2);
+        self.count = count;
+    }
+
+    pub fn variance(&self) -> f64 {
+        if self.count < 2 {
+            0.0
+        } else {
+            self.m2 / f64::from(self.count - 1)
+        }
+    }
+
+    pub fn standard_error(&self) -> f64 {
+        if self.count == 0 {
+            f64::INFINITY
+        } else {
+            (self.variance() / f64::from(self.count)).sqrt()
+        }
+    }
+
+    pub fn relative_error(&self) -> f64 {
+        self.standard_error() / self.mean.max(MIN_RELATIVE_ERROR_LUMINANCE)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{RngExt, SeedableRng, rngs::Xoshiro256PlusPlus};
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() <= 1e-12 * expected.abs().max(1.0),
+            "{actual} {expected}"
+        );
+    }
+
+    #[test]
+    fn merging_equals_pushing_every_sample() {
+        let mut rng = Xoshiro256PlusPlus::seed_from_u64(3);
+        let samples: Vec<f64> = (0..200)
+            .map(|_| rng.random::<f64>().powi(3).mul_add(50.0, 2.0))
+            .collect();
+
+        let mut all = RunningStats::default();
+        for &x in &samples {
+            all.push(x);
+        }
+
+        // The two-pass formulas as a reference
+        let mean = samples.iter().sum::<f64>() / 200.0;
+        let m2 = samples.iter().map(|x| (x - mean).powi(2)).sum::<f64>();
+        assert_close(all.mean(), mean);
+        assert_close(all.m2(), m2);
+
+        for split in [0, 1, 2, 57, 100, 199, 200] {
+            let (mut first, mut second) = (RunningStats::default(), RunningStats::default());
+            samples[..split].iter().for_each(|&x| first.push(x));
+            samples[split..].iter().for_each(|&x| second.push(x));
+            first.merge(&second);
+
+            assert_eq!(first.count(), all.count());
+            assert_close(first.mean(), all.mean());
+            assert_close(first.m2(), all.m2());
+            assert_close(first.variance(), all.variance());
+        }
+    }
+}
@@ -0,0 +1,542 @@
+use enum_dispatch::enum_dispatch;
+use rand::{RngExt, SeedableRng, rngs::Xoshiro256PlusPlus};
+
+// Dimensions 0-1 offset the sample within the pixel, 2-3 pick the point on the lens, 4 the time and
+// 5 the wavelengths for spectral rendering. Every bounce then gets its own block of dimensions, so
+// a given dimension always drives the same decision no matter how many samples earlier bounces
+// consumed. Within a bounce the material draws from the start of the block, as many as it needs,
+// and light sampling and Russian roulette start at fixed offsets after it, so they see the same
+// dimensions whichever lobe or mixed material was sampled.
+const CAMERA_DIMENSIONS: u32 = 6;
+const DIMENSIONS_PER_BOUNCE: u32 = 16;
+
+const ONE_MINUS_EPSILON: f64 = 1.0 - f64::EPSILON / 2.0;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SamplerKind {
+    #[default]
+    Independent,
+    Stratified,
+    Halton,
+    Sobol,
+}
+
+impl SamplerKind {
+    const ALL: [Self; 4] = [
+        Self::Independent,
+        Self::Stratified,
+        Self::Halton,
+        Self::Sobol,
+    ];
+
+    // A tag for the kind of sampler, as stored in checkpoints
+    pub const fn to_tag(self) -> u8 {
+        self as u8
+    }
+
+    pub fn from_tag(tag: u8) -> Option<Self> {
+        Self::ALL.into_iter().find(|kind| kind.to_tag() == tag)
+    }
+}
+
+// The parts of a bounce that draw samples after the material has scattered
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BounceStage {
+    // A light index and a point on the light
+    Light,
+    Roulette,
+}
+
+impl BounceStage {
+    const fn offset(self) -> u32 {
+        match self {
+            Self::Light => 12,
+            Self::Roulette => 15,
+        }
+    }
+}
+
+#[enum_dispatch(Sampler)]
+pub trait SampleGenerator {
+    fn get_1d(&mut self) -> f64;
+    fn get_2d(&mut self) -> [f64; 2];
+    fn start_next_bounce(&mut self);
+    fn start_stage(&mut self, stage: BounceStage);
+}
+
+// Samples for one camera sample. Every sampler is seeded from the global seed, the pixel and the
+// sample index, so every sample is reproducible no matter which thread renders it or when.
+#[derive(Clone, Debug)]
+#[enum_dispatch]
+pub enum Sampler {
+    Independent(IndependentSampler),
+    Stratified(StratifiedSampler),
+    Halton(HaltonSampler),
+    Sobol(SobolSampler),
+}
+
+impl Sampler {
+    pub fn new(
+        kind: SamplerKind,
+        seed: u64,
+        pixel_index: u64,
+        sample_index: u64,
+        samples_per_pixel: u32,
+    ) -> Self {
+        let dimensions = Dimensions::new(seed, pixel_index, sample_index);
+        match kind {
+            SamplerKind::Independent => Self::from(IndependentSampler::new(dimensions)),
+            SamplerKind::Stratified => {
+                Self::from(StratifiedSampler::new(dimensions, samples_per_pixel))
+            }
+            SamplerKind::Halton => Self::from(HaltonSampler::new(dimensions)),
+            SamplerKind::Sobol => Self::from(SobolSampler::new(dimensions)),
+        }
+    }
+}
+
+// State shared by all samplers: which dimension comes next, and a random stream for jitter and for
+// dimensions beyond what a low-discrepancy sequence provides
+#[derive(Clone, Debug)]
+struct Dimensions {
+    pixel_seed: u64,
+    sample_index: u64,
+    dimension: u32,
+    bounce: u32,
+    rng: Xoshiro256PlusPlus,
+}
+
+impl Dimensions {
+    fn new(seed: u64, pixel_index: u64, sample_index: u64) -> Self {
+        let pixel_seed = mix(mix(seed) ^ pixel_index);
+        Self {
+            pixel_seed,
+            sample_index,
+            dimension: 0,
+            bounce: 0,
+            rng: Xoshiro256PlusPlus::seed_from_u64(mix(pixel_seed ^ sample_index)),
+        }
+    }
+
+    const fn take(&mut self, count: u32) -> u32 {
+        let dimension = self.dimension;
+        self.dimension += count;
+        dimension
+    }
+
+    const fn start_next_bounce(&mut self) {
+        self.dimension = CAMERA_DIMENSIONS + self.bounce * DIMENSIONS_PER_BOUNCE;
+        self.bounce += 1;
+    }
+
+    const fn start_stage(&mut self, stage: BounceStage) {
+        self.dimension = CAMERA_DIMENSIONS
+            + self.bounce.saturating_sub(1) * DIMENSIONS_PER_BOUNCE
+            + stage.offset();
+    }
+
+    const fn dimension_seed(&self, dimension: u32) -> u64 {
+        mix(self.pixel_seed ^ mix(dimension as u64))
+    }
+
+    fn uniform(&mut self) -> f64 {
+        self.rng.random()
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct IndependentSampler {
+    dimensions: Dimensions,
+}
+
+impl IndependentSampler {
+    const fn new(dimensions: Dimensions) -> Self {
+        Self { dimensions }
+    }
+}
+
+impl SampleGenerator for IndependentSampler {
+    fn get_1d(&mut self) -> f64 {
+        self.dimensions.uniform()
+    }
+
+    fn get_2d(&mut self) -> [f64; 2] {
+        [self.dimensions.uniform(), self.dimensions.uniform()]
+    }
+
+    fn start_next_bounce(&mut self) {
+        self.dimensions.start_next_bounce();
+    }
+
+    fn start_stage(&mut self, stage: BounceStage) {
+        self.dimensions.start_stage(stage);
+    }
+}
+
+// Jittered samples where every dimension of a pixel visits its strata in its own random order.
+// Sample indices past the stratum count start a new, independently permuted round of strata.
+#[derive(Clone, Debug)]
+pub struct StratifiedSampler {
+    dimensions: Dimensions,
+    strata_1d: u32,
+    strata_2d: [u32; 2],
+}
+
+impl StratifiedSampler {
+    fn new(dimensions: Dimensions, samples_per_pixel: u32) -> Self {
+        let strata_1d = samples_per_pixel.max(1);
+        let strata_x = strata_1d.isqrt().max(1);
+        let strata_y = strata_1d.div_ceil(strata_x);
+
+        Self {
+            dimensions,
+            strata_1d,
+            strata_2d: [strata_x, strata_y],
+        }
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    fn stratum(&self, dimension: u32, stratum_count: u32) -> u32 {
+        let round = self.dimensions.sample_index / u64::from(stratum_count);
+        let index = (self.dimensions.sample_index % u64::from(stratum_count)) as u32;
+        let seed = mix(self.dimensions.dimension_seed(dimension) ^ round) as u32;
+        permute(index, stratum_count, seed)
+    }
+}
+
+impl SampleGenerator for StratifiedSampler {
+    fn get_1d(&mut self) -> f64 {
+        let dimension = self.dimensions.take(1);
+        let stratum = self.stratum(dimension, self.strata_1d);
+        let jitter = self.dimensions.uniform();
+        ((f64::from(stratum) + jitter) / f64::from(self.strata_1d)).min(ONE_MINUS_EPSILON)
+    }
+
+    fn get_2d(&mut self) -> [f64; 2] {
+        let [strata_x, strata_y] = self.strata_2d;
+        let dimension = self.dimensions.take(2);
+        let stratum = self.stratum(dimension, strata_x * strata_y);
+        let (jitter_x, jitter_y) = (self.dimensions.uniform(), self.dimensions.uniform());
+        [
+            ((f64::from(stratum % strata_x) + jitter_x) / f64::from(strata_x))
+                .min(ONE_MINUS_EPSILON),
+            ((f64::from(stratum / strata_x) + jitter_y) / f64::from(strata_y))
+                .min(ONE_MINUS_EPSILON),
+        ]
+    }
+
+    fn start_next_bounce(&mut self) {
+        self.dimensions.start_next_bounce();
+    }
+
+    fn start_stage(&mut self, stage: BounceStage) {
+        self.dimensions.start_stage(stage);
+    }
+}
+
+const HALTON_DIMENSIONS: usize = 256;
+const PRIMES: [u64; HALTON_DIMENSIONS] = first_primes();
+
+// Halton sequence with random digit permutations per pixel and dimension, which removes the
+// correlation between dimensions with large prime bases
+#[derive(Clone, Debug)]
+pub struct HaltonSampler {
+    dimensions: Dimensions,
+}
+
+impl HaltonSampler {
+    const fn new(dimensions: Dimensions) -> Self {
+        Self { dimensions }
+    }
+
+    fn sample_dimension(&mut self, dimension: u32) -> f64 {
+        match PRIMES.get(dimension as usize) {
+            Some(&base) => scrambled_radical_inverse(
+                base,
+                self.dimensions.sample_index,
+                self.dimensions.dimension_seed(dimension),
+            ),
+            None => self.dimensions.uniform(),
+        }
+    }
+}
+
+impl SampleGenerator for HaltonSampler {
+    fn get_1d(&mut self) -> f64 {
+        let dimension = self.dimensions.take(1);
+        self.sample_dimension(dimension)
+    }
+
+    fn get_2d(&mut self) -> [f64; 2] {
+        let dimension = self.dimensions.take(2);
+        [
+            self.sample_dimension(dimension),
+            self.sample_dimension(dimension + 1),
+        ]
+    }
+
+    fn start_next_bounce(&mut self) {
+        self.dimensions.start_next_bounce();
+    }
+
+    fn start_stage(&mut self, stage: BounceStage) {
+        self.dimensions.start_stage(stage);
+    }
+}
+
+// Owen-scrambled Sobol points following Burley, "Practical Hash-based Owen Scrambling" (2020).
+// Each dimension pair uses the first two Sobol dimensions with an independently shuffled sample
+// order and independent nested uniform scrambling, which pads the sequence to any dimension count.
+#[derive(Clone, Debug)]
+pub struct SobolSampler {
+    dimensions: Dimensions,
+}
+
+impl SobolSampler {
+    const fn new(dimensions: Dimensions) -> Self {
+        Self { dimensions }
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    fn sample_pair(&self, dimension: u32) -> [f64; 2] {
+        let seed = self.dimensions.dimension_seed(dimension) as u32;
+        let index = nested_uniform_scramble(self.dimensions.sample_index as u32, seed);
+
+        let x = nested_uniform_scramble(index.reverse_bits(), hash_u32(seed ^ 0x68BC_21EB));
+        let y =
+            nested_uniform_scramble(sobol_second_dimension(index), hash_u32(seed ^ 0x02E5_BE93));
+
+        [u32_to_unit(x), u32_to_unit(y)]
+    }
+}
+
+impl SampleGenerator for SobolSampler {
+    fn get_1d(&mut self) -> f64 {
+        let dimension = self.dimensions.take(1);
+        self.sample_pair(dimension)[0]
+    }
+
+    fn get_2d(&mut self) -> [f64; 2] {
+        let dimension = self.dimensions.take(2);
+        self.sample_pair(dimension)
+    }
+
+    fn start_next_bounce(&mut self) {
+        self.dimensions.start_next_bounce();
+    }
+
+    fn start_stage(&mut self, stage: BounceStage) {
+        self.dimensions.start_stage(stage);
+    }
+}
+
+// SplitMix64 finalizer, used to decorrelate the seeds of neighbouring pixels, samples and dimensions
+pub const fn mix(mut z: u64) -> u64 {
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+const fn hash_u32(mut x: u32) -> u32 {
+    x ^= x >> 16;
+    x = x.wrapping_mul(0x7FEB_352D);
+    x ^= x >> 15;
+    x = x.wrapping_mul(0x846C_A68B);
+    x ^ (x >> 16)
+}
+
+fn u32_to_unit(x: u32) -> f64 {
+    (f64::from(x) / 4_294_967_296.0).min(ONE_MINUS_EPSILON)
+}
+
+// Kensler, "Correlated Multi-Jittered Sampling" (2013): the element at `index` of a random
+// permutation of 0..length selected by `seed`
+const fn permute(mut index: u32, length: u32, seed: u32) -> u32 {
+    let mut mask = length.wrapping_sub(1);
+    mask |= mask >> 1;
+    mask |= mask >> 2;
+    mask |= mask >> 4;
+    mask |= mask >> 8;
+    mask |= mask >> 16;
+
+    loop {
+        index ^= seed;
+        index = index.wrapping_mul(0xE170_893D);
+        index ^= seed >> 16;
+        index ^= (index & mask) >> 4;
+        index ^= seed >> 8;
+        index = index.wrapping_mul(0x0929_EB3F);
+        index ^= seed >> 23;
+        index ^= (index & mask) >> 1;
+        index = index.wrapping_mul(1 | (seed >> 27));
+        index = index.wrapping_mul(0x6935_FA69);
+        index ^= (index & mask) >> 11;
+        index = index.wrapping_mul(0x74DC_B303);
+        index ^= (index & mask) >> 2;
+        index = index.wrapping_mul(0x9E50_1CC3);
+        index ^= (index & mask) >> 2;
+        index = index.wrapping_mul(0xC860_A3DF);
+        index &= mask;
+        index ^= index >> 5;
+
+        if index < length {
+            break;
+        }
+    }
+
+    index.wrapping_add(seed) % length
+}
+
+#[allow(clippy::cast_possible_truncation, clippy::cast_precision_loss)]
+fn scrambled_radical_inverse(base: u64, mut index: u64, seed: u64) -> f64 {
+    let inverse_base = (base as f64).recip();
+    let mut scale = inverse_base;
+    let mut result = 0.0;
+    let mut digit_index = 0;
+
+    // Trailing zero digits are permuted too, so keep going until a digit's place value drops below
+    // the precision of the result
+    let mut remaining_precision = 1_u64 << f64::MANTISSA_DIGITS;
+    while remaining_precision > 0 {
+        let digit = (index % base) as u32;
+        let digit_seed = mix(seed ^ digit_index) as u32;
+        let permuted = permute(digit, base as u32, digit_seed);
+        result = f64::from(permuted).mul_add(scale, result);
+
+        index /= base;
+        scale *= inverse_base;
+        digit_index += 1;
+        remaining_precision /= base;
+    }
+
+    result.min(ONE_MINUS_EPSILON)
+}
+
+// Laine and Karras' hash, applied to the reversed bits so that it scrambles like an Owen tree
+const fn nested_uniform_scramble(x: u32, seed: u32) -> u32 {
+    let mut x = x.reverse_bits();
+    x = x.wrapping_add(seed);
+    x ^= x.wrapping_mul(0x6C50_B47C);
+    x ^= x.wrapping_mul(0xB82F_1E52);
+    x ^= x.wrapping_mul(0xC7AF_E638);
+    x ^= x.wrapping_mul(0x8D22_F6E6);
+    x.reverse_bits()
+}
+
+// The second Sobol dimension, generated by the primitive polynomial x + 1. Its direction numbers
+// follow v[0] = 1 << 31 and v[i] = v[i - 1] ^ (v[i - 1] >> 1).
+const fn sobol_second_dimension(mut index: u32) -> u32 {
+    let mut direction: u32 = 1 << 31;
+    let mut result = 0;
+
+    while index != 0 {
+        if index & 1 != 0 {
+            result ^= direction;
+        }
+        index >>= 1;
+        direction ^= direction >> 1;
+    }
+
+    result
+}
+
+const fn first_primes<const N: usize>() -> [u64; N] {
+    let mut primes = [0; N];
+    let mut count = 0;
+    let mut candidate = 2;
+
+    while count < N {
+        let mut is_prime = true;
+        let mut i = 0;
+        while i < count && primes[i] * primes[i] <= candidate {
+            if candidate % primes[i] == 0 {
+                is_prime = false;
+                break;
+            }
+            i += 1;
+        }
+
+        if is_prime {
+            primes[count] = candidate;
+            count += 1;
+        }
+        candidate += 1;
+    }
+
+    primes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLES: u32 = 16;
+
+    fn samplers(kind: SamplerKind, pixel_index: u64) -> impl Iterator<Item = Sampler> {
+        (0..u64::from(SAMPLES))
+            .map(move |sample_index| Sampler::new(kind, 7, pixel_index, sample_index, SAMPLES))
+    }
+
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    fn stratum(value: f64, count: u32) -> usize {
+        (value * f64::from(count)) as usize
+    }
+
+    #[test]
+    fn samples_lie_in_unit_interval() {
+        for kind in SamplerKind::ALL {
+            for pixel_index in 0..8 {
+                for mut sampler in samplers(kind, pixel_index) {
+                    for _ in 0..4 {
+                        sampler.start_next_bounce();
+                        let [u, v] = sampler.get_2d();
+                        for value in [sampler.get_1d(), u, v] {
+                            assert!((0.0..1.0).contains(&value), "{kind:?} gave {value}");
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn samples_are_stratified() {
+        for kind in [
+            SamplerKind::Stratified,
+            SamplerKind::Halton,
+            SamplerKind::Sobol,
+        ] {
+            let mut strata_1d = [0; SAMPLES as usize];
+            let mut strata_2d = [0; SAMPLES as usize];
+            for mut sampler in samplers(kind, 3) {
+                strata_1d[stratum(sampler.get_1d(), SAMPLES)] += 1;
+                let [u, v] = sampler.get_2d();
+                strata_2d[stratum(v, 4) * 4 + stratum(u, 4)] += 1;
+            }
+            assert_eq!(strata_1d, [1; SAMPLES as usize], "{kind:?}");
+            if kind != SamplerKind::Halton {
+                assert_eq!(strata_2d, [1; SAMPLES as usize], "{kind:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn stages_use_fixed_dimensions() {
+        for kind in [SamplerKind::Halton, SamplerKind::Sobol] {
+            let mut few = Sampler::new(kind, 7, 3, 5, SAMPLES);
+            let mut many = few.clone();
+            for sampler in [&mut few, &mut many] {
+                sampler.start_next_bounce();
+                sampler.get_2d();
+            }
+            many.get_1d();
+            many.get_2d();
+
+            for stage in [BounceStage::Light, BounceStage::Roulette] {
+                few.start_stage(stage);
+                many.start_stage(stage);
+                assert_eq!(few.get_1d().to_bits(), many.get_1d().to_bits(), "{kind:?}");
+            }
+        }
+    }
+}
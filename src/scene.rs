@@ -0,0 +1,101 @@
+use crate::{
+    color::Color,
+    hittable::HitRecord,
+    hittable_collection::HittableCollection,
+    light::{EnvironmentLight, Light, LightSource},
+    ray::Ray,
+};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SceneKind {
+    #[default]
+    Spheres,
+    CornellBox,
+    Lookdev,
+    Iridescence,
+}
+
+// Radiance arriving along rays that escape the scene
+#[derive(Clone, Copy, Debug, Default)]
+pub enum Background {
+    #[default]
+    Sky,
+    Solid(Color),
+}
+
+impl Background {
+    pub fn color(&self, r: &Ray) -> Color {
+        match self {
+            Self::Sky => {
+                let unit_direction = r.dir().unit_vector();
+                let a = 0.5 * (unit_direction.y() + 1.0);
+                (1.0 - a) * Color::new(1.0, 1.0, 1.0) + a * Color::new(0.5, 0.7, 1.0)
+            }
+            Self::Solid(color) => *color,
+        }
+    }
+}
+
+// Emissive objects should also be added to `lights` as area lights so they are sampled directly.
+// Emitters that aren't are still found by scattered rays, only with more noise.
+#[derive(Clone, Debug, Default)]
+pub struct Scene {
+    world: HittableCollection,
+    lights: Vec<Light>,
+    background: Background,
+}
+
+impl Scene {
+    pub const fn new(
+        world: HittableCollection,
+        lights: Vec<Light>,
+        background: Background,
+    ) -> Self {
+        Self {
+            world,
+            lights,
+            background,
+        }
+    }
+
+    pub const fn world(&self) -> &HittableCollection {
+        &self.world
+    }
+
+    pub fn lights(&self) -> &[Light] {
+        &self.lights
+    }
+
+    // Lights are picked uniformly for direct lighting
+    #[allow(clippy::cast_precision_loss)]
+    pub const fn light_selection_pdf(&self) -> f64 {
+        (self.lights.len() as f64).recip()
+    }
+
+    // Solid angle density of direct lighting producing the light that arrived along `r`, which hit
+    // the surface in `rec` or escaped the scene when None
+    pub fn light_pdf(&self, r: &Ray, rec: Option<&HitRecord>) -> f64 {
+        if self.lights.is_empty() {
+            return 0.0;
+        }
+
+        let pdf: f64 = self.lights.iter().map(|light| light.pdf(r, rec)).sum();
+        self.light_selection_pdf() * pdf
+    }
+
+    pub fn with_light(mut self, light: Light) -> Self {
+        self.lights.push(light);
+        self
+    }
+
+    // Lights the scene with an environment map in place of the background
+    pub fn with_environment(mut self, environment: EnvironmentLight) -> Self {
+        self = self.with_light(environment.into());
+        self.background = Background::Solid(Color::default());
+        self
+    }
+
+    pub const fn background(&self) -> &Background {
+        &self.background
+    }
+}
@@ -0,0 +1,211 @@
+use crate::{
+    color::Color,
+    hdr_image::HdrImage,
+    light::{DirectionalLight, EnvironmentLight, equirectangular_direction},
+    vec3::Vec3,
+};
+use std::f64::consts::{FRAC_PI_2, PI};
+
+// Luminances are in kcd/m² and the sun's illuminance in klux, both scaled by this so a clear sky
+// is about as bright as the default background
+const UNITS_PER_KILOCANDELA: f64 = 0.1;
+
+// Illuminance from the sun above the atmosphere, in klux
+const EXTRATERRESTRIAL_SUN_ILLUMINANCE: f64 = 128.0;
+const SUN_ANGULAR_DIAMETER_DEGREES: f64 = 0.53;
+
+// Wavelengths in µm that sunlight is attenuated at for red, green and blue
+const WAVELENGTHS: [f64; 3] = [0.65, 0.55, 0.45];
+
+// The analytic daylight model from Preetham, Shirley and Smits, "A Practical Analytic Model for
+// Daylight" (1999). The sky's luminance and chromaticity each follow a Perez distribution fitted
+// against turbidity, the haziness of the atmosphere, from about 2 for a clear sky to 10 for a hazy
+// one. Angles are in degrees, with azimuth measured from +x towards +z.
+#[derive(Clone, Debug)]
+pub struct PreethamSky {
+    sun_direction: Vec3,
+    turbidity: f64,
+    luminance: Perez,
+    x: Perez,
+    y: Perez,
+}
+
+impl PreethamSky {
+    pub fn new(sun_elevation_degrees: f64, sun_azimuth_degrees: f64, turbidity: f64) -> Self {
+        let elevation = sun_elevation_degrees.clamp(-90.0, 90.0).to_radians();
+        let azimuth = sun_azimuth_degrees.to_radians();
+        let sun_direction = Vec3::new(
+            elevation.cos() * azimuth.cos(),
+            elevation.sin(),
+            elevation.cos() * azimuth.sin(),
+        );
+
+        // The fits don't cover the sun below the horizon, so the sky keeps its sunset colors
+        let t = turbidity.max(1.0);
+        let theta_sun = (FRAC_PI_2 - elevation).min(FRAC_PI_2);
+
+        let chi = (4.0 / 9.0 - t / 120.0) * 2.0f64.mul_add(-theta_sun, PI);
+        let zenith_luminance = 4.0453f64
+            .mul_add(t, -4.9710)
+            .mul_add(chi.tan(), (-0.2155f64).mul_add(t, 2.4192));
+        let zenith_x = polynomial(
+            t,
+            [
+                polynomial(theta_sun, [0.25886, 0.06052, -0.21196, 0.11693]),
+                polynomial(theta_sun, [0.00394, -0.03202, 0.06377, -0.02903]),
+                polynomial(theta_sun, [0.0, 0.00209, -0.00375, 0.00166]),
+            ],
+        );
+        let zenith_y = polynomial(
+            t,
+            [
+                polynomial(theta_sun, [0.26688, 0.06670, -0.26756, 0.15346]),
+                polynomial(theta_sun, [0.00516, -0.04153, 0.08970, -0.04214]),
+                polynomial(theta_sun, [0.0, 0.00317, -0.00610, 0.00275]),
+            ],
+        );
+
+        Self {
+            sun_direction,
+            turbidity: t,
+            luminance: Perez::new(
+                [
+                    0.1787f64.mul_add(t, -1.4630),
+                    (-0.3554f64).mul_add(t, 0.4275),
+                    (-0.0227f64).mul_add(t, 5.3251),
+                    0.1206f64.mul_add(t, -2.5771),
+                    (-0.0670f64).mul_add(t, 0.3703),
+                ],
+                zenith_luminance,
+                theta_sun,
+            ),
+            x: Perez::new(
+                [
+                    (-0.0193f64).mul_add(t, -0.2592),
+                    (-0.0665f64).mul_add(t, 0.0008),
+                    (-0.0004f64).mul_add(t, 0.2125),
+                    (-0.0641f64).mul_add(t, -0.8989),
+                    (-0.0033f64).mul_add(t, 0.0452),
+                ],
+                zenith_x,
+                theta_sun,
+            ),
+            y: Perez::new(
+                [
+                    (-0.0167f64).mul_add(t, -0.2608),
+                    (-0.0950f64).mul_add(t, 0.0092),
+                    (-0.0079f64).mul_add(t, 0.2102),
+                    (-0.0441f64).mul_add(t, -1.6537),
+                    (-0.0109f64).mul_add(t, 0.0529),
+                ],
+                zenith_y,
+                theta_sun,
+            ),
+        }
+    }
+
+    // Sky radiance along a unit direction, without the sun. Nothing comes from below the horizon.
+    pub fn radiance(&self, direction: &Vec3) -> Color {
+        let cos_theta = direction.y();
+        if cos_theta <= 0.0 {
+            return Color::default();
+        }
+
+        let cos_gamma = direction.dot(&self.sun_direction).clamp(-1.0, 1.0);
+        let luminance = self.luminance.evaluate(cos_theta, cos_gamma) * UNITS_PER_KILOCANDELA;
+        let x = self.x.evaluate(cos_theta, cos_gamma);
+        let y = self.y.evaluate(cos_theta, cos_gamma);
+
+        Color::from_xyz(x / y * luminance, luminance, (1.0 - x - y) / y * luminance)
+    }
+
+    // The sky tabulated into an equirectangular image, so it can be importance sampled like any
+    // environment map
+    #[allow(clippy::cast_precision_loss)]
+    pub fn environment_light(&self, width: usize, height: usize) -> EnvironmentLight {
+        let pixels = (0..height)
+            .flat_map(|y| {
+                (0..width).map(move |x| {
+                    self.radiance(&equirectangular_direction([
+                        (x as f64 + 0.5) / width as f64,
+                        (y as f64 + 0.5) / height as f64,
+                    ]))
+                })
+            })
+            .collect();
+
+        EnvironmentLight::new(HdrImage::new(width, height, pixels), 0.0, 1.0)
+    }
+
+    // Sunlight attenuated by Rayleigh scattering and aerosols along its path through the
+    // atmosphere, or None once the sun has set
+    pub fn sun_light(&self) -> Option<DirectionalLight> {
+        let cos_theta = self.sun_direction.y();
+        if cos_theta <= 0.0 {
+            return None;
+        }
+
+        // Relative optical mass of the air the light passes through
+        let theta_degrees = cos_theta.acos().to_degrees();
+        let optical_mass =
+            (0.15f64.mul_add((93.885 - theta_degrees).powf(-1.253), cos_theta)).recip();
+
+        let beta = 0.046_083_65f64.mul_add(self.turbidity, -0.045_860_25);
+        let [r, g, b] = WAVELENGTHS.map(|wavelength: f64| {
+            let rayleigh = -0.008_735 * wavelength.powf(-4.08);
+            let aerosol = -beta * wavelength.powf(-1.3);
+            ((rayleigh + aerosol) * optical_mass).exp()
+        });
+
+        let scale = EXTRATERRESTRIAL_SUN_ILLUMINANCE * UNITS_PER_KILOCANDELA;
+        Some(DirectionalLight::new(
+            self.sun_direction,
+            Color::new(scale * r, scale * g, scale * b),
+            SUN_ANGULAR_DIAMETER_DEGREES,
+        ))
+    }
+}
+
+// F(θ, γ) = (1 + A exp(B / cos θ)) (1 + C exp(D γ) + E cos² γ) for a direction at θ from the
+// zenith and γ from the sun, normalized to `zenith` straight up
+#[derive(Clone, Debug)]
+struct Perez {
+    coefficients: [f64; 5],
+    scale: f64,
+}
+
+impl Perez {
+    fn new(coefficients: [f64; 5], zenith: f64, theta_sun: f64) -> Self {
+        let mut perez = Self {
+            coefficients,
+            scale: 1.0,
+        };
+        perez.scale = zenith / perez.evaluate(1.0, theta_sun.cos());
+        perez
+    }
+
+    fn evaluate(&self, cos_theta: f64, cos_gamma: f64) -> f64 {
+        let [
+            horizon_scale,
+            horizon_falloff,
+            sun_scale,
+            sun_falloff,
+            backscatter,
+        ] = self.coefficients;
+        let gamma = cos_gamma.acos();
+        let horizon = horizon_scale.mul_add((horizon_falloff / cos_theta.max(1e-3)).exp(), 1.0);
+        let circumsolar = backscatter.mul_add(
+            cos_gamma * cos_gamma,
+            sun_scale.mul_add((sun_falloff * gamma).exp(), 1.0),
+        );
+        self.scale * horizon * circumsolar
+    }
+}
+
+// Evaluates the polynomial with the given coefficients, lowest order first
+fn polynomial<const N: usize>(x: f64, coefficients: [f64; N]) -> f64 {
+    coefficients
+        .iter()
+        .rev()
+        .fold(0.0, |sum, &coefficient| sum.mul_add(x, coefficient))
+}
@@ -0,0 +1,596 @@
+use crate::{color::Color, scope_timer::ScopeTimer};
+use std::{ops, sync::OnceLock};
+
+// Wavelengths in nm covered by the color matching functions
+const LAMBDA_MIN: f64 = 360.0;
+const LAMBDA_MAX: f64 = 830.0;
+
+// Each path carries a hero wavelength and this many minus one more, spread evenly over the
+// spectrum, so one path estimates several colors at once
+const SPECTRUM_SAMPLES: usize = 4;
+
+// Spectra are integrated against the color matching functions in steps of this many nm
+const INTEGRATION_STEP: f64 = 5.0;
+
+// RGB rendering integrates reflectances such as thin-film interference at every Fresnel term, so
+// it uses a coarser grid over the visible range
+const VISIBLE_MIN: f64 = 380.0;
+const VISIBLE_MAX: f64 = 780.0;
+const REFLECTANCE_STEP: f64 = 20.0;
+
+// Grid points along each axis of the RGB to spectrum table
+const TABLE_RESOLUTION: usize = 32;
+
+// Newton's method stops once the RGB of a fitted spectrum is this close to its target
+const FIT_TOLERANCE: f64 = 1e-6;
+const FIT_ITERATIONS: usize = 32;
+
+// Sigmoid coefficients are kept below this so colors on the edge of the gamut, which no smooth
+// spectrum reaches, don't diverge
+const MAX_COEFFICIENT: f64 = 200.0;
+
+// Relative spectral power of CIE illuminant D65 from 360 to 830 nm in steps of 10 nm
+const D65: [f64; 48] = [
+    46.6383, 52.0891, 49.9755, 54.6482, 82.7549, 91.486, 93.4318, 86.6823, 104.865, 117.008,
+    117.812, 114.861, 115.923, 108.811, 109.354, 107.802, 104.79, 107.689, 104.405, 104.046, 100.0,
+    96.3342, 95.788, 88.6856, 90.0062, 89.5991, 87.6987, 83.2886, 83.6992, 80.0268, 80.2146,
+    82.2778, 78.2842, 69.7213, 71.6091, 74.349, 61.604, 69.8856, 75.087, 63.5927, 46.4182, 66.8054,
+    63.3828, 64.304, 59.4519, 51.959, 57.4406, 60.3125,
+];
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SampledSpectrum([f64; SPECTRUM_SAMPLES]);
+
+impl SampledSpectrum {
+    pub const fn constant(value: f64) -> Self {
+        Self([value; SPECTRUM_SAMPLES])
+    }
+
+    pub fn is_black(&self) -> bool {
+        self.0.iter().all(|&value| value == 0.0)
+    }
+
+    pub fn max_component(&self) -> f64 {
+        self.0.iter().copied().fold(f64::MIN, f64::max)
+    }
+}
+
+impl ops::Add for SampledSpectrum {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        Self(std::array::from_fn(|i| self.0[i] + rhs.0[i]))
+    }
+}
+
+impl ops::AddAssign for SampledSpectrum {
+    fn add_assign(&mut self, rhs: Self) {
+        *self = *self + rhs;
+    }
+}
+
+impl ops::Mul for SampledSpectrum {
+    type Output = Self;
+
+    fn mul(self, rhs: Self) -> Self::Output {
+        Self(std::array::from_fn(|i| self.0[i] * rhs.0[i]))
+    }
+}
+
+impl ops::Mul<f64> for SampledSpectrum {
+    type Output = Self;
+
+    fn mul(self, rhs: f64) -> Self::Output {
+        Self(self.0.map(|value| value * rhs))
+    }
+}
+
+impl ops::Mul<SampledSpectrum> for f64 {
+    type Output = SampledSpectrum;
+
+    fn mul(self, rhs: SampledSpectrum) -> Self::Output {
+        rhs * self
+    }
+}
+
+impl ops::MulAssign<f64> for SampledSpectrum {
+    fn mul_assign(&mut self, rhs: f64) {
+        *self = *self * rhs;
+    }
+}
+
+// Hero wavelength sampling (Wilkie et al., "Hero Wavelength Spectral Sampling", 2014). The hero
+// wavelength is importance sampled towards where the eye is most sensitive and the others are
+// rotations of it across the spectrum.
+#[derive(Clone, Copy, Debug)]
+pub struct SampledWavelengths {
+    lambda: [f64; SPECTRUM_SAMPLES],
+    pdf: [f64; SPECTRUM_SAMPLES],
+}
+
+impl SampledWavelengths {
+    #[allow(clippy::cast_precision_loss)]
+    pub fn sample_visible(u: f64) -> Self {
+        let lambda = std::array::from_fn(|i| {
+            sample_visible_wavelength((u + i as f64 / SPECTRUM_SAMPLES as f64).fract())
+        });
+        Self {
+            lambda,
+            pdf: lambda.map(visible_wavelength_pdf),
+        }
+    }
+
+    pub const fn hero(&self) -> f64 {
+        self.lambda[0]
+    }
+
+    // Drops all but the hero wavelength, once the path has taken a direction that depends on it
+    #[allow(clippy::cast_precision_loss)]
+    pub fn terminate_secondary(&mut self) {
+        if self.pdf[1..].iter().all(|&pdf| pdf == 0.0) {
+            return;
+        }
+        self.pdf[1..].fill(0.0);
+        self.pdf[0] /= SPECTRUM_SAMPLES as f64;
+    }
+
+    // Linear Rec. 709 RGB, normalized so D65 light comes out white
+    #[allow(clippy::cast_precision_loss)]
+    fn to_color(self, spectrum: SampledSpectrum) -> Color {
+        let mut xyz = [0.0; 3];
+        for ((lambda, pdf), value) in self.lambda.iter().zip(self.pdf).zip(spectrum.0) {
+            if pdf > 0.0 {
+                let cmf = color_matching(*lambda);
+                for (sum, weight) in xyz.iter_mut().zip(cmf) {
+                    *sum += weight * value / pdf;
+                }
+            }
+        }
+
+        let white = &rgb_weights().white;
+        let color = Color::from_xyz_unclipped(xyz[0], xyz[1], xyz[2]);
+        Color::new(
+            color.r() / white.r(),
+            color.g() / white.g(),
+            color.b() / white.b(),
+        ) * (SPECTRUM_SAMPLES as f64).recip()
+    }
+}
+
+// How light is carried along a path: as RGB, or as radiance at sampled wavelengths that RGB
+// reflectances and lights are uplifted to as the path meets them
+#[derive(Clone, Copy, Debug)]
+pub enum Wavelengths {
+    Rgb,
+    Sampled(SampledWavelengths),
+}
+
+impl Wavelengths {
+    pub const fn hero(&self) -> Option<f64> {
+        match self {
+            Self::Rgb => None,
+            Self::Sampled(wavelengths) => Some(wavelengths.hero()),
+        }
+    }
+
+    pub fn terminate_secondary(&mut self) {
+        if let Self::Sampled(wavelengths) = self {
+            wavelengths.terminate_secondary();
+        }
+    }
+
+    // A reflectance or transmittance. Values above one, such as throughput weights, are scaled
+    // down to fit the table and back up again.
+    pub fn reflectance(&self, color: Color) -> SampledSpectrum {
+        match self {
+            Self::Rgb => rgb_spectrum(color),
+            Self::Sampled(wavelengths) => {
+                let max = color.max_component();
+                if max <= 1.0 {
+                    return sigmoid_spectrum(color, wavelengths);
+                }
+                let scale = 2.0 * max;
+                scale * sigmoid_spectrum(color * scale.recip(), wavelengths)
+            }
+        }
+    }
+
+    // Emitted radiance, as the reflectance of the same color lit by D65, so white lights are
+    // white in both modes
+    pub fn illuminant(&self, color: Color) -> SampledSpectrum {
+        match self {
+            Self::Rgb => rgb_spectrum(color),
+            Self::Sampled(wavelengths) => {
+                let max = color.max_component();
+                if max <= 0.0 {
+                    return SampledSpectrum::default();
+                }
+                let scale = 2.0 * max;
+                let reflectance = sigmoid_spectrum(color * scale.recip(), wavelengths);
+                scale * reflectance * SampledSpectrum(wavelengths.lambda.map(d65))
+            }
+        }
+    }
+
+    pub fn to_color(self, spectrum: SampledSpectrum) -> Color {
+        match self {
+            Self::Rgb => Color::new(spectrum.0[0], spectrum.0[1], spectrum.0[2]),
+            Self::Sampled(wavelengths) => wavelengths.to_color(spectrum),
+        }
+    }
+}
+
+// RGB rendering uses the first three slots for red, green and blue
+const fn rgb_spectrum(color: Color) -> SampledSpectrum {
+    SampledSpectrum([color.r(), color.g(), color.b(), 0.0])
+}
+
+// Smooth reflectance spectra for RGB colors (Jakob and Hanika, "A Low-Dimensional Function Space
+// for Efficient Spectral Upsampling", 2019). Every color in [0, 1]³ maps to a sigmoid of a
+// quadratic in wavelength whose RGB under D65 is that color.
+#[allow(clippy::float_cmp)]
+fn sigmoid_spectrum(color: Color, wavelengths: &SampledWavelengths) -> SampledSpectrum {
+    let rgb = [color.r(), color.g(), color.b()].map(|c| c.clamp(0.0, 1.0));
+
+    // Grays are flat spectra, which the sigmoid only reaches in the limit
+    if rgb[0] == rgb[1] && rgb[1] == rgb[2] {
+        return SampledSpectrum::constant(rgb[0]);
+    }
+
+    let coefficients = tables().coefficients(rgb);
+    SampledSpectrum(
+        wavelengths
+            .lambda
+            .map(|lambda| sigmoid_polynomial(coefficients, normalized_wavelength(lambda))),
+    )
+}
+
+fn normalized_wavelength(lambda: f64) -> f64 {
+    (lambda - LAMBDA_MIN) / (LAMBDA_MAX - LAMBDA_MIN)
+}
+
+fn sigmoid_polynomial([c0, c1, c2]: [f64; 3], t: f64) -> f64 {
+    let x = c0.mul_add(t, c1).mul_add(t, c2);
+    0.5 + 0.5 * x / x.mul_add(x, 1.0).sqrt()
+}
+
+// Wavelengths are drawn with a density shaped like the eye's sensitivity (pbrt-v4's
+// SampleVisibleWavelengths)
+fn sample_visible_wavelength(u: f64) -> f64 {
+    138.888_889f64.mul_add((-1.827_501_97f64).mul_add(u, 0.856_910_62).atanh(), 538.0)
+}
+
+fn visible_wavelength_pdf(lambda: f64) -> f64 {
+    if !(LAMBDA_MIN..=LAMBDA_MAX).contains(&lambda) {
+        return 0.0;
+    }
+    0.003_939_804_2 / (0.0072 * (lambda - 538.0)).cosh().powi(2)
+}
+
+// CIE 1931 color matching functions as fitted by Wyman, Sloan and Shirley, "Simple Analytic
+// Approximations to the CIE XYZ Color Matching Functions" (2013)
+fn color_matching(lambda: f64) -> [f64; 3] {
+    let lobe = |mean: f64, below: f64, above: f64| {
+        let t = (lambda - mean) / if lambda < mean { below } else { above };
+        (-0.5 * t * t).exp()
+    };
+    [
+        1.056f64.mul_add(
+            lobe(599.8, 37.9, 31.0),
+            0.362f64.mul_add(lobe(442.0, 16.0, 26.7), -0.065 * lobe(501.1, 20.4, 26.2)),
+        ),
+        0.821f64.mul_add(lobe(568.8, 46.9, 40.5), 0.286 * lobe(530.9, 16.3, 31.1)),
+        1.217f64.mul_add(lobe(437.0, 11.8, 36.0), 0.681 * lobe(459.0, 26.0, 13.8)),
+    ]
+}
+
+#[allow(
+    clippy::cast_possible_truncation,
+    clippy::cast_precision_loss,
+    clippy::cast_sign_loss
+)]
+fn d65(lambda: f64) -> f64 {
+    let x = ((lambda - LAMBDA_MIN) / 10.0).clamp(0.0, (D65.len() - 1) as f64);
+    let index = (x as usize).min(D65.len() - 2);
+    let t = x - index as f64;
+    0.01 * (D65[index + 1] - D65[index]).mul_add(t, D65[index])
+}
+
+// RGB of a reflectance given as a function of wavelength in nm, lit by D65. This is how RGB
+// rendering approximates effects that only exist per wavelength, such as interference.
+pub fn reflectance_to_color(reflectance: impl Fn(f64) -> f64) -> Color {
+    reflectance_weights()
+        .weights
+        .iter()
+        .fold(Color::default(), |sum, &(lambda, [r, g, b])| {
+            sum + Color::new(r, g, b) * reflectance(lambda)
+        })
+}
+
+fn rgb_weights() -> &'static RgbWeights {
+    static WEIGHTS: OnceLock<RgbWeights> = OnceLock::new();
+    WEIGHTS.get_or_init(|| RgbWeights::new(LAMBDA_MIN, LAMBDA_MAX, INTEGRATION_STEP))
+}
+
+fn reflectance_weights() -> &'static RgbWeights {
+    static WEIGHTS: OnceLock<RgbWeights> = OnceLock::new();
+    WEIGHTS.get_or_init(|| RgbWeights::new(VISIBLE_MIN, VISIBLE_MAX, REFLECTANCE_STEP))
+}
+
+// The RGB of a spectrum lit by D65 is a weighted sum of its values at the wavelengths of an
+// integration grid. Weights are normalized so a constant spectrum of one comes out white.
+struct RgbWeights {
+    // Rec. 709 RGB of D65, which spectral colors are divided by to white balance them
+    white: Color,
+    weights: Vec<(f64, [f64; 3])>,
+}
+
+impl RgbWeights {
+    #[allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_precision_loss,
+        clippy::cast_sign_loss
+    )]
+    fn new(lambda_min: f64, lambda_max: f64, step: f64) -> Self {
+        let steps = ((lambda_max - lambda_min) / step) as usize;
+        let wavelengths: Vec<f64> = (0..steps)
+            .map(|i| (i as f64 + 0.5).mul_add(step, lambda_min))
+            .collect();
+        let unbalanced: Vec<Color> = wavelengths
+            .iter()
+            .map(|&lambda| {
+                let [x, y, z] = color_matching(lambda);
+                Color::from_xyz_unclipped(x, y, z) * (d65(lambda) * step)
+            })
+            .collect();
+        let white = unbalanced
+            .iter()
+            .fold(Color::default(), |sum, &weight| sum + weight);
+        let weights = wavelengths
+            .into_iter()
+            .zip(unbalanced)
+            .map(|(lambda, weight)| {
+                (
+                    lambda,
+                    [
+                        weight.r() / white.r(),
+                        weight.g() / white.g(),
+                        weight.b() / white.b(),
+                    ],
+                )
+            })
+            .collect();
+
+        Self { white, weights }
+    }
+}
+
+fn tables() -> &'static SpectralTables {
+    static TABLES: OnceLock<SpectralTables> = OnceLock::new();
+    TABLES.get_or_init(SpectralTables::new)
+}
+
+// Built on first use. Colors are looked up by their largest channel, which picks one of three
+// blocks and the position along z, and the other two relative to it.
+struct SpectralTables {
+    z_scale: [f64; TABLE_RESOLUTION],
+    coefficients: Vec<[f64; 3]>,
+}
+
+impl SpectralTables {
+    #[allow(clippy::cast_precision_loss)]
+    fn new() -> Self {
+        let _timer = ScopeTimer::new("SpectralTables::new");
+        let weights = &rgb_weights().weights;
+
+        // Grid points crowd towards black and full brightness, where spectra change fastest
+        let z_scale = std::array::from_fn(|k| {
+            smoothstep(smoothstep(k as f64 / (TABLE_RESOLUTION - 1) as f64))
+        });
+
+        let mut coefficients = vec![[0.0; 3]; 3 * TABLE_RESOLUTION.pow(3)];
+        for block in 0..3 {
+            for i in 0..TABLE_RESOLUTION {
+                for j in 0..TABLE_RESOLUTION {
+                    let x = i as f64 / (TABLE_RESOLUTION - 1) as f64;
+                    let y = j as f64 / (TABLE_RESOLUTION - 1) as f64;
+                    let target = |k: usize| {
+                        let z: f64 = z_scale[k];
+                        let mut rgb = [0.0; 3];
+                        rgb[block] = z;
+                        rgb[(block + 1) % 3] = x * z;
+                        rgb[(block + 2) % 3] = y * z;
+                        rgb
+                    };
+
+                    // Each fit starts from its neighbour's, walking out from a mid gray level in
+                    // both directions
+                    let start = TABLE_RESOLUTION / 5;
+                    let mut fit = [0.0; 3];
+                    for k in start..TABLE_RESOLUTION {
+                        fit = fit_sigmoid(weights, target(k), fit);
+                        coefficients[Self::index(block, k, j, i)] = fit;
+                    }
+                    fit = coefficients[Self::index(block, start, j, i)];
+                    for k in (0..start).rev() {
+                        fit = fit_sigmoid(weights, target(k), fit);
+                        coefficients[Self::index(block, k, j, i)] = fit;
+                    }
+                }
+            }
+        }
+
+        Self {
+            z_scale,
+            coefficients,
+        }
+    }
+
+    const fn index(block: usize, k: usize, j: usize, i: usize) -> usize {
+        ((block * TABLE_RESOLUTION + k) * TABLE_RESOLUTION + j) * TABLE_RESOLUTION + i
+    }
+
+    // Trilinear interpolation between the fits around `rgb`, which has channels in [0, 1]
+    #[allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_precision_loss,
+        clippy::cast_sign_loss
+    )]
+    fn coefficients(&self, rgb: [f64; 3]) -> [f64; 3] {
+        let block = if rgb[0] > rgb[1] {
+            if rgb[0] > rgb[2] { 0 } else { 2 }
+        } else if rgb[1] > rgb[2] {
+            1
+        } else {
+            2
+        };
+        let z = rgb[block];
+        let last = (TABLE_RESOLUTION - 1) as f64;
+        let x = rgb[(block + 1) % 3] / z * last;
+        let y = rgb[(block + 2) % 3] / z * last;
+
+        let xi = (x as usize).min(TABLE_RESOLUTION - 2);
+        let yi = (y as usize).min(TABLE_RESOLUTION - 2);
+        let zi = self
+            .z_scale
+            .partition_point(|&scale| scale <= z)
+            .clamp(1, TABLE_RESOLUTION - 1)
+            - 1;
+        let dx = x - xi as f64;
+        let dy = y - yi as f64;
+        let dz = (z - self.z_scale[zi]) / (self.z_scale[zi + 1] - self.z_scale[zi]);
+
+        let lerp = |a: [f64; 3], b: [f64; 3], t: f64| {
+            std::array::from_fn(|c| (b[c] - a[c]).mul_add(t, a[c]))
+        };
+        let corner = |di: usize, dj: usize, dk: usize| {
+            self.coefficients[Self::index(block, zi + dk, yi + dj, xi + di)]
+        };
+        let along_x = |dj: usize, dk: usize| lerp(corner(0, dj, dk), corner(1, dj, dk), dx);
+        let along_y = |dk: usize| lerp(along_x(0, dk), along_x(1, dk), dy);
+        lerp(along_y(0), along_y(1), dz)
+    }
+}
+
+// Fits sigmoid coefficients to a target RGB with Newton's method, starting from `initial`.
+// `weights` pairs each wavelength with its contribution to each channel.
+fn fit_sigmoid(weights: &[(f64, [f64; 3])], target: [f64; 3], initial: [f64; 3]) -> [f64; 3] {
+    let mut coefficients = initial;
+    for _ in 0..FIT_ITERATIONS {
+        let mut residual = target.map(|c| -c);
+        let mut jacobian = [[0.0; 3]; 3];
+        for &(lambda, weight) in weights {
+            let t = normalized_wavelength(lambda);
+            let x = coefficients[0]
+                .mul_add(t, coefficients[1])
+                .mul_add(t, coefficients[2]);
+            let value = sigmoid_polynomial(coefficients, t);
+            let slope = 0.5 / x.mul_add(x, 1.0).powf(1.5);
+            for (channel, &w) in weight.iter().enumerate() {
+                residual[channel] += w * value;
+                jacobian[channel][0] += w * slope * t * t;
+                jacobian[channel][1] += w * slope * t;
+                jacobian[channel][2] += w * slope;
+            }
+        }
+
+        if residual.iter().all(|r| r.abs() < FIT_TOLERANCE) {
+            break;
+        }
+        let Some(step) = solve_3x3(jacobian, residual) else {
+            break;
+        };
+        for (c, s) in coefficients.iter_mut().zip(step) {
+            *c -= s;
+        }
+
+        let largest = coefficients
+            .iter()
+            .fold(0.0, |max: f64, c| max.max(c.abs()));
+        if largest > MAX_COEFFICIENT {
+            coefficients = coefficients.map(|c| c * MAX_COEFFICIENT / largest);
+        }
+    }
+    coefficients
+}
+
+// Cramer's rule, or None for a singular matrix
+fn solve_3x3(m: [[f64; 3]; 3], b: [f64; 3]) -> Option<[f64; 3]> {
+    let determinant = |m: [[f64; 3]; 3]| {
+        m[0][0].mul_add(
+            m[1][1].mul_add(m[2][2], -m[1][2] * m[2][1]),
+            m[0][1].mul_add(
+                m[1][2].mul_add(m[2][0], -m[1][0] * m[2][2]),
+                m[0][2] * m[1][0].mul_add(m[2][1], -m[1][1] * m[2][0]),
+            ),
+        )
+    };
+    let det = determinant(m);
+    if det.abs() < 1e-15 {
+        return None;
+    }
+    Some(std::array::from_fn(|column| {
+        let mut replaced = m;
+        for (row, value) in replaced.iter_mut().zip(b) {
+            row[column] = value;
+        }
+        determinant(replaced) / det
+    }))
+}
+
+fn smoothstep(x: f64) -> f64 {
+    x * x * 2.0f64.mul_add(-x, 3.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reflectance_color(weights: &RgbWeights, reflectance: impl Fn(f64) -> f64) -> [f64; 3] {
+        weights
+            .weights
+            .iter()
+            .fold([0.0; 3], |sum, &(lambda, [r, g, b])| {
+                let value = reflectance(lambda);
+                [
+                    value.mul_add(r, sum[0]),
+                    value.mul_add(g, sum[1]),
+                    value.mul_add(b, sum[2]),
+                ]
+            })
+    }
+
+    #[test]
+    fn constant_reflectance_is_white() {
+        for weights in [rgb_weights(), reflectance_weights()] {
+            for channel in reflectance_color(weights, |_| 1.0) {
+                assert!((channel - 1.0).abs() < 1e-12, "{channel}");
+            }
+        }
+    }
+
+    #[test]
+    fn sigmoid_spectra_round_trip_to_their_colors() {
+        let colors = [
+            [0.8, 0.2, 0.1],
+            [0.1, 0.6, 0.2],
+            [0.2, 0.3, 0.9],
+            [0.9, 0.8, 0.1],
+            [0.5, 0.5, 0.4],
+            [0.05, 0.02, 0.03],
+            [0.95, 0.9, 0.97],
+        ];
+        for rgb in colors {
+            let coefficients = tables().coefficients(rgb);
+            let round_trip = reflectance_color(rgb_weights(), |lambda| {
+                sigmoid_polynomial(coefficients, normalized_wavelength(lambda))
+            });
+            for (expected, actual) in rgb.into_iter().zip(round_trip) {
+                assert!(
+                    (expected - actual).abs() < 5e-3,
+                    "{rgb:?} came back as {round_trip:?}"
+                );
+            }
+        }
+    }
+}
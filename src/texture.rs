@@ -0,0 +1,309 @@
+use crate::{
+    color::Color,
+    hdr_image::HdrImage,
+    hittable::HitRecord,
+    perlin::Perlin,
+    vec3::{Point3, Vec3},
+};
+use enum_dispatch::enum_dispatch;
+use std::{io, path::Path, sync::Arc};
+
+#[enum_dispatch(Texture)]
+pub trait TextureLookup {
+    fn value(&self, uv: [f64; 2], p: &Point3) -> Color;
+
+    // Scalar parameters such as roughness read the average of the channels, so grayscale maps
+    // come through unchanged
+    fn scalar(&self, uv: [f64; 2], p: &Point3) -> f64 {
+        let color = self.value(uv, p);
+        (color.r() + color.g() + color.b()) / 3.0
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct SolidColor {
+    color: Color,
+}
+
+impl SolidColor {
+    pub const fn new(color: Color) -> Self {
+        Self { color }
+    }
+}
+
+impl TextureLookup for SolidColor {
+    fn value(&self, _uv: [f64; 2], _p: &Point3) -> Color {
+        self.color
+    }
+}
+
+// Alternating squares in surface coordinates, `scale` of them along each side
+#[derive(Clone, Debug)]
+pub struct CheckerTexture {
+    scale: f64,
+    even: Color,
+    odd: Color,
+}
+
+impl CheckerTexture {
+    pub const fn new(scale: f64, even: Color, odd: Color) -> Self {
+        Self { scale, even, odd }
+    }
+}
+
+impl TextureLookup for CheckerTexture {
+    #[allow(clippy::cast_possible_truncation)]
+    fn value(&self, [u, v]: [f64; 2], _p: &Point3) -> Color {
+        let square = (self.scale * u).floor() as i64 + (self.scale * v).floor() as i64;
+        if square % 2 == 0 { self.even } else { self.odd }
+    }
+}
+
+// An image wrapped over the surface coordinates, with v pointing up the image and bilinear
+// filtering between pixels. Pixel values are linear, as read from HDR files.
+#[derive(Clone, Debug)]
+pub struct ImageTexture {
+    image: Arc<HdrImage>,
+}
+
+impl ImageTexture {
+    pub fn new(image: HdrImage) -> Self {
+        Self {
+            image: Arc::new(image),
+        }
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self::new(HdrImage::load(path)?))
+    }
+}
+
+impl TextureLookup for ImageTexture {
+    #[allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_possible_wrap,
+        clippy::cast_precision_loss,
+        clippy::cast_sign_loss
+    )]
+    fn value(&self, [u, v]: [f64; 2], _p: &Point3) -> Color {
+        let (width, height) = (self.image.width(), self.image.height());
+        let x = u.rem_euclid(1.0).mul_add(width as f64, -0.5);
+        let y = (1.0 - v.rem_euclid(1.0)).mul_add(height as f64, -0.5);
+        let (x0, y0) = (x.floor(), y.floor());
+        let (tx, ty) = (x - x0, y - y0);
+
+        // Neighbouring pixels wrap around the edges, so tiled textures have no seams
+        let pixel = |dx: i64, dy: i64| {
+            let px = (x0 as i64 + dx).rem_euclid(width as i64) as usize;
+            let py = (y0 as i64 + dy).rem_euclid(height as i64) as usize;
+            self.image.pixel(px, py)
+        };
+
+        (1.0 - ty) * ((1.0 - tx) * pixel(0, 0) + tx * pixel(1, 0))
+            + ty * ((1.0 - tx) * pixel(0, 1) + tx * pixel(1, 1))
+    }
+}
+
+// Marbled Perlin turbulence in world space, blending from `low` to `high`. `scale` sets the
+// frequency of the bands.
+#[derive(Clone, Debug)]
+pub struct NoiseTexture {
+    noise: Arc<Perlin>,
+    scale: f64,
+    low: Color,
+    high: Color,
+}
+
+impl NoiseTexture {
+    pub fn new(scale: f64, low: Color, high: Color) -> Self {
+        Self {
+            noise: Arc::new(Perlin::new(0)),
+            scale,
+            low,
+            high,
+        }
+    }
+}
+
+impl TextureLookup for NoiseTexture {
+    fn value(&self, _uv: [f64; 2], p: &Point3) -> Color {
+        let q = self.scale * *p;
+        let t = 0.5 * (1.0 + 10.0f64.mul_add(self.noise.turbulence(&q, 7), q.y()).sin());
+        (1.0 - t) * self.low + t * self.high
+    }
+}
+
+#[derive(Clone, Debug)]
+#[enum_dispatch]
+pub enum Texture {
+    SolidColor(SolidColor),
+    Checker(CheckerTexture),
+    Image(ImageTexture),
+    Noise(NoiseTexture),
+}
+
+impl Default for Texture {
+    fn default() -> Self {
+        Self::SolidColor(SolidColor::default())
+    }
+}
+
+impl From<Color> for Texture {
+    fn from(color: Color) -> Self {
+        Self::SolidColor(SolidColor::new(color))
+    }
+}
+
+impl From<f64> for Texture {
+    fn from(value: f64) -> Self {
+        Self::from(Color::new(value, value, value))
+    }
+}
+
+// Surface detail that tilts the shading normal without changing the geometry. A tangent-space
+// normal map stores normals with red along the tangent, green along the bitangent and blue along
+// the normal, each mapped from [-1, 1] to [0, 1]. A bump map is a height field, scaled by `scale`
+// into a displacement in scene units along the normal.
+#[derive(Clone, Debug)]
+pub enum NormalMap {
+    TangentSpace(Arc<Texture>),
+    Bump { height: Arc<Texture>, scale: f64 },
+}
+
+impl NormalMap {
+    // Step in surface coordinates for the bump map's finite differences
+    const BUMP_DELTA: f64 = 1e-3;
+
+    pub fn tangent_space(normals: impl Into<Texture>) -> Self {
+        Self::TangentSpace(Arc::new(normals.into()))
+    }
+
+    pub fn bump(height: impl Into<Texture>, scale: f64) -> Self {
+        Self::Bump {
+            height: Arc::new(height.into()),
+            scale,
+        }
+    }
+
+    // The perturbed normal on the outward side of the surface
+    pub fn shading_normal(&self, rec: &HitRecord) -> Vec3 {
+        let normal = if rec.front_face() {
+            *rec.normal()
+        } else {
+            -rec.normal()
+        };
+        let (uv, p) = (rec.uv(), rec.p());
+
+        match self {
+            Self::TangentSpace(normals) => {
+                let color = normals.value(uv, p);
+                let local = Vec3::new(
+                    2.0f64.mul_add(color.r(), -1.0),
+                    2.0f64.mul_add(color.g(), -1.0),
+                    2.0f64.mul_add(color.b(), -1.0),
+                );
+                if local.near_zero() {
+                    normal
+                } else {
+                    rec.tangent_frame(&normal)
+                        .local_to_world(&local)
+                        .unit_vector()
+                }
+            }
+            Self::Bump { height, scale } => {
+                // Displacing the surface along its normal tilts its tangents, and their cross
+                // product is the new normal (Blinn 1978)
+                let delta = Self::BUMP_DELTA;
+                let displacement = height.scalar(uv, p);
+                let slope = |shifted_uv, shifted_p: Point3| {
+                    (height.scalar(shifted_uv, &shifted_p) - displacement) * scale / delta
+                };
+
+                let tangent = rec.tangent()
+                    + slope([uv[0] + delta, uv[1]], p + delta * rec.tangent()) * normal;
+                let bitangent = rec.bitangent()
+                    + slope([uv[0], uv[1] + delta], p + delta * rec.bitangent()) * normal;
+                let perturbed = tangent.cross(&bitangent);
+                if perturbed.near_zero() {
+                    normal
+                } else if perturbed.dot(&normal) < 0.0 {
+                    -perturbed.unit_vector()
+                } else {
+                    perturbed.unit_vector()
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        hittable::{Quad, RayIntersection, Sphere, Triangle},
+        interval,
+        material::Material,
+        ray::Ray,
+    };
+
+    // Hits on both sides of curved and flat surfaces, with the outward normal at each
+    fn hits() -> Vec<(HitRecord, Vec3)> {
+        let material = Arc::new(Material::default());
+        let sphere = Sphere::new(&Point3::new(0.0, 0.0, -3.0), 1.0, &material);
+        let quad = Quad::new(
+            &Point3::new(-1.0, -1.0, -2.0),
+            &Vec3::new(2.0, 0.0, 1.0),
+            &Vec3::new(0.0, 2.0, 0.5),
+            &material,
+        );
+        let triangle = Triangle::new(
+            &Point3::new(-1.0, -1.0, -2.0),
+            &Point3::new(2.0, -1.0, -2.5),
+            &Point3::new(0.0, 2.0, -1.5),
+            &material,
+        )
+        .with_uvs([[0.3, 0.1], [0.9, 0.4], [0.2, 0.8]]);
+
+        let objects: [&dyn RayIntersection; 3] = [&sphere, &quad, &triangle];
+        let mut hits = Vec::new();
+        for object in objects {
+            for (origin, target) in [
+                (Point3::new(0.2, 0.3, 2.0), Point3::new(0.1, 0.1, -2.5)),
+                (Point3::new(0.1, 0.1, -8.0), Point3::new(0.2, 0.2, -2.0)),
+                (Point3::new(0.2, 0.1, -3.0), Point3::new(0.5, 0.5, -2.0)),
+            ] {
+                let r = Ray::new(&origin, &(target - origin));
+                let mut rec = HitRecord::default();
+                if object.hit(&r, interval::ERROR_CORRECTED_NON_NEGATIVE, &mut rec) {
+                    let outward = if rec.front_face() {
+                        *rec.normal()
+                    } else {
+                        -rec.normal()
+                    };
+                    hits.push((rec, outward));
+                }
+            }
+        }
+        assert!(hits.iter().any(|(rec, _)| !rec.front_face()));
+        hits
+    }
+
+    fn assert_unchanged(map: &NormalMap) {
+        for (rec, outward) in hits() {
+            let normal = map.shading_normal(&rec);
+            assert!((normal - outward).length() < 1e-9, "{normal:?} {outward:?}");
+        }
+    }
+
+    // The color of a normal pointing straight out of the surface
+    #[test]
+    fn flat_normal_maps_keep_the_normal() {
+        assert_unchanged(&NormalMap::tangent_space(Color::new(0.5, 0.5, 1.0)));
+    }
+
+    #[test]
+    fn constant_bump_maps_keep_the_normal() {
+        assert_unchanged(&NormalMap::bump(0.7, 2.0));
+        assert_unchanged(&NormalMap::bump(0.0, 0.5));
+    }
+}
@@ -0,0 +1,207 @@
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TileOrder {
+    #[default]
+    Scanline,
+    Spiral,
+    Hilbert,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Tile {
+    x0: usize,
+    y0: usize,
+    x1: usize,
+    y1: usize,
+}
+
+impl Tile {
+    pub const fn new(x0: usize, y0: usize, x1: usize, y1: usize) -> Self {
+        Self { x0, y0, x1, y1 }
+    }
+
+    pub const fn x0(&self) -> usize {
+        self.x0
+    }
+
+    pub const fn y0(&self) -> usize {
+        self.y0
+    }
+
+    pub const fn x1(&self) -> usize {
+        self.x1
+    }
+
+    pub const fn y1(&self) -> usize {
+        self.y1
+    }
+
+    pub const fn width(&self) -> usize {
+        self.x1 - self.x0
+    }
+
+    pub const fn height(&self) -> usize {
+        self.y1 - self.y0
+    }
+
+    pub const fn pixel_count(&self) -> usize {
+        self.width() * self.height()
+    }
+
+    pub fn pixels(&self) -> impl Iterator<Item = (usize, usize)> + use<> {
+        let (x0, x1) = (self.x0, self.x1);
+        (self.y0..self.y1).flat_map(move |y| (x0..x1).map(move |x| (x, y)))
+    }
+}
+
+pub fn generate_tiles(
+    image_width: usize,
+    image_height: usize,
+    tile_size: usize,
+    order: TileOrder,
+) -> Vec<Tile> {
+    let tile_size = tile_size.max(1);
+    let columns = image_width.div_ceil(tile_size);
+    let rows = image_height.div_ceil(tile_size);
+
+    let grid_positions = match order {
+        TileOrder::Scanline => scanline_order(columns, rows),
+        TileOrder::Spiral => spiral_order(columns, rows),
+        TileOrder::Hilbert => hilbert_order(columns, rows),
+    };
+
+    grid_positions
+        .into_iter()
+        .map(|(column, row)| {
+            let x0 = column * tile_size;
+            let y0 = row * tile_size;
+            Tile::new(
+                x0,
+                y0,
+                (x0 + tile_size).min(image_width),
+                (y0 + tile_size).min(image_height),
+            )
+        })
+        .collect()
+}
+
+fn scanline_order(columns: usize, rows: usize) -> Vec<(usize, usize)> {
+    (0..rows)
+        .flat_map(|row| (0..columns).map(move |column| (column, row)))
+        .collect()
+}
+
+// Tiles are visited ring by ring outwards from the center of the image, walking each ring by angle
+fn spiral_order(columns: usize, rows: usize) -> Vec<(usize, usize)> {
+    #![allow(clippy::cast_precision_loss)]
+    let center_x = (columns as f64 - 1.0) * 0.5;
+    let center_y = (rows as f64 - 1.0) * 0.5;
+
+    let mut positions = scanline_order(columns, rows);
+    positions.sort_by(|&(ax, ay), &(bx, by)| {
+        let ring =
+            |x: usize, y: usize| (x as f64 - center_x).abs().max((y as f64 - center_y).abs());
+        let angle = |x: usize, y: usize| (y as f64 - center_y).atan2(x as f64 - center_x);
+        ring(ax, ay)
+            .total_cmp(&ring(bx, by))
+            .then_with(|| angle(ax, ay).total_cmp(&angle(bx, by)))
+    });
+
+    positions
+}
+
+// The curve is generated over the smallest enclosing power of two grid and clipped to the tile grid
+fn hilbert_order(columns: usize, rows: usize) -> Vec<(usize, usize)> {
+    let side = columns.max(rows).next_power_of_two();
+
+    (0..side * side)
+        .map(|d| hilbert_index_to_position(side, d))
+        .filter(|&(x, y)| x < columns && y < rows)
+        .collect()
+}
+
+const fn hilbert_index_to_position(side: usize, index: usize) -> (usize, usize) {
+    let (mut x, mut y) = (0, 0);
+    let mut t = index;
+    let mut s = 1;
+
+    while s < side {
+        let rx = 1 & (t / 2);
+        let ry = 1 & (t ^ rx);
+
+        if ry == 0 {
+            if rx == 1 {
+                x = s - 1 - x;
+                y = s - 1 - y;
+            }
+            std::mem::swap(&mut x, &mut y);
+        }
+
+        x += s * rx;
+        y += s * ry;
+        t /= 4;
+        s *= 2;
+    }
+
+    (x, y)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ORDERS: [TileOrder; 3] = [TileOrder::Scanline, TileOrder::Spiral, TileOrder::Hilbert];
+
+    // Square and non-square sizes, sizes that aren't powers of two and images smaller than a tile
+    const IMAGE_SIZES: [(usize, usize); 8] = [
+        (1, 1),
+        (7, 5),
+        (32, 32),
+        (33, 17),
+        (100, 3),
+        (3, 100),
+        (64, 40),
+        (129, 65),
+    ];
+    const TILE_SIZES: [usize; 5] = [1, 4, 16, 32, 200];
+
+    #[test]
+    fn tiles_cover_every_pixel_once() {
+        for order in ORDERS {
+            for (width, height) in IMAGE_SIZES {
+                for tile_size in TILE_SIZES {
+                    let mut coverage = vec![0u32; width * height];
+                    for tile in generate_tiles(width, height, tile_size, order) {
+                        assert!(tile.pixel_count() > 0, "{tile:?}");
+                        assert!(tile.x1() <= width && tile.y1() <= height, "{tile:?}");
+                        for (x, y) in tile.pixels() {
+                            coverage[y * width + x] += 1;
+                        }
+                    }
+                    assert!(
+                        coverage.iter().all(|&count| count == 1),
+                        "{order:?} order, {width}x{height} image, {tile_size} pixel tiles"
+                    );
+                }
+            }
+        }
+    }
+
+    // On a power of two grid the curve never jumps, each tile sharing an edge with the one before
+    #[test]
+    fn hilbert_tiles_are_adjacent() {
+        for side in [1, 2, 4, 8, 16] {
+            let positions = hilbert_order(side, side);
+            assert_eq!(positions.len(), side * side);
+            for pair in positions.windows(2) {
+                let ((ax, ay), (bx, by)) = (pair[0], pair[1]);
+                assert_eq!(ax.abs_diff(bx) + ay.abs_diff(by), 1, "{pair:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn spiral_starts_in_the_middle() {
+        let tiles = generate_tiles(90, 50, 10, TileOrder::Spiral);
+        assert_eq!((tiles[0].x0(), tiles[0].y0()), (40, 20));
+    }
+}
@@ -6,8 +6,8 @@ use rand::{
             Error as DistributionError, SampleBorrow, SampleUniform, UniformFloat, UniformSampler,
         },
     },
-    rng,
 };
+use std::f64::consts::{FRAC_PI_2, FRAC_PI_4, PI};
 
 #[derive(Clone, Copy, Debug, Default, PartialOrd, PartialEq)]
 pub struct Vec3 {
@@ -433,27 +433,314 @@ impl SampleUniform for Vec3 {
     type Sampler = UniformVec3Sampler;
 }
 
-pub fn random_unit_vector() -> Vec3 {
-    let mut rng = rng();
-    loop {
-        let p = rng.random_range(Vec3::new(-1.0, -1.0, -1.0)..Vec3::new(1.0, 1.0, 1.0));
-        let length_sq = p.length_squared();
-        if 1.0e-160 < length_sq && length_sq <= 1.0 {
-            return p / length_sq.sqrt();
+// An orthonormal basis. Directions sampled in a local frame around the z axis are turned into
+// world directions around `w`.
+#[derive(Clone, Copy, Debug)]
+pub struct Onb {
+    u: Vec3,
+    v: Vec3,
+    w: Vec3,
+}
+
+impl Onb {
+    // Completes a basis around a unit vector without branching on its direction (Duff et al.)
+    pub fn new(w: &Vec3) -> Self {
+        let (x, y, z) = (w.x(), w.y(), w.z());
+        let sign = 1.0f64.copysign(z);
+        let scale = -(sign + z).recip();
+        let xy = x * y * scale;
+
+        Self {
+            u: Vec3::new((sign * x * x).mul_add(scale, 1.0), sign * xy, -sign * x),
+            v: Vec3::new(xy, (y * y).mul_add(scale, sign), -y),
+            w: *w,
         }
     }
+
+    // A basis with `w` along `forward` and `v` as close to `up` as possible
+    pub fn with_up(forward: &Vec3, up: &Vec3) -> Self {
+        let w = forward.unit_vector();
+        let u = up.cross(&w).unit_vector();
+        let v = w.cross(&u);
+
+        Self { u, v, w }
+    }
+
+    // A basis from three orthonormal axes
+    pub const fn from_axes(u: Vec3, v: Vec3, w: Vec3) -> Self {
+        Self { u, v, w }
+    }
+
+    pub const fn u(&self) -> &Vec3 {
+        &self.u
+    }
+
+    pub const fn v(&self) -> &Vec3 {
+        &self.v
+    }
+
+    pub const fn w(&self) -> &Vec3 {
+        &self.w
+    }
+
+    pub fn local_to_world(&self, local: &Vec3) -> Vec3 {
+        local.x() * self.u + local.y() * self.v + local.z() * self.w
+    }
+
+    pub fn world_to_local(&self, world: &Vec3) -> Vec3 {
+        Vec3::new(world.dot(&self.u), world.dot(&self.v), world.dot(&self.w))
+    }
+}
+
+// The sampling functions below map uniform 2D samples to directions or points. Directions are in a
+// local frame around the z axis, and each comes with its solid angle (or area) density.
+pub fn sample_uniform_sphere(u: [f64; 2]) -> Vec3 {
+    let z = 2.0f64.mul_add(-u[0], 1.0);
+    let r = z.mul_add(-z, 1.0).max(0.0).sqrt();
+    let phi = 2.0 * PI * u[1];
+    Vec3::new(r * phi.cos(), r * phi.sin(), z)
+}
+
+pub const fn uniform_sphere_pdf() -> f64 {
+    1.0 / (4.0 * PI)
+}
+
+#[cfg_attr(
+    not(test),
+    expect(
+        dead_code,
+        reason = "Part of the sampling toolkit, not used by any material yet"
+    )
+)]
+pub fn sample_uniform_hemisphere(u: [f64; 2]) -> Vec3 {
+    let z = u[0];
+    let r = z.mul_add(-z, 1.0).max(0.0).sqrt();
+    let phi = 2.0 * PI * u[1];
+    Vec3::new(r * phi.cos(), r * phi.sin(), z)
+}
+
+#[cfg_attr(
+    not(test),
+    expect(
+        dead_code,
+        reason = "Part of the sampling toolkit, not used by any material yet"
+    )
+)]
+pub const fn uniform_hemisphere_pdf() -> f64 {
+    1.0 / (2.0 * PI)
+}
+
+// Malley's method: points uniform on the disk projected up onto the hemisphere
+pub fn sample_cosine_hemisphere(u: [f64; 2]) -> Vec3 {
+    let d = sample_uniform_disk(u);
+    let z = d
+        .x()
+        .mul_add(-d.x(), d.y().mul_add(-d.y(), 1.0))
+        .max(0.0)
+        .sqrt();
+    Vec3::new(d.x(), d.y(), z)
+}
+
+pub fn cosine_hemisphere_pdf(cos_theta: f64) -> f64 {
+    cos_theta.max(0.0) / PI
+}
+
+// Directions within `cos_theta_max` of the z axis
+pub fn sample_uniform_cone(u: [f64; 2], cos_theta_max: f64) -> Vec3 {
+    let cos_theta = u[0].mul_add(cos_theta_max - 1.0, 1.0);
+    let sin_theta = cos_theta.mul_add(-cos_theta, 1.0).max(0.0).sqrt();
+    let phi = 2.0 * PI * u[1];
+    Vec3::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta)
+}
+
+pub fn uniform_cone_pdf(cos_theta_max: f64) -> f64 {
+    (2.0 * PI * (1.0 - cos_theta_max)).recip()
+}
+
+// Shirley and Chiu's concentric mapping to the unit disk, which keeps stratified and
+// low-discrepancy samples well distributed
+pub fn sample_uniform_disk(u: [f64; 2]) -> Vec3 {
+    let offset_x = 2.0f64.mul_add(u[0], -1.0);
+    let offset_y = 2.0f64.mul_add(u[1], -1.0);
+    if offset_x == 0.0 && offset_y == 0.0 {
+        return Vec3::default();
+    }
+
+    let (r, theta) = if offset_x.abs() > offset_y.abs() {
+        (offset_x, FRAC_PI_4 * (offset_y / offset_x))
+    } else {
+        (offset_y, FRAC_PI_4.mul_add(-offset_x / offset_y, FRAC_PI_2))
+    };
+
+    Vec3::new(r * theta.cos(), r * theta.sin(), 0.0)
+}
+
+// Area density over the unit disk
+#[cfg_attr(
+    not(test),
+    expect(
+        dead_code,
+        reason = "Part of the sampling toolkit, not used by any material yet"
+    )
+)]
+pub const fn uniform_disk_pdf() -> f64 {
+    1.0 / PI
 }
 
-pub fn random_in_unit_disk() -> Vec3 {
-    let mut rng = rng();
-    loop {
-        let p = Vec3::new(
-            rng.random_range(-1.0..1.0),
-            rng.random_range(-1.0..1.0),
-            0.0,
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GRID: u32 = 400;
+    const BANDS: u32 = 20;
+    const SECTORS: u32 = 16;
+
+    // Every point of a regular grid over the unit square
+    fn grid() -> impl Iterator<Item = [f64; 2]> {
+        (0..GRID * GRID).map(|i| {
+            [
+                (f64::from(i % GRID) + 0.5) / f64::from(GRID),
+                (f64::from(i / GRID) + 0.5) / f64::from(GRID),
+            ]
+        })
+    }
+
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    fn bin(value: f64, count: u32) -> usize {
+        ((value * f64::from(count)) as usize).min(count as usize - 1)
+    }
+
+    fn sector(x: f64, y: f64) -> f64 {
+        y.atan2(x).rem_euclid(2.0 * PI) / (2.0 * PI)
+    }
+
+    // Sorts sampled directions into bins of equal solid angle, bands of cos θ by sectors of φ, and
+    // compares their counts to the density integrated over each bin with the midpoint rule. Band
+    // edges fall at multiples of 0.1, so densities that are discontinuous in cos θ, such as the
+    // cone's, are smooth within every bin.
+    fn assert_matches_direction_pdf(sample: impl Fn([f64; 2]) -> Vec3, pdf: impl Fn(&Vec3) -> f64) {
+        let mut counts = vec![0u32; (BANDS * SECTORS) as usize];
+        for u in grid() {
+            let w = sample(u);
+            assert!((w.length() - 1.0).abs() < 1e-9, "{w:?}");
+            let band = bin(0.5 * (w.z() + 1.0), BANDS);
+            counts[band * SECTORS as usize + bin(sector(w.x(), w.y()), SECTORS)] += 1;
+        }
+
+        let bin_solid_angle = 4.0 * PI / f64::from(BANDS * SECTORS);
+        let mut total_probability = 0.0;
+        for (index, count) in (0..BANDS * SECTORS).zip(counts) {
+            let cos_theta =
+                2.0f64.mul_add((f64::from(index / SECTORS) + 0.5) / f64::from(BANDS), -1.0);
+            let phi = 2.0 * PI * (f64::from(index % SECTORS) + 0.5) / f64::from(SECTORS);
+            let sin_theta = cos_theta.mul_add(-cos_theta, 1.0).sqrt();
+            let center = Vec3::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta);
+
+            let probability = pdf(&center) * bin_solid_angle;
+            total_probability += probability;
+            let fraction = f64::from(count) / f64::from(GRID * GRID);
+            assert!(
+                (fraction - probability).abs() < 2e-3,
+                "bin {index}: sampled {fraction}, expected {probability}"
+            );
+        }
+        assert!(
+            (total_probability - 1.0).abs() < 1e-9,
+            "{total_probability}"
         );
-        if p.length_squared() < 1.0 {
-            return p;
+    }
+
+    #[test]
+    fn onb_round_trips() {
+        let directions = [
+            Vec3::new(0.0, 0.0, 1.0),
+            Vec3::new(0.0, 0.0, -1.0),
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(0.3, -0.8, 0.2),
+            Vec3::new(-0.5, 0.1, -0.9),
+        ];
+        for w in directions.map(|w| w.unit_vector()) {
+            for onb in [Onb::new(&w), Onb::with_up(&w, &Vec3::new(0.3, 1.0, 0.1))] {
+                let axes = [*onb.u(), *onb.v(), *onb.w()];
+                for (i, a) in axes.iter().enumerate() {
+                    for (j, b) in axes.iter().enumerate() {
+                        let expected = if i == j { 1.0 } else { 0.0 };
+                        assert!((a.dot(b) - expected).abs() < 1e-12, "{onb:?}");
+                    }
+                }
+                assert!(
+                    (onb.u().cross(onb.v()) - *onb.w()).length() < 1e-12,
+                    "{onb:?}"
+                );
+                assert!((onb.local_to_world(&Vec3::new(0.0, 0.0, 1.0)) - w).length() < 1e-12);
+
+                for v in directions {
+                    let round_trip = onb.local_to_world(&onb.world_to_local(&v));
+                    assert!(
+                        (round_trip - v).length() < 1e-12,
+                        "{v:?} became {round_trip:?}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn uniform_sphere_matches_pdf() {
+        assert_matches_direction_pdf(sample_uniform_sphere, |_| uniform_sphere_pdf());
+    }
+
+    #[test]
+    fn uniform_hemisphere_matches_pdf() {
+        assert_matches_direction_pdf(sample_uniform_hemisphere, |w| {
+            if w.z() > 0.0 {
+                uniform_hemisphere_pdf()
+            } else {
+                0.0
+            }
+        });
+    }
+
+    #[test]
+    fn cosine_hemisphere_matches_pdf() {
+        assert_matches_direction_pdf(sample_cosine_hemisphere, |w| cosine_hemisphere_pdf(w.z()));
+    }
+
+    #[test]
+    fn uniform_cone_matches_pdf() {
+        let cos_theta_max = 0.5;
+        assert_matches_direction_pdf(
+            |u| sample_uniform_cone(u, cos_theta_max),
+            |w| {
+                if w.z() >= cos_theta_max {
+                    uniform_cone_pdf(cos_theta_max)
+                } else {
+                    0.0
+                }
+            },
+        );
+    }
+
+    // Rings of equal area, r² in equal steps, by sectors of the angle
+    #[test]
+    fn uniform_disk_matches_pdf() {
+        let mut counts = vec![0u32; (BANDS * SECTORS) as usize];
+        for u in grid() {
+            let p = sample_uniform_disk(u);
+            let r_squared = p.x().mul_add(p.x(), p.y() * p.y());
+            assert!(r_squared <= 1.0 + 1e-12 && p.z() == 0.0, "{p:?}");
+            counts
+                [bin(r_squared, BANDS) * SECTORS as usize + bin(sector(p.x(), p.y()), SECTORS)] +=
+                1;
+        }
+
+        let probability = uniform_disk_pdf() * PI / f64::from(BANDS * SECTORS);
+        for count in counts {
+            let fraction = f64::from(count) / f64::from(GRID * GRID);
+            assert!(
+                (fraction - probability).abs() < 2e-3,
+                "{fraction} {probability}"
+            );
         }
     }
 }